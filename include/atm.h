@@ -0,0 +1,170 @@
+/* Generated from src/ffi.rs by cbindgen, run `UPDATE_HEADER=1 cargo test --test ffi` to update. */
+
+#ifndef ATM_H
+#define ATM_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Size of the amount strings of `AtmAccount`, including the terminating nul
+ */
+#define ATM_AMOUNT_LEN 48
+
+/**
+ * Size of the currency code of `AtmAccount`, including the terminating nul
+ */
+#define ATM_CURRENCY_LEN 4
+
+/**
+ * Outcome of a call, the rejections mirror `TxError` and `ConversionError`
+ */
+typedef enum AtmStatus {
+  ATM_STATUS_OK = 0,
+  ATM_STATUS_NULL_POINTER = 1,
+  ATM_STATUS_INVALID_UTF8 = 2,
+  /**
+   * unknown transaction type or invalid amount
+   */
+  ATM_STATUS_MALFORMED = 3,
+  ATM_STATUS_DEPOSIT_WITHOUT_AMOUNT = 10,
+  ATM_STATUS_WITHDRAWAL_WITHOUT_AMOUNT = 11,
+  ATM_STATUS_DISPUTE_WITH_AMOUNT = 12,
+  ATM_STATUS_TOO_MANY_DECIMALS = 13,
+  ATM_STATUS_INVALID_CURRENCY = 14,
+  ATM_STATUS_NO_RATE = 15,
+  ATM_STATUS_INVALID_TIMESTAMP = 16,
+  ATM_STATUS_ACCOUNT_LOCKED = 20,
+  ATM_STATUS_INSUFFICIENT_FUNDS = 21,
+  ATM_STATUS_TRANSACTION_NOT_FOUND = 22,
+  ATM_STATUS_INVALID_STATE = 23,
+  ATM_STATUS_DISPUTE_NOT_ALLOWED = 24,
+  ATM_STATUS_ACCOUNT_LIMIT = 25,
+  ATM_STATUS_ACCOUNT_EXISTS = 26,
+  ATM_STATUS_CLIENT_MISMATCH = 27,
+  ATM_STATUS_TRANSACTION_EXISTS = 28,
+  ATM_STATUS_OUT_OF_ORDER = 29,
+  ATM_STATUS_DISPUTE_WINDOW_CLOSED = 30,
+  ATM_STATUS_LIMIT_EXCEEDED = 31,
+  /**
+   * an account invariant was violated, the database must not be used anymore
+   */
+  ATM_STATUS_INVARIANT = 99,
+} AtmStatus;
+
+/**
+ * A snapshot of the accounts taken by `atm_accounts_new`
+ */
+typedef struct AtmAccounts AtmAccounts;
+
+/**
+ * A database kept in memory
+ */
+typedef struct AtmDb AtmDb;
+
+/**
+ * The state of an account in a currency, the strings are nul terminated
+ */
+typedef struct AtmAccount {
+  /**
+   * empty for the accounts without a tenant, valid until the next call to
+   * `atm_accounts_next` or `atm_accounts_free`
+   */
+  const char *tenant;
+  uint32_t client;
+  /**
+   * empty for the balance without a currency
+   */
+  char currency[ATM_CURRENCY_LEN];
+  char available[ATM_AMOUNT_LEN];
+  char held[ATM_AMOUNT_LEN];
+  char total[ATM_AMOUNT_LEN];
+  bool locked;
+} AtmAccount;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an empty database, free it with `atm_db_free`
+ */
+struct AtmDb *atm_db_new(void);
+
+/**
+ * Frees a database created by `atm_db_new`
+ *
+ * # Safety
+ * `db` must be null or a pointer returned by `atm_db_new` that was not freed yet
+ */
+void atm_db_free(struct AtmDb *db);
+
+/**
+ * Applies a transaction, `kind` is one of the input types (`deposit`,
+ * `withdrawal`, `dispute`, `resolve`, `chargeback`), `amount` is a decimal
+ * string or null for the dispute types
+ *
+ * # Safety
+ * `db` must come from `atm_db_new`, `kind` and `amount` must be null or nul
+ * terminated strings
+ */
+enum AtmStatus atm_db_submit(struct AtmDb *db,
+                             uint32_t client,
+                             uint64_t tx,
+                             const char *kind,
+                             const char *amount);
+
+/**
+ * Applies a transaction of a tenant in a currency like `atm_db_submit`,
+ * `tenant` and `currency` may be null for none
+ *
+ * # Safety
+ * `db` must come from `atm_db_new`, `tenant`, `currency`, `kind` and `amount`
+ * must be null or nul terminated strings
+ */
+enum AtmStatus atm_db_submit_in(struct AtmDb *db,
+                                const char *tenant,
+                                const char *currency,
+                                uint32_t client,
+                                uint64_t tx,
+                                const char *kind,
+                                const char *amount);
+
+/**
+ * Takes a snapshot of the accounts, one per tenant, client and currency in
+ * that order, free it with `atm_accounts_free`
+ *
+ * # Safety
+ * `db` must be null or come from `atm_db_new`
+ */
+struct AtmAccounts *atm_accounts_new(const struct AtmDb *db);
+
+/**
+ * Fills `account` with the next account, returns false at the end
+ *
+ * # Safety
+ * `accounts` must come from `atm_accounts_new`, `account` must point to a
+ * writable `AtmAccount`
+ */
+bool atm_accounts_next(struct AtmAccounts *accounts, struct AtmAccount *account);
+
+/**
+ * Frees a snapshot created by `atm_accounts_new`
+ *
+ * # Safety
+ * `accounts` must be null or come from `atm_accounts_new` and not be freed yet
+ */
+void atm_accounts_free(struct AtmAccounts *accounts);
+
+/**
+ * A static description of the status
+ */
+const char *atm_status_message(enum AtmStatus status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ATM_H */
@@ -0,0 +1,236 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use structopt::StructOpt;
+
+use super::{load_inputs, new_db, ExitCode, Failure, FormatOpt, InputOpt};
+use atm::{
+    config::Config,
+    io::{compress::decompress, read_results},
+    model::output::Record,
+    report::compare::{compare, duplicates, Difference},
+};
+
+// parsed once, the size doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(StructOpt)]
+pub enum DiffOpt {
+    /// compare two results files written by `process`
+    Files(FilesOpt),
+    /// process the inputs with two configs and compare the results
+    Configs(ConfigsOpt),
+}
+
+#[derive(StructOpt)]
+pub struct FilesOpt {
+    #[structopt(parse(from_os_str))]
+    left: PathBuf,
+    #[structopt(parse(from_os_str))]
+    right: PathBuf,
+}
+
+#[derive(StructOpt)]
+pub struct ConfigsOpt {
+    /// the config of the left side
+    #[structopt(long, parse(from_os_str))]
+    left_config: PathBuf,
+    /// the config of the right side
+    #[structopt(long, parse(from_os_str))]
+    right_config: PathBuf,
+    #[structopt(flatten)]
+    format: FormatOpt,
+    #[structopt(flatten)]
+    input: InputOpt,
+}
+
+/// Compare two results files, or the results of two configs over the same
+/// inputs, the differences are printed as left -> right.
+/// Exits with `ExitCode::Mismatch` if the accounts differ
+pub fn run(opt: DiffOpt, _config: &Config) -> ExitCode {
+    let sides = match &opt {
+        DiffOpt::Files(opt) => read_file(&opt.left).and_then(|l| Ok((l, read_file(&opt.right)?))),
+        DiffOpt::Configs(opt) => {
+            process(opt, &opt.left_config).and_then(|l| Ok((l, process(opt, &opt.right_config)?)))
+        }
+    };
+    let (left, right) = match sides {
+        Ok(sides) => sides,
+        Err(e) => return e.report(),
+    };
+
+    let differences = compare(&left, &right);
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    println!("{}", totals(&differences));
+    exit_code(&differences)
+}
+
+/// `1 added, 0 removed, 2 changed`
+fn totals(differences: &[Difference]) -> String {
+    let count = |f: fn(&Difference) -> bool| differences.iter().filter(|d| f(d)).count();
+    format!(
+        "{} added, {} removed, {} changed",
+        count(|d| matches!(d, Difference::Added(_))),
+        count(|d| matches!(d, Difference::Removed(_))),
+        count(|d| matches!(d, Difference::Changed { .. })),
+    )
+}
+
+fn exit_code(differences: &[Difference]) -> ExitCode {
+    if differences.is_empty() {
+        ExitCode::Success
+    } else {
+        ExitCode::Mismatch
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<Record>, Failure> {
+    let file = File::open(path)
+        .and_then(decompress)
+        .map_err(|e| Failure::io(format!("can't read {}: {}", path.display(), e)))?;
+    read_records(&path.display().to_string(), file)
+}
+
+/// the records of a results file, an account can't have two of them
+fn read_records(source: &str, reader: impl Read) -> Result<Vec<Record>, Failure> {
+    let records =
+        read_results(reader).map_err(|e| Failure::io(format!("can't read {}: {}", source, e)))?;
+    let duplicates = duplicates(&records);
+    if !duplicates.is_empty() {
+        return Err(Failure::config(format!(
+            "{}: more than one row for {}",
+            source,
+            duplicates.join(", ")
+        )));
+    }
+    Ok(records)
+}
+
+/// process the inputs with the config at `path`, the command line flags still apply
+fn process(opt: &ConfigsOpt, path: &Path) -> Result<Vec<Record>, Failure> {
+    let config =
+        Config::load(path).map_err(|e| Failure::config(format!("{}: {}", path.display(), e)))?;
+    let mut db = new_db(&config);
+    let loaded = load_inputs(
+        &opt.input,
+        opt.format.rounding(&config),
+        &config,
+        &mut db,
+        &mut (),
+    )?;
+    if let Some(e) = loaded.error {
+        return Err(Failure {
+            code: ExitCode::Stopped,
+            message: format!("{}: {}", path.display(), e),
+        });
+    }
+    let precision = opt.format.precision(&config);
+    Ok(db
+        .accounts()
+        .flat_map(|account| Record::all(account, &precision))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use structopt::StructOpt;
+
+    use super::{exit_code, process, read_file, read_records, totals, DiffOpt};
+    use crate::cmd::ExitCode;
+    use atm::{
+        io::compress::{CompressedWriter, Compression},
+        report::compare::compare,
+    };
+
+    #[test]
+    fn test_diff() {
+        let left = "client,available,held,total,locked\n\
+                    1,1.5,0,1.5,false\n\
+                    2,2,0,2,false\n\
+                    3,3,0,3,false\n";
+        let right = "client,available,held,total,locked\n\
+                     1,1.5000,0,1.5000,false\n\
+                     2,1,1,2,false\n\
+                     4,4,0,4,false\n";
+        let left = read_records("left", left.as_bytes()).unwrap();
+        let right = read_records("right", right.as_bytes()).unwrap();
+
+        let differences = compare(&left, &right);
+        assert_eq!(totals(&differences), "1 added, 1 removed, 1 changed");
+        assert_eq!(exit_code(&differences), ExitCode::Mismatch);
+        let same = compare(&left, &left);
+        assert_eq!(totals(&same), "0 added, 0 removed, 0 changed");
+        assert_eq!(exit_code(&same), ExitCode::Success);
+
+        let twice = "client,available,held,total,locked\n\
+                     1,1,0,1,false\n\
+                     1,2,0,2,false\n";
+        let e = read_records("twice", twice.as_bytes()).unwrap_err();
+        assert_eq!(e.code, ExitCode::Config);
+        assert_eq!(e.message, "twice: more than one row for client 1");
+    }
+
+    #[test]
+    fn test_configs() {
+        let dir = std::env::temp_dir().join(format!("atm-diff-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.csv");
+        fs::write(
+            &input,
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,2,2,2.0\n\
+             dispute,1,1,\n\
+             chargeback,1,1,\n",
+        )
+        .unwrap();
+        let left = dir.join("left.toml");
+        fs::write(&left, "").unwrap();
+        let right = dir.join("right.toml");
+        fs::write(&right, "[dispute]\nlock_on_chargeback = false\n").unwrap();
+
+        let opt = match DiffOpt::from_iter(&[
+            "diff".as_ref(),
+            "configs".as_ref(),
+            "--left-config".as_ref(),
+            left.as_os_str(),
+            "--right-config".as_ref(),
+            right.as_os_str(),
+            input.as_os_str(),
+        ]) {
+            DiffOpt::Configs(opt) => opt,
+            DiffOpt::Files(_) => unreachable!(),
+        };
+        let differences = compare(
+            &process(&opt, &left).unwrap(),
+            &process(&opt, &right).unwrap(),
+        );
+        let lines: Vec<_> = differences.iter().map(|d| d.to_string()).collect();
+        assert_eq!(lines, vec!["client 1: locked true -> false"]);
+
+        let e = process(&opt, &dir.join("missing.toml")).unwrap_err();
+        assert_eq!(e.code, ExitCode::Config);
+
+        // a compressed results file reads like a plain one
+        let results = dir.join("results.csv.gz");
+        let mut writer =
+            CompressedWriter::new(fs::File::create(&results).unwrap(), Compression::Gzip).unwrap();
+        writer
+            .write_all(b"client,available,held,total,locked\n1,1,0,1,false\n")
+            .unwrap();
+        writer.finish().unwrap();
+        let records = read_file(&results).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].client_id, 1);
+        let e = read_file(&dir.join("missing.csv")).unwrap_err();
+        assert!(e.message.starts_with("can't read "));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
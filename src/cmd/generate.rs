@@ -0,0 +1,153 @@
+use std::{fs::File, io, path::PathBuf};
+
+use structopt::StructOpt;
+
+use super::{config_read_options, new_db, ExitCode, Failure, FormatOpt};
+use atm::{
+    config::Config,
+    generate::{generate, parse_rate, TypeMix, Workload},
+    io::{
+        compress::{decompress, CompressedWriter, Compression},
+        print_results, read_csv_data,
+    },
+    model::ClientId,
+};
+
+#[derive(StructOpt)]
+pub struct GenerateOpt {
+    /// the same seed and settings always produce the same file
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+    #[structopt(long, default_value = "100")]
+    clients: ClientId,
+    #[structopt(long, default_value = "1000")]
+    rows: u64,
+    /// weights of the deposits and withdrawals
+    #[structopt(long, default_value = "deposit=70,withdrawal=30")]
+    mix: TypeMix,
+    /// share of the rows that dispute an earlier deposit, as a fraction or a percentage
+    #[structopt(long, default_value = "0.02", parse(try_from_str = parse_rate))]
+    dispute_rate: f64,
+    /// share of the settled disputes that end with a chargeback
+    #[structopt(long, default_value = "0.2", parse(try_from_str = parse_rate))]
+    chargeback_rate: f64,
+    /// share of the deposits and withdrawals that reuse an earlier transaction id
+    #[structopt(long, default_value = "0", parse(try_from_str = parse_rate))]
+    duplicate_rate: f64,
+    /// share of the rows that can't be parsed or converted
+    #[structopt(long, default_value = "0", parse(try_from_str = parse_rate))]
+    malformed_rate: f64,
+    /// write the input to this file instead of stdout, compressed if it ends with .gz or .zst
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// also write the accounts produced by processing the input, for golden tests
+    #[structopt(long, parse(from_os_str), requires = "output")]
+    expected: Option<PathBuf>,
+    #[structopt(flatten)]
+    format: FormatOpt,
+}
+
+/// Write a reproducible input and optionally the accounts it produces
+pub fn run(opt: GenerateOpt, config: &Config) -> ExitCode {
+    let workload = Workload {
+        seed: opt.seed,
+        clients: opt.clients,
+        rows: opt.rows,
+        mix: opt.mix,
+        dispute_rate: opt.dispute_rate,
+        chargeback_rate: opt.chargeback_rate,
+        duplicate_rate: opt.duplicate_rate,
+        malformed_rate: opt.malformed_rate,
+    };
+    if let Err(e) = workload.validate() {
+        return Failure::config(e).report();
+    }
+    match write(&opt, &workload, config) {
+        Ok(()) => ExitCode::Success,
+        Err(e) => e.report(),
+    }
+}
+
+fn write(opt: &GenerateOpt, workload: &Workload, config: &Config) -> Result<(), Failure> {
+    let path = match &opt.output {
+        Some(path) => path,
+        None => {
+            let stdout = io::stdout();
+            return generate(workload, stdout.lock())
+                .map_err(|e| Failure::io(format!("can't write input: {}", e)));
+        }
+    };
+    File::create(path)
+        .and_then(|f| CompressedWriter::new(f, Compression::from_path(path)))
+        .and_then(|mut out| {
+            generate(workload, &mut out)?;
+            out.finish().map(|_| ())
+        })
+        .map_err(|e| Failure::io(format!("can't write {}: {}", path.display(), e)))?;
+
+    let expected = match &opt.expected {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let options = config_read_options(opt.format.rounding(config), config)?;
+    let input = File::open(path)
+        .and_then(decompress)
+        .map_err(|e| Failure::io(format!("can't open {}: {}", path.display(), e)))?;
+    let mut db = new_db(config);
+    let source = path.display().to_string();
+    read_csv_data(&source, input, &mut db, &options, &mut ())
+        .map_err(|e| Failure::config(format!("processing stopped: {}", e)))?;
+    File::create(expected)
+        .and_then(|f| CompressedWriter::new(f, Compression::from_path(expected)))
+        .and_then(|mut out| {
+            print_results(&mut out, db.accounts(), &opt.format.precision(config));
+            out.finish().map(|_| ())
+        })
+        .map_err(|e| Failure::io(format!("can't write {}: {}", expected.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use structopt::StructOpt;
+
+    use super::{run, GenerateOpt};
+    use crate::cmd::ExitCode;
+    use atm::config::Config;
+
+    #[test]
+    fn test_expected() {
+        let dir = std::env::temp_dir().join(format!("atm-generate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let expected = |name: &str| {
+            let input = dir.join(format!("{}.csv", name));
+            let expected = dir.join(format!("{}.expected.csv", name));
+            let opt = GenerateOpt::from_iter(&[
+                "generate".as_ref(),
+                "--seed".as_ref(),
+                "3".as_ref(),
+                "--clients".as_ref(),
+                "50".as_ref(),
+                "--output".as_ref(),
+                input.as_os_str(),
+                "--expected".as_ref(),
+                expected.as_os_str(),
+            ]);
+            assert_eq!(run(opt, &Config::default()), ExitCode::Success);
+            fs::read(expected).unwrap()
+        };
+        // the accounts are written in the same order every time
+        let first = expected("first");
+        assert_eq!(first, expected("second"));
+        let clients: Vec<u32> = String::from_utf8(first)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap().parse().unwrap())
+            .collect();
+        assert!(clients.windows(2).all(|w| w[0] < w[1]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
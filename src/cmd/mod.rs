@@ -0,0 +1,500 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{self, Display},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::Instant,
+};
+
+use structopt::StructOpt;
+
+use atm::{
+    config::{Config, StorageBackend},
+    db::{AccountStore, MemoryDB, TransactionDB, TransactionStore},
+    fx::{Converter, RateTable},
+    io::{
+        input::{resolve_inputs, InputOrder, InputSource},
+        mapping::{ColumnMapping, Mapping},
+        pending::PendingLimits,
+        read_csv_data, MergedReader, ReadError, ReadOptions, ReadReport, RowObserver, StrictChecks,
+    },
+    model::{
+        account::Account,
+        id::IdFormat,
+        input::parse_currency,
+        precision::{parse_scale, ExcessPrecision, Precision, Rounding},
+        Currency,
+    },
+    report::summary::Summary,
+};
+
+pub mod diff;
+pub mod generate;
+pub mod process;
+pub mod reconcile;
+pub mod repl;
+pub mod serve;
+pub mod statement;
+pub mod stats;
+pub mod validate;
+
+/// Exit codes of the commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// 0: every row was accepted
+    Success = 0,
+    /// 1: the run completed but some rows were rejected
+    Rejected = 1,
+    /// 2: an input or output file can't be opened, read or written
+    Io = 2,
+    /// 3: invalid command line, config file or column mapping
+    Config = 3,
+    /// 4: an internal invariant was violated, the results can't be trusted
+    Invariant = 4,
+    /// 5: processing stopped at a row that failed a strict check
+    Stopped = 5,
+    /// 6: the results differ from the expected ones
+    Mismatch = 6,
+}
+
+/// An error that ends a command early
+#[derive(Debug)]
+pub struct Failure {
+    pub code: ExitCode,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn io(message: String) -> Self {
+        Failure {
+            code: ExitCode::Io,
+            message,
+        }
+    }
+
+    pub fn config(message: String) -> Self {
+        Failure {
+            code: ExitCode::Config,
+            message,
+        }
+    }
+
+    /// log the error and return its exit code
+    pub fn report(self) -> ExitCode {
+        error!("{}", self.message);
+        self.code
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Options for reading the input files, the defaults come from the config
+#[derive(StructOpt)]
+pub struct InputOpt {
+    /// order of the inputs: args, name or mtime [default: args]
+    #[structopt(long)]
+    order: Option<InputOrder>,
+    /// toml file with aliases for the input columns and transaction types
+    #[structopt(long, parse(from_os_str))]
+    mapping: Option<PathBuf>,
+    /// maximum number of decimal places accepted in the input amounts
+    #[structopt(long, parse(try_from_str = parse_scale))]
+    input_precision: Option<u32>,
+    /// what to do with input amounts over --input-precision: reject or round [default: reject]
+    #[structopt(long)]
+    excess_precision: Option<ExcessPrecision>,
+    /// stop at the first row that fails one of the --strict-checks
+    #[structopt(long)]
+    strict: bool,
+    /// don't stop at failed rows, even if the config enables strict mode
+    #[structopt(long, conflicts_with = "strict")]
+    no_strict: bool,
+    /// comma separated list of unknown-column, column-count, malformed, conversion, rejected or all
+    /// [default: all]
+    #[structopt(long)]
+    strict_checks: Option<StrictChecks>,
+    /// accepted transaction ids: any, number (64-bit) or uuid [default: any]
+    #[structopt(long)]
+    transaction_ids: Option<IdFormat>,
+    /// tenant of the rows without a tenant column
+    #[structopt(long)]
+    tenant: Option<String>,
+    /// use the file name up to the first dot as the tenant of the rows without a tenant column
+    #[structopt(long, conflicts_with = "tenant")]
+    tenant_per_file: bool,
+    /// currency code of the rows without a currency column
+    #[structopt(long)]
+    currency: Option<String>,
+    /// csv table of exchange rates (from,to,rate,effective) used to convert the amounts into
+    /// --fx-currency
+    #[structopt(long, parse(from_os_str))]
+    fx_rates: Option<PathBuf>,
+    /// the currency of the accounts when the amounts are converted
+    #[structopt(long)]
+    fx_currency: Option<String>,
+    /// reorder the rows by their timestamp within a buffer of this many rows
+    #[structopt(long)]
+    reorder_window: Option<usize>,
+    /// share the --reorder-window buffer between the inputs, so rows are reordered across files
+    #[structopt(long)]
+    reorder_across_files: bool,
+    /// keep up to this many disputes of unknown transactions until the transaction comes
+    #[structopt(long)]
+    pending_references: Option<usize>,
+    /// reject a waiting dispute after this many more rows
+    #[structopt(long)]
+    pending_max_age: Option<u64>,
+    /// input files, directories or `-` for stdin, may be compressed with gzip or zstd
+    #[structopt(name = "FILE", parse(from_os_str), required = true, min_values = 1)]
+    inputs: Vec<PathBuf>,
+}
+
+impl InputOpt {
+    /// --strict or --no-strict, if either is given
+    fn strict(&self) -> Option<bool> {
+        match (self.strict, self.no_strict) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn read_options(
+        &self,
+        rounding: Rounding,
+        config: &Config,
+    ) -> Result<ReadOptions, Failure> {
+        let input = &config.input;
+        let mapping = match &self.mapping {
+            Some(path) => load_mapping(path)
+                .map_err(|e| Failure::config(format!("invalid mapping: {}", e)))?,
+            None => input
+                .mapping
+                .compile()
+                .map_err(|e| Failure::config(e.to_string()))?,
+        };
+        let strict = self.strict().or(input.strict).unwrap_or(false);
+        Ok(ReadOptions {
+            input_precision: self
+                .input_precision
+                .or(input.precision)
+                .map(|scale| Precision { scale, rounding }),
+            excess_precision: self
+                .excess_precision
+                .or(input.excess_precision)
+                .unwrap_or(ExcessPrecision::Reject),
+            strict: if strict {
+                Some(
+                    self.strict_checks
+                        .or(input.strict_checks)
+                        .unwrap_or_else(StrictChecks::all),
+                )
+            } else {
+                None
+            },
+            mapping,
+            max_rows: config.limits.max_rows,
+            transaction_ids: self
+                .transaction_ids
+                .or(input.transaction_ids)
+                .unwrap_or(IdFormat::Any),
+            tenant: self
+                .tenant
+                .clone()
+                .or_else(|| input.tenant.clone())
+                .unwrap_or_default(),
+            currency: default_currency(self.currency.as_ref().or(input.currency.as_ref()))?,
+            fx: load_fx(
+                self.fx_rates.as_ref().or(config.fx.rates.as_ref()),
+                self.fx_currency.as_ref().or(config.fx.currency.as_ref()),
+            )?,
+            reorder_window: self.reorder_window.or(input.reorder_window),
+            pending_references: self.pending_references.or(input.pending_references).map(
+                |max_rows| PendingLimits {
+                    max_rows,
+                    max_age: self.pending_max_age.or(input.pending_max_age),
+                },
+            ),
+        })
+    }
+
+    /// the size of the buffer shared by all the inputs, if rows are reordered
+    /// across files
+    pub fn merged_window(&self, config: &Config) -> Result<Option<usize>, Failure> {
+        if !self.reorder_across_files && !config.input.reorder_across_files.unwrap_or(false) {
+            return Ok(None);
+        }
+        match self.reorder_window.or(config.input.reorder_window) {
+            Some(window) => Ok(Some(window)),
+            None => Err(Failure::config(
+                "--reorder-across-files needs --reorder-window".into(),
+            )),
+        }
+    }
+
+    /// the options of a single source, with the file name as the tenant when
+    /// the tenants are given per file
+    pub fn source_options<'o>(
+        &self,
+        options: &'o ReadOptions,
+        source: &InputSource,
+        config: &Config,
+    ) -> Cow<'o, ReadOptions> {
+        let per_file = self.tenant_per_file
+            || (self.tenant.is_none() && config.input.tenant_per_file.unwrap_or(false));
+        match source.stem() {
+            Some(stem) if per_file => Cow::Owned(ReadOptions {
+                tenant: stem.to_owned(),
+                ..options.clone()
+            }),
+            _ => Cow::Borrowed(options),
+        }
+    }
+
+    pub fn sources(&self, config: &Config) -> Result<Vec<InputSource>, Failure> {
+        let order = self
+            .order
+            .or(config.input.order)
+            .unwrap_or(InputOrder::Args);
+        resolve_inputs(&self.inputs, order)
+            .map_err(|e| Failure::io(format!("can't resolve inputs: {}", e)))
+    }
+}
+
+/// read options for rows that don't come from input files, only the config applies
+pub fn config_read_options(rounding: Rounding, config: &Config) -> Result<ReadOptions, Failure> {
+    let input = &config.input;
+    Ok(ReadOptions {
+        input_precision: input.precision.map(|scale| Precision { scale, rounding }),
+        excess_precision: input.excess_precision.unwrap_or(ExcessPrecision::Reject),
+        transaction_ids: input.transaction_ids.unwrap_or(IdFormat::Any),
+        tenant: input.tenant.clone().unwrap_or_default(),
+        currency: default_currency(input.currency.as_ref())?,
+        fx: load_fx(config.fx.rates.as_ref(), config.fx.currency.as_ref())?,
+        mapping: input
+            .mapping
+            .compile()
+            .map_err(|e| Failure::config(e.to_string()))?,
+        ..ReadOptions::default()
+    })
+}
+
+fn default_currency(currency: Option<&String>) -> Result<Currency, Failure> {
+    parse_currency(currency.map_or("", String::as_str))
+        .map_err(|e| Failure::config(format!("invalid default currency: {}", e)))
+}
+
+/// the converter of the rate table, if there is one
+fn load_fx(
+    rates: Option<&PathBuf>,
+    currency: Option<&String>,
+) -> Result<Option<Arc<Converter>>, Failure> {
+    let rates = match rates {
+        Some(rates) => rates,
+        None => return Ok(None),
+    };
+    let currency = match currency.map(|currency| parse_currency(currency)) {
+        Some(Ok(currency)) if !currency.is_empty() => currency,
+        Some(Err(e)) => return Err(Failure::config(format!("invalid fx currency: {}", e))),
+        _ => return Err(Failure::config("the fx currency is required".into())),
+    };
+    let file = std::fs::File::open(rates)
+        .map_err(|e| Failure::io(format!("can't open {}: {}", rates.display(), e)))?;
+    let table = RateTable::read(file)
+        .map_err(|e| Failure::config(format!("{}: {}", rates.display(), e)))?;
+    Ok(Some(Arc::new(Converter::new(currency, table))))
+}
+
+/// Options for formatting the amounts, the defaults come from the config
+#[derive(StructOpt)]
+pub struct FormatOpt {
+    /// number of decimal places in the results [default: 4]
+    #[structopt(long, parse(try_from_str = parse_scale))]
+    precision: Option<u32>,
+    /// rounding of the results and of the rounded inputs: bankers, half-up or truncate
+    /// [default: half-up]
+    #[structopt(long)]
+    rounding: Option<Rounding>,
+}
+
+impl FormatOpt {
+    pub fn precision(&self, config: &Config) -> Precision {
+        let default = Precision::default();
+        Precision {
+            scale: self
+                .precision
+                .or(config.output.precision)
+                .unwrap_or(default.scale),
+            rounding: self.rounding(config),
+        }
+    }
+
+    pub fn rounding(&self, config: &Config) -> Rounding {
+        self.rounding
+            .or(config.output.rounding)
+            .unwrap_or(Precision::default().rounding)
+    }
+}
+
+/// creates the database with the storage and engine settings of the config
+pub fn new_db<'a>(config: &Config) -> MemoryDB<'a> {
+    match config.storage.backend {
+        StorageBackend::Memory => TransactionDB::new(HashMap::default(), HashMap::default())
+            .with_config(config.engine())
+            .with_rules(config.rules().expect("the config was validated"))
+            .with_detectors(config.detectors()),
+    }
+}
+
+/// What to do with the results when a strict check fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnError {
+    /// don't write any results
+    Abort,
+    /// write the accounts as they were before the failed row
+    Partial,
+}
+
+impl FromStr for OnError {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(OnError::Abort),
+            "partial" => Ok(OnError::Partial),
+            _ => Err(format!("unknown error policy: {}", s)),
+        }
+    }
+}
+
+/// Reports of all the inputs, `error` is set if the reading stopped early
+pub struct Loaded {
+    pub reports: Vec<ReadReport>,
+    pub error: Option<String>,
+}
+
+impl Loaded {
+    pub fn rejected(&self) -> usize {
+        self.reports
+            .iter()
+            .map(|r| r.rejections.len() + r.header.len())
+            .sum()
+    }
+
+    /// Stopped if the reading stopped early, Rejected if any row was rejected
+    pub fn exit_code(&self) -> ExitCode {
+        if self.error.is_some() {
+            ExitCode::Stopped
+        } else if self.rejected() > 0 {
+            ExitCode::Rejected
+        } else {
+            ExitCode::Success
+        }
+    }
+
+    /// print the end of run summary to stderr
+    pub fn summarize<'a, I>(&self, accounts: I, start: Instant)
+    where
+        I: IntoIterator<Item = &'a Account>,
+    {
+        eprintln!("{}", Summary::new(&self.reports, accounts, start.elapsed()));
+    }
+}
+
+/// read all the inputs into the database, the observer sees every row
+pub fn load_inputs<'a, T, A, O>(
+    opt: &InputOpt,
+    rounding: Rounding,
+    config: &Config,
+    db: &mut TransactionDB<'a, T, A>,
+    observer: &mut O,
+) -> Result<Loaded, Failure>
+where
+    T: TransactionStore,
+    A: AccountStore<'a>,
+    O: RowObserver,
+{
+    let read_options = opt.read_options(rounding, config)?;
+    let sources = opt.sources(config)?;
+    // the disputes waiting for their transaction are kept from one input to
+    // the next, the rows are reordered across them only when asked to
+    let merged_window = opt.merged_window(config)?;
+    if merged_window.is_some() || read_options.pending_references.is_some() {
+        let window = merged_window.or(read_options.reorder_window).unwrap_or(0);
+        let mut merged = MergedReader::new(window);
+        for source in &sources {
+            let input = source
+                .open()
+                .map_err(|e| Failure::io(format!("can't open {}: {}", source, e)))?;
+            let options = opt.source_options(&read_options, source, config);
+            let read = merged.read(&source.to_string(), input, db, &options, observer);
+            if !read || (merged_window.is_none() && !merged.flush(db, &options, observer)) {
+                break;
+            }
+        }
+        let (reports, error) = merged.finish(db, &read_options, observer);
+        let error = error.map(|e| format!("processing stopped: {}", e));
+        if let Some(error) = &error {
+            error!("{}", error);
+        }
+        return Ok(Loaded { reports, error });
+    }
+    read_sources(&sources, |source, input| {
+        let options = opt.source_options(&read_options, source, config);
+        read_csv_data(&source.to_string(), input, db, &options, observer)
+    })
+}
+
+/// open every source and pass it to `read`, stops at the first failed source
+pub fn read_sources<F>(sources: &[InputSource], mut read: F) -> Result<Loaded, Failure>
+where
+    F: FnMut(&InputSource, Box<dyn std::io::Read>) -> Result<ReadReport, Box<ReadError>>,
+{
+    let mut reports = Vec::with_capacity(sources.len());
+    for source in sources {
+        let input = source
+            .open()
+            .map_err(|e| Failure::io(format!("can't open {}: {}", source, e)))?;
+        match read(source, input) {
+            Ok(report) => {
+                debug!(
+                    "{}: rows: {}, accepted: {}, rejected: {}",
+                    report.source,
+                    report.rows,
+                    report.accepted,
+                    report.rejections.len()
+                );
+                reports.push(report);
+            }
+            Err(e) => {
+                let error = format!("processing stopped: {}", e);
+                error!("{}", error);
+                reports.push(e.report);
+                return Ok(Loaded {
+                    reports,
+                    error: Some(error),
+                });
+            }
+        }
+    }
+    Ok(Loaded {
+        reports,
+        error: None,
+    })
+}
+
+fn load_mapping(path: &PathBuf) -> Result<Mapping, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mapping: ColumnMapping =
+        toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+    mapping
+        .compile()
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
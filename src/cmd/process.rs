@@ -0,0 +1,213 @@
+use std::{
+    fs::File,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1};
+use structopt::StructOpt;
+
+use super::{load_inputs, new_db, ExitCode, Failure, FormatOpt, InputOpt, Loaded, OnError};
+use atm::{
+    config::Config,
+    db::MemoryDB,
+    io::{
+        compress::{CompressedWriter, Compression},
+        follow::Follow,
+        input::InputSource,
+        print_expired_disputes, print_flags, print_metrics, print_rejections, print_results,
+        MergedReader, ReadReport,
+    },
+    model::{account::Account, precision::Precision},
+};
+
+#[derive(StructOpt)]
+pub struct ProcessOpt {
+    /// write the results to this file instead of stdout, compressed if it ends with .gz or .zst
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// results on strict failure: abort writes nothing, partial writes the state before the failure
+    #[structopt(long, default_value = "abort")]
+    on_error: OnError,
+    /// write the rejected rows as csv to this file
+    #[structopt(long, parse(from_os_str))]
+    rejects: Option<PathBuf>,
+    /// write per input row counters as csv to this file
+    #[structopt(long, parse(from_os_str))]
+    metrics: Option<PathBuf>,
+    /// write the disputes that were resolved automatically as csv to this file
+    #[structopt(long, parse(from_os_str))]
+    expired_disputes: Option<PathBuf>,
+    /// write the clients flagged by the fraud detectors as csv to this file
+    #[structopt(long, parse(from_os_str))]
+    flags: Option<PathBuf>,
+    /// keep reading the input file as it grows, until interrupted; the results
+    /// are written on SIGUSR1, every --flush-interval and at the end
+    #[structopt(long)]
+    follow: bool,
+    /// seconds between the writes of the results in --follow mode
+    #[structopt(long, requires = "follow")]
+    flush_interval: Option<u64>,
+    #[structopt(flatten)]
+    format: FormatOpt,
+    #[structopt(flatten)]
+    input: InputOpt,
+}
+
+/// Process the inputs and write the accounts, see `ExitCode` for the exit codes
+pub fn run(opt: ProcessOpt, config: &Config) -> ExitCode {
+    if opt.follow {
+        return follow(&opt, config).unwrap_or_else(Failure::report);
+    }
+    let start = Instant::now();
+    let mut db = new_db(config);
+    let loaded = match load_inputs(
+        &opt.input,
+        opt.format.rounding(config),
+        config,
+        &mut db,
+        &mut (),
+    ) {
+        Ok(loaded) => loaded,
+        Err(e) => return e.report(),
+    };
+
+    let mut code = loaded.exit_code();
+    let precision = opt.format.precision(config);
+    let aborted = loaded.error.is_some() && opt.on_error == OnError::Abort;
+    if !aborted && !write_results(&opt, db.accounts(), &precision) {
+        code = ExitCode::Io;
+    }
+    if !write_reports(&opt, &loaded.reports, &db) {
+        code = ExitCode::Io;
+    }
+    loaded.summarize(db.accounts(), start);
+    code
+}
+
+/// read a single growing file, the results are rewritten after every segment.
+/// The reorder buffer and the disputes waiting for their transaction are kept
+/// from a segment to the next, they are only emptied when reading stops
+fn follow(opt: &ProcessOpt, config: &Config) -> Result<ExitCode, Failure> {
+    let start = Instant::now();
+    let options = opt
+        .input
+        .read_options(opt.format.rounding(config), config)?;
+    let sources = opt.input.sources(config)?;
+    let path = match sources.as_slice() {
+        [InputSource::File(path)] => path.clone(),
+        _ => return Err(Failure::config("--follow needs a single input file".into())),
+    };
+    let options = opt.input.source_options(&options, &sources[0], config);
+    let mut input = Follow::open(&path, opt.flush_interval.map(Duration::from_secs))
+        .map_err(|e| Failure::io(format!("can't open {}: {}", path.display(), e)))?;
+    let registered = signal_hook::flag::register(SIGUSR1, input.flush_flag())
+        .and_then(|_| signal_hook::flag::register(SIGINT, input.stop_flag()))
+        .and_then(|_| signal_hook::flag::register(SIGTERM, input.stop_flag()));
+    if let Err(e) = registered {
+        return Err(Failure::io(format!("can't register signals: {}", e)));
+    }
+
+    let precision = opt.format.precision(config);
+    let source = path.display().to_string();
+    let mut db = new_db(config);
+    let mut merged = MergedReader::new(options.reorder_window.unwrap_or(0));
+    let mut written = true;
+    while !input.is_stopped() {
+        let offset = input.line_offset();
+        if !merged.read_segment(&source, &mut input, offset, &mut db, &options, &mut ()) {
+            break;
+        }
+        // the last segment is written once the reader is finished
+        if !input.is_stopped() {
+            written &= write_results(opt, db.accounts(), &precision);
+            written &= write_reports(opt, merged.reports(), &db);
+        }
+    }
+
+    let (reports, error) = merged.finish(&mut db, &options, &mut ());
+    let error = error.map(|e| {
+        let message = format!("processing stopped: {}", e);
+        error!("{}", message);
+        message
+    });
+    if error.is_none() || opt.on_error == OnError::Partial {
+        written &= write_results(opt, db.accounts(), &precision);
+    }
+    written &= write_reports(opt, &reports, &db);
+    let loaded = Loaded { reports, error };
+    loaded.summarize(db.accounts(), start);
+    Ok(if written {
+        loaded.exit_code()
+    } else {
+        ExitCode::Io
+    })
+}
+
+/// write the accounts to --output or stdout, false if that failed
+fn write_results<'a>(
+    opt: &ProcessOpt,
+    accounts: impl IntoIterator<Item = &'a Account>,
+    precision: &Precision,
+) -> bool {
+    let accounts = accounts.into_iter();
+    let written = match &opt.output {
+        Some(path) => File::create(path)
+            .and_then(|f| CompressedWriter::new(f, Compression::from_path(path)))
+            .and_then(|mut out| {
+                print_results(&mut out, accounts, precision);
+                out.finish().map(|_| ())
+            }),
+        None => {
+            print_results(std::io::stdout(), accounts, precision);
+            Ok(())
+        }
+    };
+    if let Err(e) = &written {
+        error!("can't write results: {}", e);
+    }
+    written.is_ok()
+}
+
+/// write the --rejects, --metrics, --expired-disputes and --flags files, false
+/// if that failed
+fn write_reports(opt: &ProcessOpt, reports: &[ReadReport], db: &MemoryDB) -> bool {
+    let mut written = true;
+    if let Some(path) = &opt.rejects {
+        if let Err(e) = File::create(path)
+            .map_err(csv::Error::from)
+            .and_then(|f| print_rejections(f, reports.iter()))
+        {
+            error!("can't write rejects: {}", e);
+            written = false;
+        }
+    }
+    if let Some(path) = &opt.metrics {
+        if let Err(e) = File::create(path)
+            .map_err(csv::Error::from)
+            .and_then(|f| print_metrics(f, reports.iter()))
+        {
+            error!("can't write metrics: {}", e);
+            written = false;
+        }
+    }
+    if let Some(path) = &opt.expired_disputes {
+        if let Err(e) = File::create(path)
+            .map_err(csv::Error::from)
+            .and_then(|f| print_expired_disputes(f, db.expired_disputes()))
+        {
+            error!("can't write expired disputes: {}", e);
+            written = false;
+        }
+    }
+    if let Some(path) = &opt.flags {
+        if let Err(e) = File::create(path)
+            .map_err(csv::Error::from)
+            .and_then(|f| print_flags(f, db.flags()))
+        {
+            error!("can't write flags: {}", e);
+            written = false;
+        }
+    }
+    written
+}
@@ -0,0 +1,71 @@
+use std::{fs::File, path::PathBuf, time::Instant};
+
+use structopt::StructOpt;
+
+use super::{load_inputs, new_db, ExitCode, FormatOpt, InputOpt};
+use atm::{
+    config::Config,
+    io::{compress::decompress, read_results},
+    model::output::Record,
+    report::compare::compare,
+};
+
+#[derive(StructOpt)]
+pub struct ReconcileOpt {
+    /// the expected accounts, in the format written by `process`
+    #[structopt(long, parse(from_os_str))]
+    expected: PathBuf,
+    #[structopt(flatten)]
+    format: FormatOpt,
+    #[structopt(flatten)]
+    input: InputOpt,
+}
+
+/// Process the inputs and compare the accounts with the expected ones, the
+/// differences are printed as expected -> actual.
+/// Exits with `ExitCode::Mismatch` if the accounts differ
+pub fn run(opt: ReconcileOpt, config: &Config) -> ExitCode {
+    let start = Instant::now();
+    let expected = match File::open(&opt.expected)
+        .and_then(decompress)
+        .map_err(csv::Error::from)
+        .and_then(read_results)
+    {
+        Ok(expected) => expected,
+        Err(e) => {
+            error!("can't read {}: {}", opt.expected.display(), e);
+            return ExitCode::Io;
+        }
+    };
+
+    let mut db = new_db(config);
+    let loaded = match load_inputs(
+        &opt.input,
+        opt.format.rounding(config),
+        config,
+        &mut db,
+        &mut (),
+    ) {
+        Ok(loaded) => loaded,
+        Err(e) => return e.report(),
+    };
+    loaded.summarize(db.accounts(), start);
+    if loaded.error.is_some() {
+        return ExitCode::Stopped;
+    }
+
+    let precision = opt.format.precision(config);
+    let actual: Vec<Record> = db
+        .accounts()
+        .flat_map(|acc| Record::all(acc, &precision))
+        .collect();
+    let differences = compare(&expected, &actual);
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    if differences.is_empty() {
+        ExitCode::Success
+    } else {
+        ExitCode::Mismatch
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use structopt::StructOpt;
+
+use super::{config_read_options, new_db, ExitCode, Failure, FormatOpt};
+use atm::{config::Config, repl::Session};
+
+#[derive(StructOpt)]
+pub struct ReplOpt {
+    /// add the accounts of a results file written by `process` before the inputs
+    #[structopt(long, parse(from_os_str))]
+    snapshot: Option<PathBuf>,
+    #[structopt(flatten)]
+    format: FormatOpt,
+    /// input files to process before the first command
+    #[structopt(name = "FILE", parse(from_os_str))]
+    inputs: Vec<PathBuf>,
+}
+
+/// Read commands from stdin and apply them to the database, `help` lists them
+pub fn run(opt: ReplOpt, config: &Config) -> ExitCode {
+    let options = match config_read_options(opt.format.rounding(config), config) {
+        Ok(options) => options,
+        Err(e) => return e.report(),
+    };
+    let mut session = match Session::new(new_db(config), options, opt.format.precision(config)) {
+        Ok(session) => session,
+        Err(e) => return Failure::config(e.to_string()).report(),
+    };
+
+    // the snapshot first, then the inputs
+    let preload = opt
+        .snapshot
+        .iter()
+        .map(|path| (path, true))
+        .chain(opt.inputs.iter().map(|path| (path, false)));
+    for (path, snapshot) in preload {
+        let output = if snapshot {
+            session.restore(path)
+        } else {
+            session.load(path)
+        };
+        match output {
+            Ok(output) => println!("{}", output),
+            Err(e) => return Failure::io(e).report(),
+        }
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => return Failure::io(format!("can't read command: {}", e)).report(),
+            None => return ExitCode::Success,
+        };
+        if matches!(line.trim(), "quit" | "exit") {
+            return ExitCode::Success;
+        }
+        match session.execute(&line) {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{}", output),
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
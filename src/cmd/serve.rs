@@ -0,0 +1,61 @@
+use std::{net::SocketAddr, net::TcpListener, thread};
+
+use structopt::StructOpt;
+
+use super::{config_read_options, new_db, ExitCode, Failure, FormatOpt};
+use atm::{
+    config::Config,
+    server::{http, tcp, Server},
+};
+
+/// the http address when neither the command line nor the config set one
+const DEFAULT_HTTP: &str = "127.0.0.1:8080";
+
+#[derive(StructOpt)]
+pub struct ServeOpt {
+    /// address of the http server [default: 127.0.0.1:8080]
+    #[structopt(long)]
+    http: Option<SocketAddr>,
+    /// also accept csv rows over tcp on this address
+    #[structopt(long)]
+    tcp: Option<SocketAddr>,
+    #[structopt(flatten)]
+    format: FormatOpt,
+}
+
+/// Keep the database in memory and apply the transactions submitted over http
+/// or tcp, runs until the process is stopped
+pub fn run(opt: ServeOpt, config: &Config) -> ExitCode {
+    let options = match config_read_options(opt.format.rounding(config), config) {
+        Ok(options) => options,
+        Err(e) => return e.report(),
+    };
+    let server = Server::new(new_db(config), options, opt.format.precision(config));
+
+    let http_addr = opt
+        .http
+        .or(config.serve.http)
+        .unwrap_or_else(|| DEFAULT_HTTP.parse().expect("valid default address"));
+    let http = match tiny_http::Server::http(http_addr) {
+        Ok(http) => http,
+        Err(e) => return Failure::io(format!("can't listen on {}: {}", http_addr, e)).report(),
+    };
+    let listener = match opt.tcp.or(config.serve.tcp).map(TcpListener::bind) {
+        Some(Ok(listener)) => Some(listener),
+        Some(Err(e)) => return Failure::io(format!("can't listen for tcp: {}", e)).report(),
+        None => None,
+    };
+
+    eprintln!("listening for http on {}", http_addr);
+    thread::scope(|scope| {
+        if let Some(listener) = &listener {
+            if let Ok(addr) = listener.local_addr() {
+                eprintln!("listening for csv on {}", addr);
+            }
+            let server = &server;
+            scope.spawn(move || tcp::serve(server, listener));
+        }
+        http::serve(&server, &http);
+    });
+    ExitCode::Success
+}
@@ -0,0 +1,61 @@
+use std::time::Instant;
+
+use structopt::StructOpt;
+
+use super::{load_inputs, new_db, ExitCode, FormatOpt, InputOpt};
+use atm::{
+    config::Config,
+    model::ClientId,
+    report::statement::{print_statement_csv, print_statement_text, Statement, StatementFormat},
+};
+
+#[derive(StructOpt)]
+pub struct StatementOpt {
+    /// the client to print the statement for
+    #[structopt(long)]
+    client: ClientId,
+    /// the tenant of the client [default: no tenant]
+    #[structopt(long = "client-tenant", default_value = "")]
+    client_tenant: String,
+    /// output format: csv or text
+    #[structopt(long = "format", default_value = "text")]
+    statement_format: StatementFormat,
+    #[structopt(flatten)]
+    format: FormatOpt,
+    #[structopt(flatten)]
+    input: InputOpt,
+}
+
+/// Process the inputs and write the statement of a single client, see
+/// `ExitCode` for the exit codes
+pub fn run(opt: StatementOpt, config: &Config) -> ExitCode {
+    let start = Instant::now();
+    let mut db = new_db(config);
+    let mut statement = Statement::new(opt.client).in_tenant(opt.client_tenant.clone());
+    let loaded = match load_inputs(
+        &opt.input,
+        opt.format.rounding(config),
+        config,
+        &mut db,
+        &mut statement,
+    ) {
+        Ok(loaded) => loaded,
+        Err(e) => return e.report(),
+    };
+
+    let precision = opt.format.precision(config);
+    let stdout = std::io::stdout();
+    let written =
+        match opt.statement_format {
+            StatementFormat::Csv => print_statement_csv(stdout.lock(), &statement, &precision)
+                .map_err(|e| e.to_string()),
+            StatementFormat::Text => print_statement_text(stdout.lock(), &statement, &precision)
+                .map_err(|e| e.to_string()),
+        };
+    loaded.summarize(db.accounts(), start);
+    if let Err(e) = written {
+        error!("can't write statement: {}", e);
+        return ExitCode::Io;
+    }
+    loaded.exit_code()
+}
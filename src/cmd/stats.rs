@@ -0,0 +1,42 @@
+use structopt::StructOpt;
+
+use super::{load_inputs, new_db, ExitCode, FormatOpt, InputOpt};
+use atm::{
+    config::Config,
+    report::stats::{print_stats, Stats},
+};
+
+#[derive(StructOpt)]
+pub struct StatsOpt {
+    #[structopt(flatten)]
+    format: FormatOpt,
+    #[structopt(flatten)]
+    input: InputOpt,
+}
+
+/// Process the inputs and print a summary of them, see `ExitCode` for the exit codes
+pub fn run(opt: StatsOpt, config: &Config) -> ExitCode {
+    let mut db = new_db(config);
+    let mut stats = Stats::default();
+    let loaded = match load_inputs(
+        &opt.input,
+        opt.format.rounding(config),
+        config,
+        &mut db,
+        &mut stats,
+    ) {
+        Ok(loaded) => loaded,
+        Err(e) => return e.report(),
+    };
+    let stdout = std::io::stdout();
+    if let Err(e) = print_stats(
+        stdout.lock(),
+        &stats,
+        loaded.reports.iter(),
+        &opt.format.precision(config),
+    ) {
+        error!("can't write stats: {}", e);
+        return ExitCode::Io;
+    }
+    loaded.exit_code()
+}
@@ -0,0 +1,62 @@
+use structopt::StructOpt;
+
+use super::{read_sources, ExitCode, InputOpt};
+use atm::{
+    config::Config,
+    io::validate_csv_data,
+    model::precision::{Precision, Rounding},
+};
+
+#[derive(StructOpt)]
+pub struct ValidateOpt {
+    /// rounding of the inputs over --input-precision: bankers, half-up or truncate
+    /// [default: half-up]
+    #[structopt(long)]
+    rounding: Option<Rounding>,
+    /// print only the summary, not every invalid row
+    #[structopt(short, long)]
+    quiet: bool,
+    #[structopt(flatten)]
+    input: InputOpt,
+}
+
+/// Parse and convert the inputs without processing the transactions, see
+/// `ExitCode` for the exit codes
+pub fn run(opt: ValidateOpt, config: &Config) -> ExitCode {
+    let rounding = opt
+        .rounding
+        .or(config.output.rounding)
+        .unwrap_or(Precision::default().rounding);
+    let loaded = opt
+        .input
+        .read_options(rounding, config)
+        .and_then(|options| {
+            read_sources(&opt.input.sources(config)?, |source, input| {
+                let options = opt.input.source_options(&options, source, config);
+                validate_csv_data(&source.to_string(), input, &options)
+            })
+        });
+    let loaded = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => return e.report(),
+    };
+
+    for report in &loaded.reports {
+        if !opt.quiet {
+            for rejection in report.all_rejections() {
+                println!("{}:{}: {}", report.source, rejection.line, rejection.reason);
+            }
+        }
+        println!(
+            "{}: {} rows, {} valid, {} invalid",
+            report.source,
+            report.rows,
+            report.accepted,
+            report.rejections.len()
+        );
+    }
+    if let Some(e) = &loaded.error {
+        println!("{}", e);
+    }
+    loaded.exit_code()
+}
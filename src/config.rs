@@ -0,0 +1,640 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::{
+    db::{
+        fraud::{DepositWithdrawal, Detectors, DisputeRate, FailedWithdrawals, RepeatedDisputes},
+        rules::{Limit, Rule, RuleKind, RuleSet, Scope},
+        EngineConfig,
+    },
+    io::{
+        input::InputOrder,
+        mapping::{ColumnMapping, MappingError},
+        StrictChecks,
+    },
+    model::{
+        account::DisputePolicy,
+        id::IdFormat,
+        input::parse_currency,
+        precision::{ExcessPrecision, Rounding, MAX_SCALE},
+        time::Window,
+        Amount, ClientId, TenantId, Timestamp,
+    },
+};
+
+/// Settings loaded from a toml file, every value is optional and the command
+/// line flags take precedence over it
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub input: InputConfig,
+    pub output: OutputConfig,
+    pub dispute: DisputeConfig,
+    pub limits: LimitsConfig,
+    pub storage: StorageConfig,
+    pub serve: ServeConfig,
+    pub fx: FxConfig,
+    pub time: TimeConfig,
+    pub rules: Vec<RuleConfig>,
+    /// the clients of every tier the rules may refer to
+    pub tiers: BTreeMap<String, TierConfig>,
+    pub fraud: FraudConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    #[serde(deserialize_with = "from_str")]
+    pub order: Option<InputOrder>,
+    pub precision: Option<u32>,
+    #[serde(deserialize_with = "from_str")]
+    pub excess_precision: Option<ExcessPrecision>,
+    pub strict: Option<bool>,
+    #[serde(deserialize_with = "from_str")]
+    pub strict_checks: Option<StrictChecks>,
+    #[serde(deserialize_with = "from_str")]
+    pub transaction_ids: Option<IdFormat>,
+    /// tenant of the rows without a tenant column
+    pub tenant: Option<String>,
+    /// use the file name as the tenant of the rows without a tenant column
+    pub tenant_per_file: Option<bool>,
+    /// currency of the rows without a currency column
+    pub currency: Option<String>,
+    /// reorder the rows by timestamp within a buffer of this many rows
+    pub reorder_window: Option<usize>,
+    /// share the reorder buffer between the inputs
+    pub reorder_across_files: Option<bool>,
+    /// keep up to this many disputes of unknown transactions until the
+    /// transaction comes
+    pub pending_references: Option<usize>,
+    /// reject a waiting dispute after this many more rows
+    pub pending_max_age: Option<u64>,
+    pub mapping: ColumnMapping,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub precision: Option<u32>,
+    #[serde(deserialize_with = "from_str")]
+    pub rounding: Option<Rounding>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputeConfig {
+    pub lock_on_chargeback: Option<bool>,
+    pub dispute_withdrawals: Option<bool>,
+    /// how long after the transaction a dispute may be opened, e.g. `90d`
+    #[serde(deserialize_with = "from_str")]
+    pub open_window: Option<Window>,
+    /// how long a dispute stays open before it is resolved, e.g. `1000tx`
+    #[serde(deserialize_with = "from_str")]
+    pub expire_after: Option<Window>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// maximum number of rows in a single input
+    pub max_rows: Option<u64>,
+    /// maximum number of accounts
+    pub max_accounts: Option<usize>,
+}
+
+/// Where the accounts and transactions are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Memory,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::Memory,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeConfig {
+    /// address of the http server
+    pub http: Option<SocketAddr>,
+    /// address of the csv over tcp listener
+    pub tcp: Option<SocketAddr>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FxConfig {
+    /// csv table of exchange rates, see `fx`
+    pub rates: Option<PathBuf>,
+    /// the currency of the accounts, the amounts are converted into it
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeConfig {
+    /// seconds a timestamp may go back from the latest one before the
+    /// transaction is refused
+    pub tolerance: Option<Timestamp>,
+}
+
+/// A limit on the deposits or withdrawals, see `RuleSet`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleConfig {
+    pub name: String,
+    #[serde(rename = "type", deserialize_with = "from_str")]
+    pub kind: Option<RuleKind>,
+    /// limit the rule to a currency, in any case
+    pub currency: Option<String>,
+    /// the largest single amount, e.g. `"1000"`
+    #[serde(deserialize_with = "from_str")]
+    pub max_amount: Option<Amount>,
+    /// the most transactions in the window
+    pub max_count: Option<u64>,
+    /// the largest sum of the amounts in the window
+    #[serde(deserialize_with = "from_str")]
+    pub max_volume: Option<Amount>,
+    /// the window of `max_count` and `max_volume`, e.g. `1d` or `100tx`
+    #[serde(deserialize_with = "from_str")]
+    pub window: Option<Window>,
+    /// apply the rule to the clients of a tier only
+    pub tier: Option<String>,
+    /// apply the rule to a single client only
+    pub client: Option<ClientId>,
+    /// the tenant of `client`, the empty tenant when not set
+    pub tenant: Option<TenantId>,
+}
+
+impl RuleConfig {
+    fn rule(&self) -> Result<(Scope, Rule), ConfigError> {
+        let invalid =
+            |message: &str| ConfigError::Invalid(format!("rules.{}: {}", self.name, message));
+        if self.name.is_empty() {
+            return Err(ConfigError::Invalid("rules: name is required".into()));
+        }
+        let kind = self.kind.ok_or_else(|| invalid("type is required"))?;
+        let limit = match (
+            self.max_amount,
+            self.max_count,
+            self.max_volume,
+            self.window,
+        ) {
+            (Some(amount), None, None, None) => Limit::Amount(amount),
+            (None, Some(count), None, Some(window)) => Limit::Count { count, window },
+            (None, None, Some(amount), Some(window)) => Limit::Volume { amount, window },
+            (None, Some(_), None, None) | (None, None, Some(_), None) => {
+                return Err(invalid("window is required with max_count and max_volume"))
+            }
+            _ => {
+                return Err(invalid(
+                    "exactly one of max_amount, max_count or max_volume is required",
+                ))
+            }
+        };
+        let currency = match &self.currency {
+            Some(currency) => Some(parse_currency(currency).map_err(|e| invalid(&e.to_string()))?),
+            None => None,
+        };
+        let scope = match (&self.tier, self.client) {
+            (_, None) if self.tenant.is_some() => return Err(invalid("tenant requires client")),
+            (None, None) => Scope::Global,
+            (Some(tier), None) => Scope::Tier(tier.clone()),
+            (None, Some(client_id)) => {
+                Scope::Client(self.tenant.clone().unwrap_or_default(), client_id)
+            }
+            (Some(_), Some(_)) => return Err(invalid("tier and client are exclusive")),
+        };
+        let rule = Rule {
+            name: self.name.clone(),
+            kind,
+            currency,
+            limit,
+        };
+        Ok((scope, rule))
+    }
+}
+
+/// The clients of a tier, either `[2, 3]` for clients of the empty tenant or
+/// `[[tiers.<name>]]` tables with a `tenant` and its `clients`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TierConfig {
+    Clients(Vec<ClientId>),
+    Tenants(Vec<TenantClients>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantClients {
+    pub tenant: TenantId,
+    pub clients: Vec<ClientId>,
+}
+
+impl TierConfig {
+    /// the tenant and the id of every client of the tier
+    fn clients(&self) -> Vec<(&str, ClientId)> {
+        match self {
+            TierConfig::Clients(clients) => clients.iter().map(|id| ("", *id)).collect(),
+            TierConfig::Tenants(tenants) => tenants
+                .iter()
+                .flat_map(|t| t.clients.iter().map(move |id| (t.tenant.as_str(), *id)))
+                .collect(),
+        }
+    }
+}
+
+/// The detectors of suspicious clients, see `db::fraud`, a detector is on
+/// when its section is present
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FraudConfig {
+    /// freeze an account once the scores of its flags add up to this
+    pub freeze_score: Option<u32>,
+    pub deposit_withdrawal: Option<DepositWithdrawalConfig>,
+    pub repeated_disputes: Option<CountConfig>,
+    pub failed_withdrawals: Option<CountConfig>,
+    pub dispute_rate: Option<DisputeRateConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DepositWithdrawalConfig {
+    /// the smallest deposit that is watched, e.g. `"10000"`
+    #[serde(deserialize_with = "parse")]
+    pub min_amount: Amount,
+    /// how soon after the deposit a withdrawal is suspicious, e.g. `1d`
+    #[serde(deserialize_with = "parse")]
+    pub window: Window,
+    pub score: u32,
+}
+
+/// A number of events of a client within a window
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CountConfig {
+    pub count: usize,
+    #[serde(deserialize_with = "parse")]
+    pub window: Window,
+    pub score: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisputeRateConfig {
+    /// the largest ratio of disputes to deposits and withdrawals, e.g. `"0.1"`
+    #[serde(deserialize_with = "parse")]
+    pub max_rate: Decimal,
+    /// the deposits and withdrawals a client needs before the rate is checked
+    pub min_transactions: u64,
+    pub score: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("can't read config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("invalid config: {0}")]
+    Invalid(String),
+    #[error("invalid config: input.mapping: {0}")]
+    Mapping(#[from] MappingError),
+}
+
+impl Config {
+    /// read and validate the config file
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let scales = [
+            ("input.precision", self.input.precision),
+            ("output.precision", self.output.precision),
+        ];
+        for (name, scale) in &scales {
+            if matches!(scale, Some(scale) if *scale > MAX_SCALE) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be at most {}",
+                    name, MAX_SCALE
+                )));
+            }
+        }
+        if self.limits.max_rows == Some(0) {
+            return Err(ConfigError::Invalid(
+                "limits.max_rows must be positive".into(),
+            ));
+        }
+        if self.limits.max_accounts == Some(0) {
+            return Err(ConfigError::Invalid(
+                "limits.max_accounts must be positive".into(),
+            ));
+        }
+        if self.fx.rates.is_some() && self.fx.currency.is_none() {
+            return Err(ConfigError::Invalid(
+                "fx.currency is required with fx.rates".into(),
+            ));
+        }
+        if matches!(self.time.tolerance, Some(tolerance) if tolerance < 0) {
+            return Err(ConfigError::Invalid(
+                "time.tolerance can't be negative".into(),
+            ));
+        }
+        if self.input.reorder_across_files == Some(true) && self.input.reorder_window.is_none() {
+            return Err(ConfigError::Invalid(
+                "input.reorder_window is required with input.reorder_across_files".into(),
+            ));
+        }
+        self.input.mapping.compile()?;
+        self.rules()?;
+        let counts = [
+            ("fraud.repeated_disputes", &self.fraud.repeated_disputes),
+            ("fraud.failed_withdrawals", &self.fraud.failed_withdrawals),
+        ];
+        for (name, config) in &counts {
+            if matches!(config, Some(config) if config.count == 0) {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.count must be positive",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// the fraud detectors
+    pub fn detectors(&self) -> Detectors {
+        let fraud = &self.fraud;
+        let mut detectors = Detectors::default();
+        if let Some(c) = &fraud.deposit_withdrawal {
+            detectors.add(Box::new(DepositWithdrawal::new(
+                c.min_amount,
+                c.window,
+                c.score,
+            )));
+        }
+        if let Some(c) = &fraud.repeated_disputes {
+            detectors.add(Box::new(RepeatedDisputes::new(c.count, c.window, c.score)));
+        }
+        if let Some(c) = &fraud.failed_withdrawals {
+            detectors.add(Box::new(FailedWithdrawals::new(c.count, c.window, c.score)));
+        }
+        if let Some(c) = &fraud.dispute_rate {
+            detectors.add(Box::new(DisputeRate::new(
+                c.max_rate,
+                c.min_transactions,
+                c.score,
+            )));
+        }
+        detectors
+    }
+
+    /// the limit rules and the tiers of the clients
+    pub fn rules(&self) -> Result<RuleSet, ConfigError> {
+        let mut rules = RuleSet::default();
+        for (tier, clients) in &self.tiers {
+            for (tenant, client_id) in clients.clients() {
+                if let Some(other) = rules.assign(tenant.to_owned(), client_id, tier.clone()) {
+                    let client = match tenant {
+                        "" => format!("client {}", client_id),
+                        _ => format!("client {} of tenant {}", client_id, tenant),
+                    };
+                    return Err(ConfigError::Invalid(format!(
+                        "{} is in tiers {} and {}",
+                        client, other, tier
+                    )));
+                }
+            }
+        }
+        for config in &self.rules {
+            let (scope, rule) = config.rule()?;
+            if let Scope::Tier(tier) = &scope {
+                if !self.tiers.contains_key(tier) {
+                    return Err(ConfigError::Invalid(format!(
+                        "rules.{}: unknown tier {}",
+                        rule.name, tier
+                    )));
+                }
+            }
+            rules.add(scope, rule);
+        }
+        Ok(rules)
+    }
+
+    /// the engine settings of the config
+    pub fn engine(&self) -> EngineConfig {
+        let default = DisputePolicy::default();
+        EngineConfig {
+            dispute: DisputePolicy {
+                lock_on_chargeback: self
+                    .dispute
+                    .lock_on_chargeback
+                    .unwrap_or(default.lock_on_chargeback),
+                dispute_withdrawals: self
+                    .dispute
+                    .dispute_withdrawals
+                    .unwrap_or(default.dispute_withdrawals),
+                open_window: self.dispute.open_window,
+                expire_after: self.dispute.expire_after,
+            },
+            max_accounts: self.limits.max_accounts,
+            time_tolerance: self.time.tolerance,
+            freeze_score: self.fraud.freeze_score,
+        }
+    }
+}
+
+/// deserialize an optional value with its `FromStr` implementation
+fn from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+/// deserialize a required value with its `FromStr` implementation
+fn parse<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        db::rules::Scope,
+        model::{id::IdFormat, precision::Rounding, time::Window},
+    };
+
+    use super::{Config, StorageBackend};
+
+    #[test]
+    fn test_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [input]
+            order = "name"
+            strict = true
+            strict_checks = "malformed,conversion"
+            transaction_ids = "uuid"
+
+            [input.mapping.columns]
+            tx = ["txn_id"]
+
+            [output]
+            precision = 2
+            rounding = "bankers"
+
+            [dispute]
+            lock_on_chargeback = false
+            open_window = "90d"
+            expire_after = "100tx"
+
+            [limits]
+            max_accounts = 10
+
+            [storage]
+            backend = "memory"
+
+            [serve]
+            http = "127.0.0.1:9000"
+
+            [time]
+            tolerance = 60
+
+            [tiers]
+            gold = [2, 3]
+
+            [[tiers.silver]]
+            tenant = "b"
+            clients = [2, 4]
+
+            [[rules]]
+            name = "max_withdrawal"
+            type = "withdrawal"
+            currency = "usd"
+            max_amount = "1000"
+
+            [[rules]]
+            name = "max_withdrawal"
+            type = "withdrawal"
+            max_amount = "5000"
+            tier = "gold"
+
+            [[rules]]
+            name = "daily_withdrawals"
+            type = "withdrawal"
+            max_count = 5
+            window = "1d"
+
+            [[rules]]
+            name = "daily_withdrawals"
+            type = "withdrawal"
+            max_count = 50
+            window = "1d"
+            tenant = "b"
+            client = 7
+
+            [fraud]
+            freeze_score = 100
+
+            [fraud.deposit_withdrawal]
+            min_amount = "10000"
+            window = "1h"
+            score = 60
+
+            [fraud.dispute_rate]
+            max_rate = "0.1"
+            min_transactions = 20
+            score = 40
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.output.rounding, Some(Rounding::Bankers));
+        assert_eq!(config.input.transaction_ids, Some(IdFormat::Uuid));
+        assert_eq!(config.storage.backend, StorageBackend::Memory);
+        let engine = config.engine();
+        assert!(!engine.dispute.lock_on_chargeback);
+        assert!(engine.dispute.dispute_withdrawals);
+        assert_eq!(
+            engine.dispute.open_window,
+            Some(Window::Seconds(90 * 86400))
+        );
+        assert_eq!(engine.dispute.expire_after, Some(Window::Transactions(100)));
+        assert_eq!(engine.max_accounts, Some(10));
+        assert_eq!(engine.time_tolerance, Some(60));
+        assert_eq!(config.serve.http, Some(([127, 0, 0, 1], 9000).into()));
+        assert!(!config.rules().unwrap().is_empty());
+        let (_, rule) = config.rules[0].rule().unwrap();
+        assert_eq!(rule.currency.as_deref(), Some("USD"));
+        let (scope, _) = config.rules[3].rule().unwrap();
+        assert_eq!(scope, Scope::Client("b".into(), 7));
+        assert_eq!(config.tiers["silver"].clients(), [("b", 2), ("b", 4)]);
+        assert!(!config.detectors().is_empty());
+        assert_eq!(engine.freeze_score, Some(100));
+
+        let invalid = [
+            "[output]\nrounding = \"up\"",
+            "[output]\nprecision = 30",
+            "[input]\ntransaction_ids = \"string\"",
+            "[storage]\nbackend = \"postgres\"",
+            "[limits]\nmax_rows = 0",
+            "[time]\ntolerance = -1",
+            "[dispute]\nopen_window = \"3 days\"",
+            "[input]\nreorder_across_files = true",
+            "[input.mapping.columns]\ntx = [\"client\"]",
+            "[[rules]]\nname = \"a\"\ntype = \"deposit\"\nmax_count = 3",
+            "[[rules]]\nname = \"a\"\ntype = \"deposit\"\nmax_amount = \"1\"\nmax_count = 3\nwindow = \"1d\"",
+            "[[rules]]\nname = \"a\"\ntype = \"deposit\"\nmax_amount = \"1\"\ntier = \"gold\"",
+            "[[rules]]\nname = \"a\"\ntype = \"transfer\"\nmax_amount = \"1\"",
+            "[[rules]]\nname = \"a\"\ntype = \"deposit\"\ncurrency = \"dollar\"\nmax_amount = \"1\"",
+            "[tiers]\ngold = [1]\nsilver = [1]",
+            "[tiers]\ngold = [1]\n[[tiers.silver]]\nclients = [1]",
+            "[[rules]]\nname = \"a\"\ntype = \"deposit\"\nmax_amount = \"1\"\ntenant = \"b\"",
+            "[fraud.repeated_disputes]\ncount = 0\nwindow = \"1d\"\nscore = 1",
+            "[fraud.failed_withdrawals]\ncount = 3\nscore = 1",
+            "[serve]\nhttp = \"localhost\"",
+            "[unknown]",
+        ];
+        for content in &invalid {
+            let valid = toml::from_str::<Config>(content)
+                .map_err(|e| e.to_string())
+                .and_then(|c| c.validate().map_err(|e| e.to_string()));
+            assert!(valid.is_err(), "{} should be invalid", content);
+        }
+    }
+}
@@ -20,6 +20,14 @@ impl<'a> IntoIterator for AccountsIter<'a> {
 impl<'a> AccountStore<'a> for HashMap<ClientId, Account> {
     type IteratorType = AccountsIter<'a>;
 
+    fn get_account(&self, client_id: &ClientId) -> Option<&Account> {
+        self.get(client_id)
+    }
+
+    fn account_count(&self) -> usize {
+        self.len()
+    }
+
     fn get_account_mut(&mut self, client_id: &ClientId) -> Option<&mut Account> {
         self.get_mut(client_id)
     }
@@ -0,0 +1,646 @@
+//! Heuristics that flag the clients whose activity deserves a review.
+//!
+//! The detectors see every transaction after it was processed, accepted or
+//! not, and may flag its client with a score and a reason. The scores of a
+//! client add up, the account is frozen once they reach
+//! `EngineConfig::freeze_score`.
+
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+use rust_decimal::Decimal;
+
+use crate::model::{
+    account::TxError, time::Window, Amount, ClientId, Currency, DisputeState, Moment, TenantId,
+    TransactionId, Tx, TxOperation,
+};
+
+type Key = (TenantId, ClientId);
+
+/// The state a detector keeps about a client, see `Detector::save`
+pub type Saved = Box<dyn Any + Send>;
+
+fn key(tx: &Tx) -> Key {
+    (tx.tenant.clone(), tx.client_id)
+}
+
+/// A processed transaction as the detectors see it
+#[derive(Debug)]
+pub struct Observation<'t> {
+    pub tx: &'t Tx,
+    pub at: Moment,
+    /// the reason the transaction was rejected for
+    pub error: Option<&'t TxError>,
+}
+
+impl Observation<'_> {
+    fn accepted(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// What a detector found about the client of a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub score: u32,
+    pub reason: String,
+}
+
+/// Watches the processed transactions, a detector keeps its own state per
+/// client
+pub trait Detector: Send {
+    /// the name of the detector in the flags
+    fn name(&self) -> &'static str;
+
+    fn observe(&mut self, observation: &Observation) -> Option<Finding>;
+
+    /// a copy of the state kept about a client, put back by `restore` when a
+    /// change of the database is undone
+    fn save(&self, tenant: &str, client_id: ClientId) -> Saved;
+
+    fn restore(&mut self, tenant: &str, client_id: ClientId, saved: Saved);
+
+    /// a copy of the detector and its state
+    fn clone_box(&self) -> Box<dyn Detector>;
+}
+
+impl Clone for Box<dyn Detector> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// A client flagged by a detector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flag {
+    pub tenant: TenantId,
+    pub client_id: ClientId,
+    /// the transaction that raised the flag
+    pub transaction_id: TransactionId,
+    pub detector: &'static str,
+    pub score: u32,
+    /// the sum of the scores of the client so far
+    pub total: u32,
+    pub reason: String,
+    pub at: Moment,
+    /// if the flag froze the account
+    pub frozen: bool,
+}
+
+/// The state of the detectors about a client, see `Detectors::save`
+pub struct SavedClient {
+    score: Option<u32>,
+    detectors: Vec<Saved>,
+}
+
+/// The detectors, the scores of the clients and the flags raised so far
+#[derive(Clone, Default)]
+pub struct Detectors {
+    detectors: Vec<Box<dyn Detector>>,
+    scores: HashMap<Key, u32>,
+    flags: Vec<Flag>,
+    /// the first flag raised by the latest observation
+    observed: usize,
+}
+
+impl Detectors {
+    pub fn add(&mut self, detector: Box<dyn Detector>) {
+        self.detectors.push(detector);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.detectors.is_empty()
+    }
+
+    /// run the detectors on a transaction, returns the score of the client
+    /// if it was flagged
+    pub fn observe(&mut self, observation: &Observation) -> Option<u32> {
+        self.observed = self.flags.len();
+        let mut flagged = None;
+        for detector in &mut self.detectors {
+            let finding = match detector.observe(observation) {
+                Some(finding) => finding,
+                None => continue,
+            };
+            let total = self.scores.entry(key(observation.tx)).or_default();
+            *total = total.saturating_add(finding.score);
+            self.flags.push(Flag {
+                tenant: observation.tx.tenant.clone(),
+                client_id: observation.tx.client_id,
+                transaction_id: observation.tx.transaction_id,
+                detector: detector.name(),
+                score: finding.score,
+                total: *total,
+                reason: finding.reason,
+                at: observation.at,
+                frozen: false,
+            });
+            flagged = Some(*total);
+        }
+        flagged
+    }
+
+    /// mark the flag of the latest observation that brought the score of the
+    /// client to `threshold` as the one that froze the account
+    pub fn froze(&mut self, threshold: u32) {
+        let flag = self
+            .flags
+            .iter_mut()
+            .skip(self.observed)
+            .find(|flag| flag.total >= threshold);
+        if let Some(flag) = flag {
+            flag.frozen = true;
+        }
+    }
+
+    pub fn flags(&self) -> &[Flag] {
+        &self.flags
+    }
+
+    /// a copy of the state kept about a client
+    pub fn save(&self, tenant: &str, client_id: ClientId) -> SavedClient {
+        SavedClient {
+            score: self.scores.get(&(tenant.to_owned(), client_id)).copied(),
+            detectors: self
+                .detectors
+                .iter()
+                .map(|detector| detector.save(tenant, client_id))
+                .collect(),
+        }
+    }
+
+    pub fn restore(&mut self, tenant: &str, client_id: ClientId, saved: SavedClient) {
+        let key = (tenant.to_owned(), client_id);
+        match saved.score {
+            Some(score) => self.scores.insert(key, score),
+            None => self.scores.remove(&key),
+        };
+        for (detector, saved) in self.detectors.iter_mut().zip(saved.detectors) {
+            detector.restore(tenant, client_id, saved);
+        }
+    }
+
+    /// drop the flags raised after the first `len`
+    pub fn truncate_flags(&mut self, len: usize) {
+        self.flags.truncate(len);
+    }
+}
+
+/// put back an entry of a map saved as an option
+fn restore_entry<V: 'static>(map: &mut HashMap<Key, V>, key: Key, saved: Saved) {
+    if let Ok(saved) = saved.downcast::<Option<V>>() {
+        match *saved {
+            Some(value) => map.insert(key, value),
+            None => map.remove(&key),
+        };
+    }
+}
+
+/// The moments of the recent events of every client
+#[derive(Debug, Clone, Default)]
+struct Recent {
+    clients: HashMap<Key, VecDeque<Moment>>,
+}
+
+impl Recent {
+    /// add an event, returns the number of events of the client in the
+    /// window that ends with it
+    fn push(&mut self, key: Key, at: Moment, window: Window) -> usize {
+        let events = self.clients.entry(key).or_default();
+        while events
+            .front()
+            .is_some_and(|start| !window.contains(*start, at))
+        {
+            events.pop_front();
+        }
+        events.push_back(at);
+        events.len()
+    }
+
+    fn save(&self, key: &Key) -> Saved {
+        Box::new(self.clients.get(key).cloned())
+    }
+
+    fn restore(&mut self, key: Key, saved: Saved) {
+        restore_entry(&mut self.clients, key, saved);
+    }
+}
+
+/// A large deposit followed within the window by a withdrawal of at least
+/// the same amount
+#[derive(Debug, Clone)]
+pub struct DepositWithdrawal {
+    pub min_amount: Amount,
+    pub window: Window,
+    pub score: u32,
+    deposits: HashMap<Key, VecDeque<(Moment, Currency, Amount)>>,
+    /// the client of every large deposit in the order they were made, so the
+    /// deposits are dropped once out of the window even if the client never
+    /// withdraws
+    order: VecDeque<(Moment, Key)>,
+}
+
+impl DepositWithdrawal {
+    pub fn new(min_amount: Amount, window: Window, score: u32) -> Self {
+        DepositWithdrawal {
+            min_amount,
+            window,
+            score,
+            deposits: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// drop the deposits that are out of the window at `at`
+    fn prune(&mut self, at: Moment) {
+        let window = self.window;
+        while self
+            .order
+            .front()
+            .is_some_and(|(start, _)| !window.contains(*start, at))
+        {
+            let key = match self.order.pop_front() {
+                Some((_, key)) => key,
+                None => break,
+            };
+            if let Some(deposits) = self.deposits.get_mut(&key) {
+                deposits.retain(|(start, _, _)| window.contains(*start, at));
+                if deposits.is_empty() {
+                    self.deposits.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+impl Detector for DepositWithdrawal {
+    fn name(&self) -> &'static str {
+        "deposit_withdrawal"
+    }
+
+    fn observe(&mut self, observation: &Observation) -> Option<Finding> {
+        if !observation.accepted() {
+            return None;
+        }
+        self.prune(observation.at);
+        let tx = observation.tx;
+        match tx.operation {
+            TxOperation::Deposit(amount) if amount >= self.min_amount => {
+                let deposits = self.deposits.entry(key(tx)).or_default();
+                deposits.push_back((observation.at, tx.currency.clone(), amount));
+                self.order.push_back((observation.at, key(tx)));
+                None
+            }
+            TxOperation::Withdraw(amount) => {
+                let window = self.window;
+                let deposits = self.deposits.get_mut(&key(tx))?;
+                deposits.retain(|(start, _, _)| window.contains(*start, observation.at));
+                let index = deposits.iter().position(|(_, currency, deposit)| {
+                    *currency == tx.currency && *deposit <= amount
+                })?;
+                let (_, _, deposit) = deposits.remove(index)?;
+                if deposits.is_empty() {
+                    self.deposits.remove(&key(tx));
+                }
+                Some(Finding {
+                    score: self.score,
+                    reason: format!(
+                        "withdrawal of {} within {} of a deposit of {}",
+                        amount.normalize(),
+                        window,
+                        deposit.normalize()
+                    ),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn save(&self, tenant: &str, client_id: ClientId) -> Saved {
+        Box::new(self.deposits.get(&(tenant.to_owned(), client_id)).cloned())
+    }
+
+    fn restore(&mut self, tenant: &str, client_id: ClientId, saved: Saved) {
+        restore_entry(&mut self.deposits, (tenant.to_owned(), client_id), saved);
+    }
+
+    fn clone_box(&self) -> Box<dyn Detector> {
+        Box::new(self.clone())
+    }
+}
+
+/// Flags a client once it opened `count` disputes within the window
+#[derive(Debug, Clone)]
+pub struct RepeatedDisputes {
+    pub count: usize,
+    pub window: Window,
+    pub score: u32,
+    recent: Recent,
+}
+
+impl RepeatedDisputes {
+    pub fn new(count: usize, window: Window, score: u32) -> Self {
+        RepeatedDisputes {
+            count,
+            window,
+            score,
+            recent: Recent::default(),
+        }
+    }
+}
+
+impl Detector for RepeatedDisputes {
+    fn name(&self) -> &'static str {
+        "repeated_disputes"
+    }
+
+    fn observe(&mut self, observation: &Observation) -> Option<Finding> {
+        let tx = observation.tx;
+        if !observation.accepted() || tx.operation != TxOperation::Dispute(DisputeState::Initiated)
+        {
+            return None;
+        }
+        let count = self.recent.push(key(tx), observation.at, self.window);
+        (count == self.count).then(|| Finding {
+            score: self.score,
+            reason: format!("{} disputes within {}", count, self.window),
+        })
+    }
+
+    fn save(&self, tenant: &str, client_id: ClientId) -> Saved {
+        self.recent.save(&(tenant.to_owned(), client_id))
+    }
+
+    fn restore(&mut self, tenant: &str, client_id: ClientId, saved: Saved) {
+        self.recent.restore((tenant.to_owned(), client_id), saved);
+    }
+
+    fn clone_box(&self) -> Box<dyn Detector> {
+        Box::new(self.clone())
+    }
+}
+
+/// Flags a client once `count` of its withdrawals were refused for
+/// insufficient funds within the window
+#[derive(Debug, Clone)]
+pub struct FailedWithdrawals {
+    pub count: usize,
+    pub window: Window,
+    pub score: u32,
+    recent: Recent,
+}
+
+impl FailedWithdrawals {
+    pub fn new(count: usize, window: Window, score: u32) -> Self {
+        FailedWithdrawals {
+            count,
+            window,
+            score,
+            recent: Recent::default(),
+        }
+    }
+}
+
+impl Detector for FailedWithdrawals {
+    fn name(&self) -> &'static str {
+        "failed_withdrawals"
+    }
+
+    fn observe(&mut self, observation: &Observation) -> Option<Finding> {
+        if !matches!(observation.error, Some(TxError::InsufficientFunds(_))) {
+            return None;
+        }
+        let count = self
+            .recent
+            .push(key(observation.tx), observation.at, self.window);
+        (count == self.count).then(|| Finding {
+            score: self.score,
+            reason: format!(
+                "{} withdrawals refused for insufficient funds within {}",
+                count, self.window
+            ),
+        })
+    }
+
+    fn save(&self, tenant: &str, client_id: ClientId) -> Saved {
+        self.recent.save(&(tenant.to_owned(), client_id))
+    }
+
+    fn restore(&mut self, tenant: &str, client_id: ClientId, saved: Saved) {
+        self.recent.restore((tenant.to_owned(), client_id), saved);
+    }
+
+    fn clone_box(&self) -> Box<dyn Detector> {
+        Box::new(self.clone())
+    }
+}
+
+/// Flags a client once, when its disputes per deposit and withdrawal go
+/// over `max_rate` after at least `min_transactions` of them
+#[derive(Debug, Clone)]
+pub struct DisputeRate {
+    pub max_rate: Decimal,
+    pub min_transactions: u64,
+    pub score: u32,
+    /// the deposits and withdrawals, and the disputes of every client
+    counts: HashMap<Key, (u64, u64)>,
+    flagged: HashSet<Key>,
+}
+
+impl DisputeRate {
+    pub fn new(max_rate: Decimal, min_transactions: u64, score: u32) -> Self {
+        DisputeRate {
+            max_rate,
+            min_transactions,
+            score,
+            counts: HashMap::new(),
+            flagged: HashSet::new(),
+        }
+    }
+}
+
+impl Detector for DisputeRate {
+    fn name(&self) -> &'static str {
+        "dispute_rate"
+    }
+
+    fn observe(&mut self, observation: &Observation) -> Option<Finding> {
+        if !observation.accepted() {
+            return None;
+        }
+        let key = key(observation.tx);
+        if self.flagged.contains(&key) {
+            return None;
+        }
+        let (transactions, disputes) = self.counts.entry(key.clone()).or_default();
+        match observation.tx.operation {
+            TxOperation::Deposit(_) | TxOperation::Withdraw(_) => *transactions += 1,
+            TxOperation::Dispute(DisputeState::Initiated) => *disputes += 1,
+            TxOperation::Dispute(_) => return None,
+        }
+        if *transactions < self.min_transactions.max(1)
+            || Decimal::from(*disputes) / Decimal::from(*transactions) <= self.max_rate
+        {
+            return None;
+        }
+        let reason = format!("{} disputes for {} transactions", disputes, transactions);
+        self.flagged.insert(key);
+        Some(Finding {
+            score: self.score,
+            reason,
+        })
+    }
+
+    fn save(&self, tenant: &str, client_id: ClientId) -> Saved {
+        let key = (tenant.to_owned(), client_id);
+        Box::new((self.counts.get(&key).copied(), self.flagged.contains(&key)))
+    }
+
+    fn restore(&mut self, tenant: &str, client_id: ClientId, saved: Saved) {
+        let key = (tenant.to_owned(), client_id);
+        if let Ok(saved) = saved.downcast::<(Option<(u64, u64)>, bool)>() {
+            let (counts, flagged) = *saved;
+            match counts {
+                Some(counts) => self.counts.insert(key.clone(), counts),
+                None => self.counts.remove(&key),
+            };
+            if flagged {
+                self.flagged.insert(key);
+            } else {
+                self.flagged.remove(&key);
+            }
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Detector> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::model::{
+        account::TxError, time::Window, Amount, DisputeState, Moment, Tx, TxOperation,
+    };
+
+    use super::{
+        DepositWithdrawal, Detector, Detectors, DisputeRate, FailedWithdrawals, Observation,
+        RepeatedDisputes,
+    };
+
+    #[test]
+    fn test_detectors() {
+        let mut detectors = Detectors::default();
+        detectors.add(Box::new(DepositWithdrawal::new(
+            1000.into(),
+            Window::Seconds(3600),
+            50,
+        )));
+        detectors.add(Box::new(RepeatedDisputes::new(
+            2,
+            Window::Transactions(10),
+            20,
+        )));
+        detectors.add(Box::new(FailedWithdrawals::new(2, Window::Seconds(60), 10)));
+        detectors.add(Box::new(DisputeRate::new(Decimal::new(5, 1), 2, 30)));
+
+        let mut sequence = 0;
+        let mut observe = |client_id, operation, timestamp, error: Option<TxError>| {
+            let tx = Tx {
+                tenant: String::new(),
+                transaction_id: sequence.into(),
+                client_id,
+                currency: String::new(),
+                operation,
+                conversion: None,
+                timestamp: Some(timestamp),
+            };
+            let at = Moment {
+                sequence,
+                timestamp: Some(timestamp),
+            };
+            sequence += 1;
+            detectors.observe(&Observation {
+                tx: &tx,
+                at,
+                error: error.as_ref(),
+            })
+        };
+        let deposit = |amount: u32| TxOperation::Deposit(Amount::from(amount));
+        let withdraw = |amount: u32| TxOperation::Withdraw(Amount::from(amount));
+        let dispute = TxOperation::Dispute(DisputeState::Initiated);
+
+        // a late withdrawal or a small deposit are not flagged
+        assert_eq!(observe(1, deposit(2000), 0, None), None);
+        assert_eq!(observe(1, withdraw(2000), 4000, None), None);
+        assert_eq!(observe(1, deposit(500), 4000, None), None);
+        assert_eq!(observe(1, withdraw(500), 4100, None), None);
+        assert_eq!(observe(2, deposit(5000), 0, None), None);
+        assert_eq!(observe(2, withdraw(5000), 60, None), Some(50));
+
+        // the refusals count within a minute
+        let refused = || Some(TxError::InsufficientFunds(0.into()));
+        assert_eq!(observe(3, withdraw(10), 0, refused()), None);
+        assert_eq!(observe(3, withdraw(10), 100, refused()), None);
+        assert_eq!(observe(3, withdraw(10), 130, refused()), Some(10));
+
+        // the second dispute raises two flags, the rate is over a half
+        assert_eq!(observe(4, deposit(1), 0, None), None);
+        assert_eq!(observe(4, deposit(1), 0, None), None);
+        assert_eq!(observe(4, dispute, 0, None), None);
+        assert_eq!(observe(4, dispute, 0, None), Some(50));
+        assert_eq!(observe(4, dispute, 0, None), None);
+
+        let flags = detectors.flags();
+        assert_eq!(flags.len(), 4);
+        assert_eq!(
+            flags[0].reason,
+            "withdrawal of 5000 within 1h of a deposit of 5000"
+        );
+        assert_eq!(flags[3].detector, "dispute_rate");
+        assert_eq!(flags[3].reason, "2 disputes for 2 transactions");
+        assert_eq!(flags[3].total, 50);
+    }
+
+    #[test]
+    fn test_deposit_withdrawal() {
+        let observe = |detector: &mut DepositWithdrawal, client_id, operation, sequence: u64| {
+            let tx = Tx {
+                tenant: String::new(),
+                transaction_id: sequence.into(),
+                client_id,
+                currency: String::new(),
+                operation,
+                conversion: None,
+                timestamp: None,
+            };
+            let at = Moment {
+                sequence,
+                timestamp: None,
+            };
+            detector.observe(&Observation {
+                tx: &tx,
+                at,
+                error: None,
+            })
+        };
+        let deposit = |amount: u32| TxOperation::Deposit(Amount::from(amount));
+        let withdraw = |amount: u32| TxOperation::Withdraw(Amount::from(amount));
+
+        let mut detector = DepositWithdrawal::new(1000.into(), Window::Transactions(2), 50);
+        assert_eq!(observe(&mut detector, 1, deposit(2000), 0), None);
+        assert_eq!(observe(&mut detector, 2, deposit(2000), 1), None);
+        assert!(observe(&mut detector, 2, withdraw(2000), 2).is_some());
+        // the deposits of the clients that don't withdraw are dropped too
+        assert_eq!(observe(&mut detector, 3, deposit(10), 3), None);
+        assert!(detector.deposits.is_empty());
+        // the withdrawn deposit leaves the order once out of the window
+        assert_eq!(detector.order.len(), 1);
+        assert_eq!(observe(&mut detector, 3, deposit(10), 4), None);
+        assert!(detector.order.is_empty());
+    }
+}
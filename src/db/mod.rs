@@ -1,48 +1,295 @@
-use std::marker::PhantomData;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    marker::PhantomData,
+    sync::Arc,
+};
 
 use thiserror::Error;
 mod accounts;
+pub mod fraud;
+pub mod rules;
 mod transactions;
 
+pub use accounts::AccountsIter;
+use fraud::{Detectors, Flag, Observation, SavedClient};
+use rules::{Activity, RuleSet, SavedEntries};
+
 use super::model::{
-    account::{Account, TxError},
-    ClientId, TransactionId, Tx, TxRecord,
+    account::{Account, DisputePolicy, TxError},
+    ClientId, Currency, DisputeState, Moment, TenantId, Timestamp, TransactionId, Tx, TxOperation,
+    TxRecord,
 };
 
-/// Stores and process accounts and transactions
-pub struct TransactionDB<'a, T: TransactionStore, A: AccountStore<'a>> {
+/// Settings of the transaction processing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub dispute: DisputePolicy,
+    /// maximum number of accounts, transactions of new clients are refused above it
+    pub max_accounts: Option<usize>,
+    /// seconds a timestamp may be before the latest one seen, older
+    /// transactions are refused, no check when not set
+    pub time_tolerance: Option<Timestamp>,
+    /// freeze an account once the scores of its flags add up to this
+    pub freeze_score: Option<u32>,
+}
+
+/// A dispute that was resolved automatically after `DisputePolicy::expire_after`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpiredDispute {
+    pub tenant: TenantId,
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub opened: Moment,
+    pub expired: Moment,
+}
+
+/// The database kept in memory
+pub type MemoryDB<'a> =
+    TransactionDB<'a, HashMap<TransactionId, TxRecord>, HashMap<ClientId, Account>>;
+
+/// The state a change of the database replaced, see `TransactionDB::record`
+pub struct Change {
+    /// the accounts and transactions before they were first touched, `None`
+    /// for the ones the change created
+    accounts: HashMap<(TenantId, ClientId), Option<Account>>,
+    transactions: HashMap<(TenantId, TransactionId), Option<TxRecord>>,
+    /// the tenants the change created
+    tenants: Vec<TenantId>,
+    latest: Option<Timestamp>,
+    sequence: u64,
+    expiring: HashMap<(i64, u64), Option<ExpiredDispute>>,
+    expired: usize,
+    flags: usize,
+    activity: HashMap<(TenantId, ClientId), SavedEntries>,
+    detectors: HashMap<(TenantId, ClientId), SavedClient>,
+}
+
+impl Change {
+    /// the accounts the change touched, as they were before it
+    pub fn accounts(&self) -> impl Iterator<Item = (&str, ClientId, Option<&Account>)> {
+        self.accounts
+            .iter()
+            .map(|((tenant, client_id), account)| (tenant.as_str(), *client_id, account.as_ref()))
+    }
+}
+
+/// The change being recorded, a copy of the database records nothing
+#[derive(Default)]
+struct Journal(Option<Change>);
+
+impl Clone for Journal {
+    fn clone(&self) -> Self {
+        Journal(None)
+    }
+}
+
+/// The accounts and transactions of a single tenant
+#[derive(Clone)]
+struct Stores<T, A> {
     accounts: A,
     transactions: T,
+}
+
+/// Stores and process accounts and transactions, every tenant has its own
+/// stores so a transaction can only refer to transactions of its tenant
+#[derive(Clone)]
+pub struct TransactionDB<'a, T: TransactionStore, A: AccountStore<'a>> {
+    /// the stores passed to `new` belong to the empty tenant, the stores of the
+    /// other tenants are created when their first transaction is added
+    tenants: BTreeMap<TenantId, Stores<T, A>>,
+    config: EngineConfig,
+    /// the latest timestamp of the accepted transactions
+    latest: Option<Timestamp>,
+    /// the number of accepted transactions
+    sequence: u64,
+    /// the open disputes that expire, by deadline and the sequence they were
+    /// opened at, they may have been resolved since
+    expiring: BTreeMap<(i64, u64), ExpiredDispute>,
+    expired: Vec<ExpiredDispute>,
+    rules: Arc<RuleSet>,
+    /// the recent deposits and withdrawals the rules are checked against
+    activity: Activity,
+    detectors: Detectors,
+    journal: Journal,
     //we need 'a captured by one of the fields here
     _phantom_data: PhantomData<&'a ()>,
 }
 
 impl<'a, T: TransactionStore, A: AccountStore<'a>> TransactionDB<'a, T, A> {
     pub fn new(transaction_store: T, account_store: A) -> Self {
-        TransactionDB {
+        let stores = Stores {
             accounts: account_store,
             transactions: transaction_store,
+        };
+        TransactionDB {
+            tenants: vec![(TenantId::new(), stores)].into_iter().collect(),
+            config: EngineConfig::default(),
+            latest: None,
+            sequence: 0,
+            expiring: BTreeMap::new(),
+            expired: Vec::new(),
+            rules: Arc::default(),
+            activity: Activity::default(),
+            detectors: Detectors::default(),
+            journal: Journal::default(),
             _phantom_data: PhantomData,
         }
     }
 
-    pub fn accounts(&'a self) -> A::IteratorType {
-        self.accounts.accounts()
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// the limits checked before a deposit or a withdrawal is processed
+    pub fn with_rules(mut self, rules: RuleSet) -> Self {
+        self.rules = Arc::new(rules);
+        self
+    }
+
+    /// the heuristics that flag suspicious clients
+    pub fn with_detectors(mut self, detectors: Detectors) -> Self {
+        self.detectors = detectors;
+        self
+    }
+
+    /// the accounts of all the tenants, ordered by tenant
+    pub fn accounts(&'a self) -> impl Iterator<Item = &'a Account> + 'a {
+        self.tenants
+            .values()
+            .flat_map(|stores| stores.accounts.accounts())
+    }
+
+    /// the tenants that have accounts, in order
+    pub fn tenants(&self) -> impl Iterator<Item = &str> + '_ {
+        self.tenants
+            .iter()
+            .filter(|(_, stores)| stores.accounts.account_count() > 0)
+            .map(|(tenant, _)| tenant.as_str())
+    }
+
+    /// an account of the empty tenant
+    pub fn account(&self, client_id: &ClientId) -> Option<&Account> {
+        self.tenant_account("", client_id)
+    }
+
+    pub fn tenant_account(&self, tenant: &str, client_id: &ClientId) -> Option<&Account> {
+        self.tenants.get(tenant)?.accounts.get_account(client_id)
+    }
+
+    /// a transaction of the empty tenant
+    pub fn transaction(&self, id: &TransactionId) -> Option<&TxRecord> {
+        self.tenant_transaction("", id)
+    }
+
+    pub fn tenant_transaction(&self, tenant: &str, id: &TransactionId) -> Option<&TxRecord> {
+        self.tenants.get(tenant)?.transactions.get_tx(id)
+    }
+
+    /// the disputes that were resolved automatically, in the order they expired
+    pub fn expired_disputes(&self) -> &[ExpiredDispute] {
+        &self.expired
+    }
+
+    /// the flags raised by the detectors, in order
+    pub fn flags(&self) -> &[Flag] {
+        self.detectors.flags()
+    }
+
+    /// run `f` and return what it changed, the change can be undone with
+    /// `revert`
+    pub fn record<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> (R, Change) {
+        self.journal = Journal(Some(Change {
+            accounts: HashMap::new(),
+            transactions: HashMap::new(),
+            tenants: Vec::new(),
+            latest: self.latest,
+            sequence: self.sequence,
+            expiring: HashMap::new(),
+            expired: self.expired.len(),
+            flags: self.detectors.flags().len(),
+            activity: HashMap::new(),
+            detectors: HashMap::new(),
+        }));
+        let res = f(self);
+        let change = self.journal.0.take().expect("the change is recorded");
+        (res, change)
+    }
+
+    /// keep the account and the transaction as they are before they change
+    fn save(&mut self, tenant: &str, client_id: ClientId, transaction_id: Option<TransactionId>) {
+        let change = match &mut self.journal.0 {
+            Some(change) => change,
+            None => return,
+        };
+        let stores = self.tenants.get(tenant);
+        change
+            .accounts
+            .entry((tenant.to_owned(), client_id))
+            .or_insert_with(|| stores?.accounts.get_account(&client_id).cloned());
+        if let Some(transaction_id) = transaction_id {
+            change
+                .transactions
+                .entry((tenant.to_owned(), transaction_id))
+                .or_insert_with(|| stores?.transactions.get_tx(&transaction_id).cloned());
+        }
+    }
+
+    /// keep what the rules and the detectors know about a client
+    fn save_client(&mut self, tenant: &str, client_id: ClientId) {
+        let change = match &mut self.journal.0 {
+            Some(change) => change,
+            None => return,
+        };
+        let key = (tenant.to_owned(), client_id);
+        let (activity, detectors) = (&self.activity, &self.detectors);
+        change
+            .activity
+            .entry(key.clone())
+            .or_insert_with(|| activity.save(tenant, client_id));
+        change
+            .detectors
+            .entry(key)
+            .or_insert_with(|| detectors.save(tenant, client_id));
+    }
+
+    /// keep an expiring dispute as it is before it is added or removed
+    fn save_expiring(&mut self, key: (i64, u64)) {
+        if let Some(change) = &mut self.journal.0 {
+            let expiring = &self.expiring;
+            change
+                .expiring
+                .entry(key)
+                .or_insert_with(|| expiring.get(&key).cloned());
+        }
+    }
+
+    fn account_count(&self) -> usize {
+        self.tenants
+            .values()
+            .map(|stores| stores.accounts.account_count())
+            .sum()
     }
 }
 
-/// Simple trait for working with accounts
-pub trait AccountStore<'a> {
-    type IteratorType: 'a;
+/// Simple trait for working with accounts, a new tenant starts with the
+/// default store, which must be empty. `Default` is required since 0.2
+pub trait AccountStore<'a>: Default {
+    type IteratorType: IntoIterator<Item = &'a Account> + 'a;
+    fn get_account(&self, client_id: &ClientId) -> Option<&Account>;
+    fn account_count(&self) -> usize;
     fn get_account_mut(&mut self, client_id: &ClientId) -> Option<&mut Account>;
     fn add_account(&mut self, client_id: ClientId, account: Account) -> &mut Account;
     fn accounts(&'a self) -> Self::IteratorType;
 }
 
-/// Simple trait for working with transactions
-pub trait TransactionStore {
+/// Simple trait for working with transactions, a new tenant starts with the
+/// default store, which must be empty. `Default` is required since 0.2
+pub trait TransactionStore: Default {
     fn add(&mut self, id: TransactionId, record: TxRecord) -> Result<(), TransactionStoreError>;
 
+    fn get_tx(&self, id: &TransactionId) -> Option<&TxRecord>;
+
     fn get_tx_mut(
         &mut self,
         client_id: &ClientId,
@@ -52,22 +299,319 @@ pub trait TransactionStore {
 
 impl<'a, T: TransactionStore, A: AccountStore<'a>> TransactionDB<'a, T, A> {
     pub fn add(&mut self, tx: Tx) -> Result<(), TxError> {
-        let account = match self.accounts.get_account_mut(&tx.client_id) {
+        let at = Moment {
+            sequence: self.sequence,
+            timestamp: tx.timestamp,
+        };
+        self.save_client(&tx.tenant, tx.client_id);
+        if self.detectors.is_empty() {
+            return self.apply(tx, at);
+        }
+        let observed = tx.clone();
+        let res = self.apply(tx, at);
+        let observation = Observation {
+            tx: &observed,
+            at,
+            error: res.as_ref().err(),
+        };
+        let score = self.detectors.observe(&observation);
+        if let (Some(score), Some(threshold)) = (score, self.config.freeze_score) {
+            self.save(&observed.tenant, observed.client_id, None);
+            let account = self
+                .tenants
+                .get_mut(&observed.tenant)
+                .and_then(|stores| stores.accounts.get_account_mut(&observed.client_id))
+                .filter(|account| score >= threshold && !account.is_locked());
+            if let Some(account) = account {
+                account.freeze();
+                self.detectors.froze(threshold);
+            }
+        }
+        res
+    }
+
+    fn apply(&mut self, tx: Tx, at: Moment) -> Result<(), TxError> {
+        if let (Some(timestamp), Some(latest), Some(tolerance)) =
+            (tx.timestamp, self.latest, self.config.time_tolerance)
+        {
+            if timestamp < latest.saturating_sub(tolerance) {
+                return Err(TxError::OutOfOrder { timestamp, latest });
+            }
+        }
+        // only the accepted transactions move the time forward, a rejected
+        // row with a wrong timestamp can't expire the disputes
+        self.expire_disputes(Moment {
+            timestamp: self.latest,
+            ..at
+        });
+        if !self.rules.is_empty() {
+            self.activity
+                .check(&self.rules, &tx, at)
+                .map_err(|rule| TxError::LimitExceeded { rule })?;
+        }
+        if self.tenant_account(&tx.tenant, &tx.client_id).is_none() {
+            if let Some(max) = self.config.max_accounts {
+                if self.account_count() >= max {
+                    return Err(TxError::AccountLimit(tx.client_id));
+                }
+            }
+        }
+        let policy = self.config.dispute;
+        let recorded = (!self.rules.is_empty()).then(|| tx.clone());
+        self.save(&tx.tenant, tx.client_id, Some(tx.transaction_id));
+        let stores = self.stores(&tx.tenant);
+        let account = match stores.accounts.get_account_mut(&tx.client_id) {
             Some(acc) => acc,
-            None => self
-                .accounts
-                .add_account(tx.client_id, Account::new(tx.client_id)),
+            None => stores.accounts.add_account(
+                tx.client_id,
+                Account::new(tx.client_id).in_tenant(tx.tenant.clone()),
+            ),
         };
 
-        account.process(tx, &mut self.transactions)?;
+        let opened = match (tx.operation, policy.expire_after) {
+            (TxOperation::Dispute(DisputeState::Initiated), Some(window)) => window
+                .deadline(at)
+                .map(|deadline| (deadline, tx.tenant.clone(), tx.client_id, tx.transaction_id)),
+            _ => None,
+        };
+        account.process_at(tx, &mut stores.transactions, &policy, at)?;
+        if let Some(tx) = recorded {
+            self.activity.record(&self.rules, &tx, at);
+        }
+        if let Some((deadline, tenant, client_id, transaction_id)) = opened {
+            let dispute = ExpiredDispute {
+                tenant,
+                client_id,
+                transaction_id,
+                opened: at,
+                expired: at,
+            };
+            self.save_expiring((deadline, at.sequence));
+            self.expiring.insert((deadline, at.sequence), dispute);
+        }
+        self.sequence += 1;
+        self.latest = self.latest.max(at.timestamp);
+        self.expire_disputes(Moment {
+            timestamp: self.latest,
+            ..at
+        });
         Ok(())
     }
+
+    /// resolve the disputes whose window is over at `now`
+    fn expire_disputes(&mut self, now: Moment) {
+        let policy = self.config.dispute;
+        let position = match policy.expire_after.and_then(|window| window.position(now)) {
+            Some(position) => position,
+            None => return,
+        };
+        while let Some(key) = self.expiring.keys().next().copied() {
+            if key.0 >= position {
+                break;
+            }
+            self.save_expiring(key);
+            let mut dispute = self.expiring.remove(&key).expect("the key was just found");
+            let resolve = Tx {
+                tenant: dispute.tenant.clone(),
+                transaction_id: dispute.transaction_id,
+                client_id: dispute.client_id,
+                currency: Currency::new(),
+                operation: TxOperation::Dispute(DisputeState::Resolved),
+                conversion: None,
+                timestamp: now.timestamp,
+            };
+            self.save(
+                &dispute.tenant,
+                dispute.client_id,
+                Some(dispute.transaction_id),
+            );
+            let stores = self.stores(&dispute.tenant);
+            // disputes that were closed in the meantime are left as they are,
+            // as are the ones of locked accounts
+            let resolved = match stores.accounts.get_account_mut(&dispute.client_id) {
+                Some(account) => account
+                    .process_at(resolve, &mut stores.transactions, &policy, now)
+                    .is_ok(),
+                None => false,
+            };
+            if resolved {
+                dispute.expired = now;
+                self.expired.push(dispute);
+            }
+        }
+    }
+
+    /// add an account restored from a snapshot, fails if the client already has one
+    pub fn restore_account(&mut self, account: Account) -> Result<(), TxError> {
+        let client_id = account.client();
+        if self.tenant_account(account.tenant(), &client_id).is_some() {
+            return Err(TxError::AccountExists(client_id));
+        }
+        let tenant = account.tenant().to_owned();
+        self.save(&tenant, client_id, None);
+        self.stores(&tenant)
+            .accounts
+            .add_account(client_id, account);
+        Ok(())
+    }
+
+    fn stores(&mut self, tenant: &str) -> &mut Stores<T, A> {
+        if !self.tenants.contains_key(tenant) {
+            let stores = Stores {
+                accounts: A::default(),
+                transactions: T::default(),
+            };
+            self.tenants.insert(tenant.to_owned(), stores);
+            if let Some(change) = &mut self.journal.0 {
+                change.tenants.push(tenant.to_owned());
+            }
+        }
+        self.tenants
+            .get_mut(tenant)
+            .expect("the tenant was just added")
+    }
+}
+
+impl MemoryDB<'_> {
+    /// undo a change returned by `record`, the changes recorded after it
+    /// must have been reverted first
+    pub fn revert(&mut self, change: Change) {
+        for ((tenant, client_id), account) in change.accounts {
+            let accounts = &mut self.stores(&tenant).accounts;
+            match account {
+                Some(account) => accounts.insert(client_id, account),
+                None => accounts.remove(&client_id),
+            };
+        }
+        for ((tenant, transaction_id), record) in change.transactions {
+            let transactions = &mut self.stores(&tenant).transactions;
+            match record {
+                Some(record) => transactions.insert(transaction_id, record),
+                None => transactions.remove(&transaction_id),
+            };
+        }
+        for tenant in change.tenants {
+            self.tenants.remove(&tenant);
+        }
+        for (key, dispute) in change.expiring {
+            match dispute {
+                Some(dispute) => self.expiring.insert(key, dispute),
+                None => self.expiring.remove(&key),
+            };
+        }
+        for ((tenant, client_id), saved) in change.activity {
+            self.activity.restore(&tenant, client_id, saved);
+        }
+        for ((tenant, client_id), saved) in change.detectors {
+            self.detectors.restore(&tenant, client_id, saved);
+        }
+        self.latest = change.latest;
+        self.sequence = change.sequence;
+        self.expired.truncate(change.expired);
+        self.detectors.truncate_flags(change.flags);
+    }
+
+    /// undo a rejected transaction except what the detectors made of it, as
+    /// they see the rejected rows of an input too: their state and the
+    /// accounts they froze are kept, the returned change undoes them
+    pub fn revert_rejected(&mut self, mut change: Change) -> Change {
+        let frozen: HashSet<(TenantId, ClientId)> = self.detectors.flags()[change.flags..]
+            .iter()
+            .filter(|flag| flag.frozen)
+            .map(|flag| (flag.tenant.clone(), flag.client_id))
+            .collect();
+        let mut kept = Change {
+            accounts: HashMap::new(),
+            transactions: HashMap::new(),
+            tenants: Vec::new(),
+            latest: change.latest,
+            sequence: change.sequence,
+            expiring: HashMap::new(),
+            expired: change.expired,
+            flags: change.flags,
+            activity: HashMap::new(),
+            detectors: std::mem::take(&mut change.detectors),
+        };
+        for key in frozen {
+            if let Some(index) = change.tenants.iter().position(|tenant| *tenant == key.0) {
+                kept.tenants.push(change.tenants.remove(index));
+            }
+            if let Some(account) = change.accounts.remove(&key) {
+                kept.accounts.insert(key, account);
+            }
+        }
+        change.flags = self.detectors.flags().len();
+        self.revert(change);
+        kept
+    }
 }
 
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum TransactionStoreError {
     #[error("client mismatch: {0:?} != {1:?}")]
     ClientMismatch(ClientId, ClientId),
-    #[error("transaction already exists({0:?})")]
+    #[error("transaction already exists({0})")]
     TransactionAlreadyExists(TransactionId),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::model::{account::TxError, Amount, ClientId, TenantId, Tx, TxOperation};
+
+    use super::{EngineConfig, MemoryDB};
+
+    fn deposit(tenant: &str, transaction_id: u64, client_id: ClientId, amount: i64) -> Tx {
+        Tx {
+            tenant: TenantId::from(tenant),
+            transaction_id: transaction_id.into(),
+            client_id,
+            currency: "USD".into(),
+            operation: TxOperation::Deposit(Amount::from(amount)),
+            conversion: None,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_memory_db() {
+        let mut db = MemoryDB::new(HashMap::new(), HashMap::new()).with_config(EngineConfig {
+            max_accounts: Some(2),
+            ..Default::default()
+        });
+        db.add(deposit("b", 1, 1, 5)).unwrap();
+        // the ids of the transactions are scoped by tenant
+        db.add(deposit("", 1, 2, 10)).unwrap();
+        assert_eq!(db.add(deposit("", 2, 3, 1)), Err(TxError::AccountLimit(3)));
+
+        let accounts: Vec<_> = db
+            .accounts()
+            .map(|a| (a.tenant().to_owned(), a.client(), a.balance("USD")))
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![
+                (String::new(), 2, Amount::from(10)),
+                ("b".to_owned(), 1, Amount::from(5))
+            ]
+        );
+        assert_eq!(db.tenants().collect::<Vec<_>>(), vec!["", "b"]);
+        assert_eq!(db.account(&2).unwrap().balance("USD"), Amount::from(10));
+        assert!(db.account(&1).is_none());
+        assert_eq!(db.tenant_account("b", &1).unwrap().total("USD"), 5.into());
+        assert_eq!(db.transaction(&1.into()).unwrap().client_id, 2);
+        assert_eq!(db.tenant_transaction("b", &1.into()).unwrap().client_id, 1);
+        assert!(db.tenant_transaction("c", &1.into()).is_none());
+
+        let (result, change) = db.record(|db| db.add(deposit("c", 1, 1, 7)));
+        assert_eq!(result, Err(TxError::AccountLimit(1)));
+        db.revert(change);
+        let (result, change) = db.record(|db| db.add(deposit("b", 2, 1, 7)));
+        result.unwrap();
+        assert_eq!(db.tenant_account("b", &1).unwrap().total("USD"), 12.into());
+        db.revert(change);
+        assert_eq!(db.tenant_account("b", &1).unwrap().total("USD"), 5.into());
+        assert!(db.tenant_transaction("b", &2.into()).is_none());
+    }
+}
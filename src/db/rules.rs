@@ -0,0 +1,339 @@
+//! Limits on the deposits and withdrawals of the clients, checked before a
+//! transaction is processed.
+//!
+//! A rule applies to every client, to the clients of a tier or to a single
+//! client of a tenant, and the most specific rule of a name wins, so a tier
+//! or a client can get another limit than the global one. Counts and volumes are kept
+//! per tenant, client and currency over a window in seconds, which only
+//! counts transactions with a timestamp, or in accepted transactions.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    str::FromStr,
+};
+
+use crate::model::{time::Window, Amount, ClientId, Currency, Moment, TenantId, Tx, TxOperation};
+
+/// The transactions a rule applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    Deposit,
+    Withdrawal,
+}
+
+impl RuleKind {
+    fn of(operation: &TxOperation) -> Option<(RuleKind, Amount)> {
+        match operation {
+            TxOperation::Deposit(amount) => Some((RuleKind::Deposit, *amount)),
+            TxOperation::Withdraw(amount) => Some((RuleKind::Withdrawal, *amount)),
+            TxOperation::Dispute(_) => None,
+        }
+    }
+}
+
+impl FromStr for RuleKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(RuleKind::Deposit),
+            "withdrawal" => Ok(RuleKind::Withdrawal),
+            _ => Err(format!("unknown rule type: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// the largest single amount
+    Amount(Amount),
+    /// the most transactions in the window, the new one included
+    Count { count: u64, window: Window },
+    /// the largest sum of the amounts in the window, the new one included
+    Volume { amount: Amount, window: Window },
+}
+
+impl Limit {
+    fn window(&self) -> Option<Window> {
+        match self {
+            Limit::Amount(_) => None,
+            Limit::Count { window, .. } | Limit::Volume { window, .. } => Some(*window),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    /// reported when the rule refuses a transaction
+    pub name: String,
+    pub kind: RuleKind,
+    /// the currency the rule is limited to, every currency when not set
+    pub currency: Option<Currency>,
+    pub limit: Limit,
+}
+
+/// The clients a rule applies to
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Global,
+    Tier(String),
+    Client(TenantId, ClientId),
+}
+
+/// The configured rules and the tiers of the clients, a client of a tenant is
+/// in at most one tier
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<(Scope, Rule)>,
+    tiers: HashMap<(TenantId, ClientId), String>,
+}
+
+impl RuleSet {
+    pub fn add(&mut self, scope: Scope, rule: Rule) {
+        self.rules.push((scope, rule));
+    }
+
+    /// put the client of the tenant in a tier, returns the tier it was in
+    pub fn assign(
+        &mut self,
+        tenant: TenantId,
+        client_id: ClientId,
+        tier: String,
+    ) -> Option<String> {
+        self.tiers.insert((tenant, client_id), tier)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// the rules of the client, the most specific one of every name
+    fn rules_for(&self, tenant: &str, client_id: ClientId) -> impl Iterator<Item = &Rule> {
+        let tier = self.tiers.get(&(tenant.to_owned(), client_id));
+        let mut rules: BTreeMap<&str, (&Scope, &Rule)> = BTreeMap::new();
+        for (scope, rule) in &self.rules {
+            let applies = match scope {
+                Scope::Global => true,
+                Scope::Tier(name) => tier == Some(name),
+                Scope::Client(t, id) => t == tenant && *id == client_id,
+            };
+            if !applies {
+                continue;
+            }
+            match rules.get(rule.name.as_str()) {
+                Some((current, _)) if *current > scope => {}
+                _ => {
+                    rules.insert(&rule.name, (scope, rule));
+                }
+            }
+        }
+        rules.into_values().map(|(_, rule)| rule)
+    }
+}
+
+/// An accepted deposit or withdrawal, kept while it is in a window
+#[derive(Debug, Clone)]
+struct Entry {
+    at: Moment,
+    kind: RuleKind,
+    currency: Currency,
+    amount: Amount,
+}
+
+/// The entries of a client, see `Activity::save`
+#[derive(Debug)]
+pub struct SavedEntries(Option<VecDeque<Entry>>);
+
+/// The recent deposits and withdrawals of the clients
+#[derive(Debug, Clone, Default)]
+pub struct Activity {
+    clients: HashMap<(TenantId, ClientId), VecDeque<Entry>>,
+}
+
+impl Activity {
+    /// the name of the first rule the transaction breaks at `at`
+    pub fn check(&self, rules: &RuleSet, tx: &Tx, at: Moment) -> Result<(), String> {
+        let (kind, amount) = match RuleKind::of(&tx.operation) {
+            Some(operation) => operation,
+            None => return Ok(()),
+        };
+        let entries = self.clients.get(&(tx.tenant.clone(), tx.client_id));
+        for rule in rules.rules_for(&tx.tenant, tx.client_id) {
+            if rule.kind != kind || matches!(&rule.currency, Some(c) if *c != tx.currency) {
+                continue;
+            }
+            let recent = || {
+                entries.into_iter().flatten().filter(|entry| {
+                    entry.kind == kind
+                        && entry.currency == tx.currency
+                        && matches!(rule.limit.window(), Some(w) if w.contains(entry.at, at))
+                })
+            };
+            let exceeded = match rule.limit {
+                Limit::Amount(max) => amount > max,
+                Limit::Count { count, .. } => recent().count() as u64 + 1 > count,
+                Limit::Volume { amount: max, .. } => {
+                    recent().map(|entry| entry.amount).sum::<Amount>() + amount > max
+                }
+            };
+            if exceeded {
+                return Err(rule.name.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// remember an accepted transaction, dropping the entries of the client
+    /// that are out of every window
+    pub fn record(&mut self, rules: &RuleSet, tx: &Tx, at: Moment) {
+        let (kind, amount) = match RuleKind::of(&tx.operation) {
+            Some(operation) => operation,
+            None => return,
+        };
+        let windows: Vec<Window> = rules
+            .rules_for(&tx.tenant, tx.client_id)
+            .filter_map(|rule| rule.limit.window())
+            .collect();
+        if windows.is_empty() {
+            return;
+        }
+        let entries = self
+            .clients
+            .entry((tx.tenant.clone(), tx.client_id))
+            .or_default();
+        let expired = |entry: &Entry| !windows.iter().any(|w| w.contains(entry.at, at));
+        while entries.front().is_some_and(expired) {
+            entries.pop_front();
+        }
+        entries.push_back(Entry {
+            at,
+            kind,
+            currency: tx.currency.clone(),
+            amount,
+        });
+    }
+
+    /// a copy of the entries of a client
+    pub fn save(&self, tenant: &str, client_id: ClientId) -> SavedEntries {
+        SavedEntries(self.clients.get(&(tenant.to_owned(), client_id)).cloned())
+    }
+
+    pub fn restore(&mut self, tenant: &str, client_id: ClientId, saved: SavedEntries) {
+        let key = (tenant.to_owned(), client_id);
+        match saved.0 {
+            Some(entries) => self.clients.insert(key, entries),
+            None => self.clients.remove(&key),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{time::Window, Amount, ClientId, Moment, Tx, TxOperation};
+
+    use super::{Activity, Limit, Rule, RuleKind, RuleSet, Scope};
+
+    #[test]
+    fn test_rules() {
+        let rule = |name: &str, kind, limit| Rule {
+            name: name.to_owned(),
+            kind,
+            currency: None,
+            limit,
+        };
+        let mut rules = RuleSet::default();
+        rules.add(
+            Scope::Global,
+            rule(
+                "max_withdrawal",
+                RuleKind::Withdrawal,
+                Limit::Amount(100.into()),
+            ),
+        );
+        rules.add(
+            Scope::Tier("gold".into()),
+            rule(
+                "max_withdrawal",
+                RuleKind::Withdrawal,
+                Limit::Amount(1000.into()),
+            ),
+        );
+        rules.add(
+            Scope::Global,
+            rule(
+                "daily_withdrawals",
+                RuleKind::Withdrawal,
+                Limit::Count {
+                    count: 2,
+                    window: Window::Seconds(86400),
+                },
+            ),
+        );
+        rules.add(
+            Scope::Client(String::new(), 3),
+            rule(
+                "deposit_volume",
+                RuleKind::Deposit,
+                Limit::Volume {
+                    amount: 50.into(),
+                    window: Window::Transactions(2),
+                },
+            ),
+        );
+        rules.assign(String::new(), 2, "gold".into());
+
+        let tx = |client_id: ClientId, operation, timestamp| Tx {
+            tenant: String::new(),
+            transaction_id: 1.into(),
+            client_id,
+            currency: String::new(),
+            operation,
+            conversion: None,
+            timestamp,
+        };
+        let withdraw = |amount: u32| TxOperation::Withdraw(Amount::from(amount));
+        let deposit = |amount: u32| TxOperation::Deposit(Amount::from(amount));
+        let at = |sequence, timestamp| Moment {
+            sequence,
+            timestamp,
+        };
+        let mut activity = Activity::default();
+        let mut add = |tx: Tx, at: Moment| {
+            let res = activity.check(&rules, &tx, at);
+            if res.is_ok() {
+                activity.record(&rules, &tx, at);
+            }
+            res
+        };
+
+        // the tier raises the global limit
+        let res = add(tx(1, withdraw(500), Some(0)), at(0, Some(0)));
+        assert_eq!(res, Err("max_withdrawal".to_owned()));
+        assert!(add(tx(2, withdraw(500), Some(0)), at(0, Some(0))).is_ok());
+
+        // two withdrawals a day, the third one is refused until a day passed
+        assert!(add(tx(1, withdraw(10), Some(0)), at(1, Some(0))).is_ok());
+        assert!(add(tx(1, withdraw(10), Some(100)), at(2, Some(100))).is_ok());
+        let res = add(tx(1, withdraw(10), Some(200)), at(3, Some(200)));
+        assert_eq!(res, Err("daily_withdrawals".to_owned()));
+        assert!(add(tx(1, withdraw(10), Some(86401)), at(3, Some(86401))).is_ok());
+        // deposits and the windows in seconds don't count without timestamps
+        assert!(add(tx(1, deposit(1000), None), at(4, None)).is_ok());
+
+        // the volume only applies to client 3, over the last two transactions
+        assert!(add(tx(3, deposit(30), None), at(5, None)).is_ok());
+        let res = add(tx(3, deposit(30), None), at(6, None));
+        assert_eq!(res, Err("deposit_volume".to_owned()));
+        assert!(add(tx(3, deposit(30), None), at(8, None)).is_ok());
+
+        // the tier and the client rules don't apply to the other tenants
+        let other = |client_id, operation| Tx {
+            tenant: "b".into(),
+            ..tx(client_id, operation, None)
+        };
+        let res = add(other(2, withdraw(500)), at(9, None));
+        assert_eq!(res, Err("max_withdrawal".to_owned()));
+        assert!(add(other(3, deposit(30)), at(10, None)).is_ok());
+        assert!(add(other(3, deposit(30)), at(11, None)).is_ok());
+    }
+}
@@ -13,6 +13,10 @@ impl TransactionStore for HashMap<TransactionId, TxRecord> {
         Ok(())
     }
 
+    fn get_tx(&self, id: &TransactionId) -> Option<&TxRecord> {
+        self.get(id)
+    }
+
     fn get_tx_mut(
         &mut self,
         client_id: &ClientId,
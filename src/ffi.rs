@@ -0,0 +1,357 @@
+//! C interface of the engine, see `include/atm.h`.
+//!
+//! A database is not thread safe, every handle must be used by a single thread
+//! at a time. Amounts are passed and returned as decimal strings.
+
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use csv::StringRecord;
+
+use crate::{
+    db::{MemoryDB, TransactionDB, TransactionStoreError},
+    io::{mapping::Header, parse_row, ReadOptions, RejectReason},
+    model::{
+        account::TxError, input::ConversionError, input::COLUMNS, output::Record,
+        precision::Precision,
+    },
+};
+
+/// Size of the amount strings of `AtmAccount`, including the terminating nul
+pub const ATM_AMOUNT_LEN: usize = 48;
+
+/// Size of the currency code of `AtmAccount`, including the terminating nul
+pub const ATM_CURRENCY_LEN: usize = 4;
+
+/// Outcome of a call, the rejections mirror `TxError` and `ConversionError`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtmStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidUtf8 = 2,
+    /// unknown transaction type or invalid amount
+    Malformed = 3,
+    DepositWithoutAmount = 10,
+    WithdrawalWithoutAmount = 11,
+    DisputeWithAmount = 12,
+    TooManyDecimals = 13,
+    InvalidCurrency = 14,
+    NoRate = 15,
+    InvalidTimestamp = 16,
+    AccountLocked = 20,
+    InsufficientFunds = 21,
+    TransactionNotFound = 22,
+    InvalidState = 23,
+    DisputeNotAllowed = 24,
+    AccountLimit = 25,
+    AccountExists = 26,
+    ClientMismatch = 27,
+    TransactionExists = 28,
+    OutOfOrder = 29,
+    DisputeWindowClosed = 30,
+    LimitExceeded = 31,
+    /// an account invariant was violated, the database must not be used anymore
+    Invariant = 99,
+}
+
+impl From<&ConversionError> for AtmStatus {
+    fn from(e: &ConversionError) -> Self {
+        match e {
+            ConversionError::DepositWithoutAmount => AtmStatus::DepositWithoutAmount,
+            ConversionError::WithdrawalWithoutAmount => AtmStatus::WithdrawalWithoutAmount,
+            ConversionError::DisputeWithAmount => AtmStatus::DisputeWithAmount,
+            ConversionError::TooManyDecimals { .. } => AtmStatus::TooManyDecimals,
+            ConversionError::InvalidCurrency(_) => AtmStatus::InvalidCurrency,
+            ConversionError::NoRate { .. } => AtmStatus::NoRate,
+            ConversionError::InvalidTimestamp(_) => AtmStatus::InvalidTimestamp,
+        }
+    }
+}
+
+impl From<&TxError> for AtmStatus {
+    fn from(e: &TxError) -> Self {
+        match e {
+            TxError::AccountLocked(_) => AtmStatus::AccountLocked,
+            TxError::InsufficientFunds(_) => AtmStatus::InsufficientFunds,
+            TxError::TransactionNotFound(_) => AtmStatus::TransactionNotFound,
+            TxError::InvalidState(_, _) => AtmStatus::InvalidState,
+            TxError::DisputeNotAllowed(_) => AtmStatus::DisputeNotAllowed,
+            TxError::DisputeWindowClosed(_) => AtmStatus::DisputeWindowClosed,
+            TxError::LimitExceeded { .. } => AtmStatus::LimitExceeded,
+            TxError::AccountLimit(_) => AtmStatus::AccountLimit,
+            TxError::AccountExists(_) => AtmStatus::AccountExists,
+            TxError::OutOfOrder { .. } => AtmStatus::OutOfOrder,
+            TxError::IntegrityError(TransactionStoreError::ClientMismatch(..)) => {
+                AtmStatus::ClientMismatch
+            }
+            TxError::IntegrityError(TransactionStoreError::TransactionAlreadyExists(_)) => {
+                AtmStatus::TransactionExists
+            }
+        }
+    }
+}
+
+impl From<&RejectReason> for AtmStatus {
+    fn from(reason: &RejectReason) -> Self {
+        match reason {
+            RejectReason::Conversion(e) => e.into(),
+            RejectReason::Rejected(e) => e.into(),
+            _ => AtmStatus::Malformed,
+        }
+    }
+}
+
+/// A database kept in memory
+pub struct AtmDb {
+    db: MemoryDB<'static>,
+    options: ReadOptions,
+    header: Header,
+}
+
+/// The state of an account in a currency, the strings are nul terminated
+#[repr(C)]
+pub struct AtmAccount {
+    /// empty for the accounts without a tenant, valid until the next call to
+    /// `atm_accounts_next` or `atm_accounts_free`
+    pub tenant: *const c_char,
+    pub client: u32,
+    /// empty for the balance without a currency
+    pub currency: [c_char; ATM_CURRENCY_LEN],
+    pub available: [c_char; ATM_AMOUNT_LEN],
+    pub held: [c_char; ATM_AMOUNT_LEN],
+    pub total: [c_char; ATM_AMOUNT_LEN],
+    pub locked: bool,
+}
+
+/// A snapshot of the accounts taken by `atm_accounts_new`
+pub struct AtmAccounts {
+    records: std::vec::IntoIter<Record>,
+    /// the tenant of the last account
+    tenant: CString,
+}
+
+/// Creates an empty database, free it with `atm_db_free`
+#[no_mangle]
+pub extern "C" fn atm_db_new() -> *mut AtmDb {
+    let options = ReadOptions::default();
+    let header = options
+        .mapping
+        .header(&StringRecord::from(COLUMNS.to_vec()))
+        .expect("the default columns are mapped");
+    Box::into_raw(Box::new(AtmDb {
+        db: TransactionDB::new(HashMap::default(), HashMap::default()),
+        options,
+        header,
+    }))
+}
+
+/// Frees a database created by `atm_db_new`
+///
+/// # Safety
+/// `db` must be null or a pointer returned by `atm_db_new` that was not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn atm_db_free(db: *mut AtmDb) {
+    if !db.is_null() {
+        drop(Box::from_raw(db));
+    }
+}
+
+/// Applies a transaction, `kind` is one of the input types (`deposit`,
+/// `withdrawal`, `dispute`, `resolve`, `chargeback`), `amount` is a decimal
+/// string or null for the dispute types
+///
+/// # Safety
+/// `db` must come from `atm_db_new`, `kind` and `amount` must be null or nul
+/// terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn atm_db_submit(
+    db: *mut AtmDb,
+    client: u32,
+    tx: u64,
+    kind: *const c_char,
+    amount: *const c_char,
+) -> AtmStatus {
+    atm_db_submit_in(db, ptr::null(), ptr::null(), client, tx, kind, amount)
+}
+
+/// Applies a transaction of a tenant in a currency like `atm_db_submit`,
+/// `tenant` and `currency` may be null for none
+///
+/// # Safety
+/// `db` must come from `atm_db_new`, `tenant`, `currency`, `kind` and `amount`
+/// must be null or nul terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn atm_db_submit_in(
+    db: *mut AtmDb,
+    tenant: *const c_char,
+    currency: *const c_char,
+    client: u32,
+    tx: u64,
+    kind: *const c_char,
+    amount: *const c_char,
+) -> AtmStatus {
+    let db = match db.as_mut() {
+        Some(db) => db,
+        None => return AtmStatus::NullPointer,
+    };
+    if kind.is_null() {
+        return AtmStatus::NullPointer;
+    }
+    let kind = match CStr::from_ptr(kind).to_str() {
+        Ok(kind) => kind,
+        Err(_) => return AtmStatus::InvalidUtf8,
+    };
+    let (amount, tenant, currency) = match (
+        optional_str(amount),
+        optional_str(tenant),
+        optional_str(currency),
+    ) {
+        (Some(amount), Some(tenant), Some(currency)) => (amount, tenant, currency),
+        _ => return AtmStatus::InvalidUtf8,
+    };
+
+    // in the order of `COLUMNS`
+    let mut record = StringRecord::from(vec![
+        kind.to_owned(),
+        client.to_string(),
+        tx.to_string(),
+        amount.to_owned(),
+        tenant.to_owned(),
+        currency.to_owned(),
+    ]);
+    let tx = match parse_row(&mut record, &db.header, &db.options) {
+        Ok(tx) => tx,
+        Err(rejection) => return (&rejection.reason).into(),
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| db.db.add(tx))) {
+        Ok(Ok(())) => AtmStatus::Ok,
+        Ok(Err(e)) => (&e).into(),
+        Err(_) => AtmStatus::Invariant,
+    }
+}
+
+/// Takes a snapshot of the accounts, one per tenant, client and currency in
+/// that order, free it with `atm_accounts_free`
+///
+/// # Safety
+/// `db` must be null or come from `atm_db_new`
+#[no_mangle]
+pub unsafe extern "C" fn atm_accounts_new(db: *const AtmDb) -> *mut AtmAccounts {
+    let db = match db.as_ref() {
+        Some(db) => db,
+        None => return ptr::null_mut(),
+    };
+    let precision = Precision::default();
+    let mut records: Vec<Record> = db
+        .db
+        .accounts()
+        .flat_map(|account| Record::all(account, &precision))
+        .collect();
+    records.sort_by(|a, b| {
+        (&a.tenant, a.client_id, &a.currency).cmp(&(&b.tenant, b.client_id, &b.currency))
+    });
+    Box::into_raw(Box::new(AtmAccounts {
+        records: records.into_iter(),
+        tenant: CString::default(),
+    }))
+}
+
+/// Fills `account` with the next account, returns false at the end
+///
+/// # Safety
+/// `accounts` must come from `atm_accounts_new`, `account` must point to a
+/// writable `AtmAccount`
+#[no_mangle]
+pub unsafe extern "C" fn atm_accounts_next(
+    accounts: *mut AtmAccounts,
+    account: *mut AtmAccount,
+) -> bool {
+    let (accounts, account) = match (accounts.as_mut(), account.as_mut()) {
+        (Some(accounts), Some(account)) => (accounts, account),
+        _ => return false,
+    };
+    let record = match accounts.records.next() {
+        Some(record) => record,
+        None => return false,
+    };
+    // tenants come from C strings, they have no nul
+    accounts.tenant = CString::new(record.tenant.unwrap_or_default()).unwrap_or_default();
+    account.tenant = accounts.tenant.as_ptr();
+    account.client = record.client_id;
+    copy_str(
+        record.currency.as_deref().unwrap_or_default(),
+        &mut account.currency,
+    );
+    copy_str(&record.balance.to_string(), &mut account.available);
+    copy_str(&record.held.to_string(), &mut account.held);
+    copy_str(&record.total.to_string(), &mut account.total);
+    account.locked = record.locked;
+    true
+}
+
+/// Frees a snapshot created by `atm_accounts_new`
+///
+/// # Safety
+/// `accounts` must be null or come from `atm_accounts_new` and not be freed yet
+#[no_mangle]
+pub unsafe extern "C" fn atm_accounts_free(accounts: *mut AtmAccounts) {
+    if !accounts.is_null() {
+        drop(Box::from_raw(accounts));
+    }
+}
+
+/// A static description of the status
+#[no_mangle]
+pub extern "C" fn atm_status_message(status: AtmStatus) -> *const c_char {
+    let message: &'static [u8] = match status {
+        AtmStatus::Ok => b"ok\0",
+        AtmStatus::NullPointer => b"null pointer\0",
+        AtmStatus::InvalidUtf8 => b"invalid utf-8\0",
+        AtmStatus::Malformed => b"malformed transaction\0",
+        AtmStatus::DepositWithoutAmount => b"deposit without amount\0",
+        AtmStatus::WithdrawalWithoutAmount => b"withdrawal without amount\0",
+        AtmStatus::DisputeWithAmount => b"dispute with amount\0",
+        AtmStatus::TooManyDecimals => b"too many decimal places\0",
+        AtmStatus::InvalidCurrency => b"invalid currency code\0",
+        AtmStatus::NoRate => b"no exchange rate\0",
+        AtmStatus::InvalidTimestamp => b"invalid timestamp\0",
+        AtmStatus::AccountLocked => b"account locked\0",
+        AtmStatus::InsufficientFunds => b"insufficient funds\0",
+        AtmStatus::TransactionNotFound => b"transaction not found\0",
+        AtmStatus::InvalidState => b"invalid dispute state\0",
+        AtmStatus::DisputeNotAllowed => b"dispute not allowed\0",
+        AtmStatus::AccountLimit => b"account limit reached\0",
+        AtmStatus::AccountExists => b"account already exists\0",
+        AtmStatus::ClientMismatch => b"transaction belongs to another client\0",
+        AtmStatus::TransactionExists => b"transaction already exists\0",
+        AtmStatus::OutOfOrder => b"timestamp out of order\0",
+        AtmStatus::DisputeWindowClosed => b"dispute window closed\0",
+        AtmStatus::LimitExceeded => b"limit exceeded\0",
+        AtmStatus::Invariant => b"account invariant violated\0",
+    };
+    message.as_ptr() as *const c_char
+}
+
+/// an empty string for null, none if it isn't utf-8
+unsafe fn optional_str<'s>(s: *const c_char) -> Option<&'s str> {
+    if s.is_null() {
+        Some("")
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+/// copy as a nul terminated string, amounts and currency codes always fit
+fn copy_str(s: &str, out: &mut [c_char]) {
+    let bytes = &s.as_bytes()[..s.len().min(out.len() - 1)];
+    for (out, byte) in out.iter_mut().zip(bytes) {
+        *out = *byte as c_char;
+    }
+    out[bytes.len()] = 0;
+}
@@ -0,0 +1,281 @@
+//! Conversion of deposits and withdrawals into the currency of the accounts,
+//! with the rates of a local csv table.
+//!
+//! The table has the columns `from,to,rate,effective`: an amount in `from` is
+//! multiplied by `rate` to get the amount in `to`, starting at the `effective`
+//! time (RFC 3339 or seconds since the unix epoch, empty for a rate that always
+//! applies).
+//! A pair that is missing is looked up in the other direction with the inverse
+//! rate.
+
+use std::collections::BTreeMap;
+
+use csv::{ReaderBuilder, StringRecord, Trim};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::model::{
+    input::{parse_currency, ConversionError},
+    precision::Precision,
+    time::parse_timestamp,
+    Conversion, Currency, Timestamp, Tx, TxOperation,
+};
+
+#[derive(Debug, Error)]
+pub enum FxError {
+    #[error("can't read rates: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("line {line}: {reason}")]
+    Invalid { line: u64, reason: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct RateRow {
+    from: String,
+    to: String,
+    rate: Decimal,
+    effective: Option<String>,
+}
+
+/// Exchange rates by currency pair, every pair keeps its rates ordered by the
+/// time they become effective
+#[derive(Debug, Clone, Default)]
+pub struct RateTable {
+    rates: BTreeMap<Currency, BTreeMap<Currency, Vec<(Timestamp, Decimal)>>>,
+}
+
+impl RateTable {
+    /// read the rates from csv
+    pub fn read(reader: impl std::io::Read) -> Result<Self, FxError> {
+        let mut table = RateTable::default();
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+        let headers = reader.headers()?.clone();
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record)? {
+            let row: RateRow = record.deserialize(Some(&headers))?;
+            let line = record.position().map_or(0, |p| p.line());
+            let invalid = |reason: String| FxError::Invalid { line, reason };
+            let from = parse_currency(&row.from).map_err(|e| invalid(e.to_string()))?;
+            let to = parse_currency(&row.to).map_err(|e| invalid(e.to_string()))?;
+            if from.is_empty() || to.is_empty() {
+                return Err(invalid("missing currency".into()));
+            }
+            if row.rate <= Decimal::ZERO {
+                return Err(invalid(format!("rate must be positive: {}", row.rate)));
+            }
+            let effective = match row.effective.as_deref() {
+                Some(effective) => {
+                    parse_timestamp(effective).map_err(|e| invalid(e.to_string()))?
+                }
+                None => Timestamp::MIN,
+            };
+            table.insert(from, to, effective, row.rate);
+        }
+        Ok(table)
+    }
+
+    /// add a rate, it replaces the one of the pair with the same effective time
+    pub fn insert(&mut self, from: Currency, to: Currency, effective: Timestamp, rate: Decimal) {
+        let rates = self.rates.entry(from).or_default().entry(to).or_default();
+        match rates.binary_search_by_key(&effective, |(time, _)| *time) {
+            Ok(i) => rates[i].1 = rate,
+            Err(i) => rates.insert(i, (effective, rate)),
+        }
+    }
+
+    /// the rate effective at the time, or the latest one when the time is not
+    /// known
+    pub fn rate(&self, from: &str, to: &str, at: Option<Timestamp>) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+        let effective = |rates: &Vec<(Timestamp, Decimal)>| match at {
+            Some(at) => rates
+                .iter()
+                .rev()
+                .find(|(time, _)| *time <= at)
+                .map(|(_, rate)| *rate),
+            None => rates.last().map(|(_, rate)| *rate),
+        };
+        let pair = |from: &str, to: &str| self.rates.get(from)?.get(to);
+        match pair(from, to).and_then(effective) {
+            Some(rate) => Some(rate),
+            None => pair(to, from)
+                .and_then(effective)
+                .map(|rate| Decimal::ONE / rate),
+        }
+    }
+}
+
+/// Converts the amounts of deposits and withdrawals into a single currency
+#[derive(Debug, Clone)]
+pub struct Converter {
+    currency: Currency,
+    rates: RateTable,
+}
+
+impl Converter {
+    pub fn new(currency: Currency, rates: RateTable) -> Self {
+        Converter { currency, rates }
+    }
+
+    /// the currency of the accounts
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// convert the amount of the transaction into the currency of the accounts
+    /// and round it to the precision, transactions without a currency are taken
+    /// as already in it
+    pub fn convert(
+        &self,
+        mut tx: Tx,
+        at: Option<Timestamp>,
+        precision: &Precision,
+    ) -> Result<Tx, ConversionError> {
+        if tx.currency.is_empty() || tx.currency == self.currency {
+            tx.currency.clone_from(&self.currency);
+            return Ok(tx);
+        }
+        let amount = match tx.operation {
+            TxOperation::Deposit(amount) | TxOperation::Withdraw(amount) => amount,
+            // disputes are in the currency of the disputed transaction
+            TxOperation::Dispute(_) => return Ok(tx),
+        };
+        let rate = self
+            .rates
+            .rate(&tx.currency, &self.currency, at)
+            .ok_or_else(|| ConversionError::NoRate {
+                from: tx.currency.clone(),
+                to: self.currency.clone(),
+            })?;
+        let unrounded = amount * rate;
+        tx.conversion = Some(Conversion {
+            currency: std::mem::replace(&mut tx.currency, self.currency.clone()),
+            amount,
+            rate,
+            unrounded,
+        });
+        if let TxOperation::Deposit(amount) | TxOperation::Withdraw(amount) = &mut tx.operation {
+            *amount = precision.round(unrounded);
+        }
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use rust_decimal::Decimal;
+
+    use crate::{
+        db::TransactionDB,
+        io::{read_csv_data, ReadOptions},
+        model::{
+            input::ConversionError,
+            precision::{Precision, Rounding},
+            Amount, Conversion, Tx, TxOperation,
+        },
+    };
+
+    use super::{Converter, RateTable};
+
+    #[test]
+    fn test_conversion() {
+        let rates = RateTable::read(
+            "from,to,rate,effective
+EUR,USD,1.1,
+eur,usd,1.2,1970-01-01T00:16:40Z
+GBP,USD,1.25,2000
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let dec = |s: &str| s.parse::<Decimal>().unwrap();
+        assert_eq!(rates.rate("EUR", "USD", Some(999)), Some(dec("1.1")));
+        assert_eq!(rates.rate("EUR", "USD", Some(1000)), Some(dec("1.2")));
+        assert_eq!(rates.rate("EUR", "USD", None), Some(dec("1.2")));
+        assert_eq!(rates.rate("USD", "GBP", Some(2000)), Some(dec("0.8")));
+        assert_eq!(rates.rate("GBP", "USD", Some(1999)), None);
+        assert_eq!(rates.rate("GBP", "EUR", None), None);
+        assert!(RateTable::read("from,to,rate,effective\nEUR,USD,0,\n".as_bytes()).is_err());
+
+        let converter = Converter::new("USD".into(), rates);
+        let tx = |currency: &str| Tx {
+            tenant: String::new(),
+            transaction_id: 1.into(),
+            client_id: 1,
+            currency: currency.into(),
+            operation: TxOperation::Deposit(Amount::from(10)),
+            conversion: None,
+            timestamp: None,
+        };
+        let precision = Precision::default();
+        let converted = converter
+            .convert(tx("EUR"), Some(1500), &precision)
+            .unwrap();
+        assert_eq!(converted.currency, "USD");
+        assert_eq!(converted.operation, TxOperation::Deposit(Amount::from(12)));
+        assert_eq!(
+            converted.conversion,
+            Some(Conversion {
+                currency: "EUR".into(),
+                amount: Amount::from(10),
+                rate: dec("1.2"),
+                unrounded: dec("12.0"),
+            })
+        );
+        assert_eq!(
+            converter
+                .convert(tx(""), None, &precision)
+                .unwrap()
+                .currency,
+            "USD"
+        );
+        assert_eq!(
+            converter.convert(tx("JPY"), None, &precision),
+            Err(ConversionError::NoRate {
+                from: "JPY".into(),
+                to: "USD".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_converted() {
+        let rates = RateTable::read("from,to,rate,effective\nEUR,USD,3,\n".as_bytes()).unwrap();
+        let input = "type,client,tx,amount,currency\n\
+                     deposit,1,1,1,USD\n\
+                     deposit,1,2,1,USD\n\
+                     withdrawal,1,3,0.5,EUR\n";
+        let read = |input_precision| {
+            let options = ReadOptions {
+                input_precision,
+                fx: Some(Arc::new(Converter::new("EUR".into(), rates.clone()))),
+                ..ReadOptions::default()
+            };
+            let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+            read_csv_data("test", input.as_bytes(), &mut db, &options, &mut ()).unwrap();
+            let tx = db.transaction(&1.into()).unwrap();
+            let unrounded = tx.conversion.as_ref().unwrap().unrounded;
+            (
+                db.account(&1).unwrap().balance("EUR"),
+                tx.amount(),
+                unrounded,
+            )
+        };
+        // a third of a dollar is rounded to four decimal places by default
+        let (balance, amount, unrounded) = read(None);
+        assert_eq!(balance, "0.1666".parse::<Amount>().unwrap());
+        assert_eq!(amount, "0.3333".parse::<Amount>().unwrap());
+        assert_eq!(unrounded.scale(), 28);
+        let precision = Precision {
+            scale: 2,
+            rounding: Rounding::HalfUp,
+        };
+        let (balance, _, _) = read(Some(precision));
+        assert_eq!(balance, "0.16".parse::<Amount>().unwrap());
+    }
+}
@@ -0,0 +1,316 @@
+use std::{io::Write, str::FromStr};
+
+use rust_decimal::Decimal;
+
+use crate::model::ClientId;
+
+/// Relative weights of the deposits and withdrawals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeMix {
+    pub deposit: u32,
+    pub withdrawal: u32,
+}
+
+impl Default for TypeMix {
+    fn default() -> Self {
+        TypeMix {
+            deposit: 70,
+            withdrawal: 30,
+        }
+    }
+}
+
+impl TypeMix {
+    /// the sum of the weights, it can't overflow
+    fn total(&self) -> u64 {
+        u64::from(self.deposit) + u64::from(self.withdrawal)
+    }
+}
+
+impl FromStr for TypeMix {
+    type Err = String;
+
+    /// comma separated `type=weight` pairs, e.g. `deposit=60,withdrawal=40`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = TypeMix {
+            deposit: 0,
+            withdrawal: 0,
+        };
+        for pair in s.split(',').map(str::trim) {
+            let (name, weight) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected type=weight: {}", pair))?;
+            let weight = weight
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid weight {}: {}", weight, e))?;
+            match name.trim() {
+                "deposit" => mix.deposit = weight,
+                "withdrawal" => mix.withdrawal = weight,
+                name => return Err(format!("unknown type in mix: {}", name)),
+            }
+        }
+        if mix.total() == 0 {
+            return Err("the weights of the mix can't all be zero".into());
+        }
+        Ok(mix)
+    }
+}
+
+/// Shape of a generated input, the rates are per row probabilities
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workload {
+    pub seed: u64,
+    pub clients: ClientId,
+    pub rows: u64,
+    pub mix: TypeMix,
+    /// a row disputes an earlier deposit
+    pub dispute_rate: f64,
+    /// a settled dispute ends with a chargeback instead of a resolve
+    pub chargeback_rate: f64,
+    /// a deposit or withdrawal reuses an earlier transaction id
+    pub duplicate_rate: f64,
+    /// a row can't be parsed or converted
+    pub malformed_rate: f64,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload {
+            seed: 0,
+            clients: 100,
+            rows: 1000,
+            mix: TypeMix::default(),
+            dispute_rate: 0.02,
+            chargeback_rate: 0.2,
+            duplicate_rate: 0.0,
+            malformed_rate: 0.0,
+        }
+    }
+}
+
+impl Workload {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.clients == 0 {
+            return Err("at least one client is needed".into());
+        }
+        let rates = [
+            ("dispute", self.dispute_rate),
+            ("chargeback", self.chargeback_rate),
+            ("duplicate", self.duplicate_rate),
+            ("malformed", self.malformed_rate),
+        ];
+        for (name, rate) in &rates {
+            if !(0.0..=1.0).contains(rate) {
+                return Err(format!("{} rate must be between 0 and 1", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SplitMix64, small and stable so a seed always produces the same file
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// uniform in [0, n)
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && self.next_f64() < rate
+    }
+}
+
+/// Writes `workload.rows` rows of csv with a header, the same workload always
+/// produces the same output
+pub fn generate(workload: &Workload, mut writer: impl Write) -> std::io::Result<()> {
+    let mut rng = Rng(workload.seed);
+    // (client, tx) of the deposits that can be disputed
+    let mut deposits: Vec<(ClientId, u64)> = Vec::new();
+    let mut disputed: Vec<(ClientId, u64)> = Vec::new();
+    let mut next_tx: u64 = 1;
+
+    writeln!(writer, "type,client,tx,amount")?;
+    for _ in 0..workload.rows {
+        let client = 1 + rng.below(u64::from(workload.clients)) as ClientId;
+        if rng.chance(workload.malformed_rate) {
+            write_malformed(&mut writer, &mut rng, client, next_tx)?;
+            continue;
+        }
+        if !disputed.is_empty() && rng.chance(workload.dispute_rate) {
+            let (client, tx) = disputed.remove(0);
+            let settle = if rng.chance(workload.chargeback_rate) {
+                "chargeback"
+            } else {
+                "resolve"
+            };
+            writeln!(writer, "{},{},{},", settle, client, tx)?;
+            continue;
+        }
+        if !deposits.is_empty() && rng.chance(workload.dispute_rate) {
+            let (client, tx) = deposits.swap_remove(rng.below(deposits.len() as u64) as usize);
+            writeln!(writer, "dispute,{},{},", client, tx)?;
+            disputed.push((client, tx));
+            continue;
+        }
+
+        let duplicate = next_tx > 1 && rng.chance(workload.duplicate_rate);
+        let tx = if duplicate {
+            1 + rng.below(next_tx - 1)
+        } else {
+            next_tx += 1;
+            next_tx - 1
+        };
+        let mix = workload.mix;
+        let amount = random_amount(&mut rng);
+        if rng.below(mix.total()) < u64::from(mix.deposit) {
+            writeln!(writer, "deposit,{},{},{}", client, tx, amount)?;
+            if !duplicate {
+                deposits.push((client, tx));
+            }
+        } else {
+            writeln!(writer, "withdrawal,{},{},{}", client, tx, amount)?;
+        }
+    }
+    writer.flush()
+}
+
+/// up to 1000 with 4 decimal places
+fn random_amount(rng: &mut Rng) -> Decimal {
+    Decimal::new(1 + rng.below(10_000_000) as i64, 4)
+}
+
+fn write_malformed(
+    writer: &mut impl Write,
+    rng: &mut Rng,
+    client: ClientId,
+    tx: u64,
+) -> std::io::Result<()> {
+    match rng.below(4) {
+        0 => writeln!(writer, "deposit,{},{},not-a-number", client, tx),
+        1 => writeln!(writer, "deposit,{}", client),
+        2 => writeln!(writer, "refund,{},{},1.0", client, tx),
+        _ => writeln!(writer, "withdrawal,{},{},", client, tx),
+    }
+}
+
+/// parse a rate given either as a fraction or as a percentage, e.g. `0.05` or `5%`
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let (value, scale) = match s.strip_suffix('%') {
+        Some(value) => (value, 100.0),
+        None => (s, 1.0),
+    };
+    value
+        .trim()
+        .parse::<f64>()
+        .map(|rate| rate / scale)
+        .map_err(|e| format!("invalid rate {}: {}", s, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{generate, parse_rate, TypeMix, Workload};
+    use crate::{
+        db::TransactionDB,
+        io::{read_csv_data, ReadOptions},
+    };
+
+    fn generated(workload: &Workload) -> String {
+        let mut out = Vec::new();
+        generate(workload, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_generate() {
+        let workload = Workload {
+            seed: 7,
+            rows: 500,
+            clients: 5,
+            dispute_rate: 0.1,
+            chargeback_rate: 0.5,
+            ..Workload::default()
+        };
+        let data = generated(&workload);
+        assert_eq!(data, generated(&workload));
+        assert_ne!(
+            data,
+            generated(&Workload {
+                seed: 8,
+                ..workload.clone()
+            })
+        );
+        assert_eq!(data.lines().count(), 501);
+        assert!(data.contains("\ndispute,"));
+        assert!(data.contains("\nchargeback,"));
+
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let report = read_csv_data(
+            "gen",
+            data.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut (),
+        )
+        .unwrap();
+        assert_eq!(report.rows, 500);
+        assert!(report
+            .rejections
+            .iter()
+            .all(|r| r.reason.category() == "insufficient_funds"
+                || r.reason.category() == "account_locked"));
+
+        let faulty = Workload {
+            duplicate_rate: 0.1,
+            malformed_rate: 0.1,
+            ..workload
+        };
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let report = read_csv_data(
+            "gen",
+            generated(&faulty).as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut (),
+        )
+        .unwrap();
+        let categories: Vec<_> = report
+            .rejections
+            .iter()
+            .map(|r| r.reason.category())
+            .collect();
+        assert!(categories.contains(&"malformed"));
+        assert!(categories.contains(&"integrity"));
+
+        assert_eq!(parse_rate("5%"), Ok(0.05));
+        assert_eq!(parse_rate("0.5"), Ok(0.5));
+        assert!(parse_rate("x").is_err());
+
+        // the weights add up without overflowing
+        let mix: TypeMix = "deposit=4294967295,withdrawal=4294967295".parse().unwrap();
+        let data = generated(&Workload {
+            rows: 10,
+            mix,
+            ..workload
+        });
+        assert_eq!(data.lines().count(), 11);
+        assert!("deposit=0,withdrawal=0".parse::<TypeMix>().is_err());
+    }
+}
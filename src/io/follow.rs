@@ -0,0 +1,297 @@
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// how long to wait for the file to grow before checking it again
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads a file as it grows, like `tail -f`.
+///
+/// The data is split in segments that end on a line boundary: when a flush is
+/// due (after `interval`, or when `flush` is set) or the file was rotated or
+/// truncated, `read` returns end of file. The next segment starts again with
+/// the header line, so every segment is a complete csv input. Only complete
+/// lines are returned, except for the last line of a rotated file. After
+/// `stop` is set the segment ends as soon as the lines written so far are
+/// consumed, an unfinished last line is dropped, and `is_stopped` returns
+/// true.
+pub struct Follow {
+    path: PathBuf,
+    file: File,
+    metadata: Metadata,
+    /// bytes read from the current file
+    position: u64,
+    /// lines read from the current file
+    lines: u64,
+    header: Vec<u8>,
+    /// part of the header still to be returned in this segment
+    replay: usize,
+    replaying: bool,
+    /// bytes read from the file and not returned yet
+    buffer: Vec<u8>,
+    /// the file was replaced, the segment ends with the buffer
+    rotated: bool,
+    /// the current segment was ended, the next read starts a new one
+    ended: bool,
+    interval: Option<Duration>,
+    last_flush: Instant,
+    flush: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    stopped: bool,
+}
+
+impl Follow {
+    pub fn open(path: &Path, interval: Option<Duration>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        Ok(Follow {
+            path: path.to_owned(),
+            file,
+            metadata,
+            position: 0,
+            lines: 0,
+            header: Vec::new(),
+            replay: 0,
+            replaying: false,
+            buffer: Vec::new(),
+            rotated: false,
+            ended: false,
+            interval,
+            last_flush: Instant::now(),
+            flush: Arc::default(),
+            stop: Arc::default(),
+            stopped: false,
+        })
+    }
+
+    /// when set, the current segment ends at the next line boundary
+    pub fn flush_flag(&self) -> Arc<AtomicBool> {
+        self.flush.clone()
+    }
+
+    /// when set, reading ends once the file has no more data
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// the difference between the line numbers of the next segment and the
+    /// lines of the file
+    pub fn line_offset(&self) -> u64 {
+        self.lines.saturating_sub(1)
+    }
+
+    fn start_segment(&mut self) {
+        self.ended = false;
+        self.last_flush = Instant::now();
+        if self.lines > 0 {
+            self.replaying = true;
+            self.replay = 0;
+        }
+    }
+
+    fn end_segment(&mut self) -> usize {
+        self.ended = true;
+        self.flush.store(false, Ordering::SeqCst);
+        0
+    }
+
+    fn flush_due(&self) -> bool {
+        self.flush.load(Ordering::SeqCst)
+            || matches!(self.interval, Some(interval) if self.last_flush.elapsed() >= interval)
+    }
+
+    /// reopen the file if it was replaced or truncated
+    fn check_rotation(&mut self) -> io::Result<bool> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // the file is being rotated, wait for the new one
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if same_file(&self.metadata, &metadata) && metadata.len() >= self.position {
+            return Ok(false);
+        }
+        if same_file(&self.metadata, &metadata) {
+            warn!("{}: truncated, reading from the start", self.path.display());
+            self.file.seek(SeekFrom::Start(0))?;
+        } else {
+            warn!("{}: rotated, reading the new file", self.path.display());
+            self.file = File::open(&self.path)?;
+        }
+        self.metadata = self.file.metadata()?;
+        self.position = 0;
+        self.lines = 0;
+        self.header.clear();
+        Ok(true)
+    }
+
+    /// read more of the file into the buffer
+    fn read_file(&mut self) -> io::Result<usize> {
+        let mut chunk = [0; 8192];
+        let n = self.file.read(&mut chunk)?;
+        for &byte in &chunk[..n] {
+            if self.lines == 0 {
+                self.header.push(byte);
+            }
+            if byte == b'\n' {
+                self.lines += 1;
+            }
+        }
+        self.buffer.extend_from_slice(&chunk[..n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.ended {
+            if self.stopped {
+                return Ok(0);
+            }
+            self.start_segment();
+        }
+        if self.replaying {
+            let rest = &self.header[self.replay..];
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            self.replay += n;
+            self.replaying = self.replay < self.header.len();
+            if n > 0 {
+                return Ok(n);
+            }
+        }
+        loop {
+            if let Some(end) = self.buffer.iter().rposition(|&byte| byte == b'\n') {
+                let n = (end + 1).min(buf.len());
+                buf[..n].copy_from_slice(&self.buffer[..n]);
+                self.buffer.drain(..n);
+                return Ok(n);
+            }
+            if self.rotated {
+                self.rotated = false;
+                return Ok(self.end_segment());
+            }
+            if self.read_file()? > 0 {
+                continue;
+            }
+            if self.stop.load(Ordering::SeqCst) {
+                if !self.buffer.is_empty() {
+                    warn!(
+                        "{}: dropping the unfinished last line: {}",
+                        self.path.display(),
+                        String::from_utf8_lossy(&self.buffer)
+                    );
+                    self.buffer.clear();
+                }
+                self.stopped = true;
+                return Ok(self.end_segment());
+            }
+            if self.buffer.is_empty() && self.flush_due() {
+                return Ok(self.end_segment());
+            }
+            if self.check_rotation()? {
+                // a partial last line of the old file is completed by the end of the segment
+                if !self.buffer.is_empty() {
+                    self.buffer.push(b'\n');
+                }
+                self.rotated = true;
+                continue;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_: &Metadata, _: &Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, OpenOptions},
+        io::{Read, Write},
+        sync::atomic::Ordering,
+    };
+
+    use super::Follow;
+
+    fn segment(follow: &mut Follow) -> String {
+        let mut data = String::new();
+        follow.read_to_string(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_follow() {
+        let dir = std::env::temp_dir().join(format!("atm-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tx.csv");
+        fs::write(&path, "type,client,tx,amount\ndeposit,1,1,1\n").unwrap();
+
+        let mut follow = Follow::open(&path, None).unwrap();
+        follow.flush_flag().store(true, Ordering::SeqCst);
+        assert_eq!(
+            segment(&mut follow),
+            "type,client,tx,amount\ndeposit,1,1,1\n"
+        );
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"deposit,1,2,2\n").unwrap();
+        follow.flush_flag().store(true, Ordering::SeqCst);
+        assert_eq!(follow.line_offset(), 1);
+        assert_eq!(
+            segment(&mut follow),
+            "type,client,tx,amount\ndeposit,1,2,2\n"
+        );
+
+        // truncated, the segment ends after the replayed header
+        fs::write(&path, "type,client,tx,amount\n").unwrap();
+        assert_eq!(segment(&mut follow), "type,client,tx,amount\n");
+        assert_eq!(follow.line_offset(), 0);
+
+        // rotated, the truncated file is read to its end first
+        fs::rename(&path, dir.join("tx.csv.1")).unwrap();
+        fs::write(&path, "type,client,tx,amount\ndeposit,2,3,3\n").unwrap();
+        assert_eq!(segment(&mut follow), "type,client,tx,amount\n");
+        follow.stop_flag().store(true, Ordering::SeqCst);
+        assert_eq!(
+            segment(&mut follow),
+            "type,client,tx,amount\ndeposit,2,3,3\n"
+        );
+        assert!(follow.is_stopped());
+
+        // a stop ends the reading in the middle of a line
+        let mut follow = Follow::open(&path, None).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"deposit,2,4,").unwrap();
+        follow.stop_flag().store(true, Ordering::SeqCst);
+        assert_eq!(
+            segment(&mut follow),
+            "type,client,tx,amount\ndeposit,2,3,3\n"
+        );
+        assert!(follow.is_stopped());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use super::compress::decompress;
+
+/// Name used on the command line for the standard input
+pub const STDIN_NAME: &str = "-";
+
+/// A single source of transactions, either a file or the standard input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    Stdin,
+    File(PathBuf),
+}
+
+impl InputSource {
+    /// open the source for reading, compressed data is decompressed on the fly
+    pub fn open(&self) -> io::Result<Box<dyn Read>> {
+        match self {
+            InputSource::Stdin => decompress(io::stdin()),
+            InputSource::File(path) => decompress(File::open(path)?),
+        }
+    }
+
+    /// the file name up to the first dot, e.g. `merchant-a` for
+    /// `in/merchant-a.csv.gz`, none for the standard input
+    pub fn stem(&self) -> Option<&str> {
+        match self {
+            InputSource::Stdin => None,
+            InputSource::File(path) => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split('.').next()),
+        }
+    }
+}
+
+impl Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputSource::Stdin => write!(f, "<stdin>"),
+            InputSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The order in which the resolved input files are fed into the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputOrder {
+    /// keep the order of the arguments, files of a directory are sorted by name
+    Args,
+    /// sort all files by their file name
+    Name,
+    /// sort all files by their modification time, oldest first
+    Modified,
+}
+
+impl FromStr for InputOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "args" => Ok(InputOrder::Args),
+            "name" => Ok(InputOrder::Name),
+            "mtime" => Ok(InputOrder::Modified),
+            _ => Err(format!("unknown input order: {}", s)),
+        }
+    }
+}
+
+/// Expands the command line arguments into a list of input sources.
+/// Directories are expanded to the regular files they contain (not recursive),
+/// `-` stands for the standard input and may be given only once. When sorting,
+/// the standard input is always read first.
+pub fn resolve_inputs(args: &[PathBuf], order: InputOrder) -> io::Result<Vec<InputSource>> {
+    let mut sources = Vec::new();
+    let mut stdin = false;
+    for arg in args {
+        if arg.as_os_str() == STDIN_NAME {
+            if stdin {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "stdin given more than once",
+                ));
+            }
+            stdin = true;
+            sources.push(InputSource::Stdin);
+        } else if arg.is_dir() {
+            sources.extend(dir_files(arg)?.into_iter().map(InputSource::File));
+        } else {
+            sources.push(InputSource::File(arg.clone()));
+        }
+    }
+
+    match order {
+        InputOrder::Args => {}
+        InputOrder::Name => sources.sort_by_key(|s| match s {
+            InputSource::Stdin => None,
+            InputSource::File(path) => Some(path.file_name().map(|n| n.to_owned())),
+        }),
+        InputOrder::Modified => {
+            let mut keyed = sources
+                .into_iter()
+                .map(|s| {
+                    let key = match &s {
+                        InputSource::Stdin => None,
+                        InputSource::File(path) => Some(fs::metadata(path)?.modified()?),
+                    };
+                    Ok((key, s))
+                })
+                .collect::<io::Result<Vec<(Option<SystemTime>, InputSource)>>>()?;
+            keyed.sort_by_key(|(key, _)| *key);
+            sources = keyed.into_iter().map(|(_, s)| s).collect();
+        }
+    }
+    Ok(sources)
+}
+
+fn dir_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::{resolve_inputs, InputOrder, InputSource};
+
+    #[test]
+    fn test_resolve_inputs() {
+        let dir = std::env::temp_dir().join(format!("atm-inputs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["b.csv", "a.csv"] {
+            fs::write(dir.join(name), "type,client,tx,amount\n").unwrap();
+        }
+        let single = PathBuf::from("/nonexistent/0.csv");
+
+        let args = vec![single.clone(), PathBuf::from("-"), dir.clone()];
+        let sources = resolve_inputs(&args, InputOrder::Args).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                InputSource::File(single.clone()),
+                InputSource::Stdin,
+                InputSource::File(dir.join("a.csv")),
+                InputSource::File(dir.join("b.csv")),
+            ]
+        );
+
+        let args = vec![dir.clone(), single.clone(), PathBuf::from("-")];
+        let sources = resolve_inputs(&args, InputOrder::Name).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                InputSource::Stdin,
+                InputSource::File(single),
+                InputSource::File(dir.join("a.csv")),
+                InputSource::File(dir.join("b.csv")),
+            ]
+        );
+
+        let args = vec![PathBuf::from("-"), PathBuf::from("-")];
+        assert!(resolve_inputs(&args, InputOrder::Args).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashMap};
+
+use csv::StringRecord;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::model::input::{COLUMNS, REQUIRED_COLUMNS, TRANSACTION_TYPES};
+
+/// Aliases for the input columns and the transaction type values, keyed by
+/// their internal names, e.g. `columns.tx = ["txn_id"]`, `types.withdrawal = ["withdraw"]`,
+/// sorted so a conflict between two aliases is always reported the same way
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColumnMapping {
+    pub columns: BTreeMap<String, Vec<String>>,
+    pub types: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MappingError {
+    #[error("unknown column in mapping: {0}")]
+    UnknownColumn(String),
+    #[error("unknown transaction type in mapping: {0}")]
+    UnknownType(String),
+    #[error("alias {0:?} is used for both {1} and {2}")]
+    AmbiguousAlias(String, &'static str, &'static str),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HeaderError {
+    #[error("missing required column: {0}")]
+    MissingColumn(&'static str),
+    #[error("columns {1:?} and {2:?} both map to {0}")]
+    AmbiguousColumn(&'static str, String, String),
+}
+
+/// Validated mapping, names are compared case insensitively
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    columns: HashMap<String, &'static str>,
+    types: HashMap<String, &'static str>,
+}
+
+impl Default for Mapping {
+    /// the internal names only
+    fn default() -> Self {
+        ColumnMapping::default()
+            .compile()
+            .expect("empty mapping is valid")
+    }
+}
+
+impl ColumnMapping {
+    /// check the aliases and build the lookup tables
+    pub fn compile(&self) -> Result<Mapping, MappingError> {
+        Ok(Mapping {
+            columns: aliases(COLUMNS, &self.columns, MappingError::UnknownColumn)?,
+            types: aliases(TRANSACTION_TYPES, &self.types, MappingError::UnknownType)?,
+        })
+    }
+}
+
+fn aliases(
+    names: &[&'static str],
+    config: &BTreeMap<String, Vec<String>>,
+    unknown: fn(String) -> MappingError,
+) -> Result<HashMap<String, &'static str>, MappingError> {
+    let mut table: HashMap<String, &'static str> =
+        names.iter().map(|name| (name.to_string(), *name)).collect();
+    for (name, aliases) in config {
+        let name = match names.iter().find(|n| n.eq_ignore_ascii_case(name)) {
+            Some(name) => *name,
+            None => return Err(unknown(name.clone())),
+        };
+        for alias in aliases {
+            let alias = alias.trim().to_lowercase();
+            match table.get(&alias) {
+                Some(other) if *other != name => {
+                    return Err(MappingError::AmbiguousAlias(alias, other, name));
+                }
+                _ => {
+                    table.insert(alias, name);
+                }
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// The header of an input file resolved against a `Mapping`
+#[derive(Debug)]
+pub struct Header {
+    /// the header with all known columns renamed to their internal names
+    pub columns: StringRecord,
+    /// columns that are not known
+    pub unknown: Vec<String>,
+    type_index: usize,
+}
+
+impl Mapping {
+    /// resolve the column names of the input, fails if a required column is
+    /// missing or two columns map to the same name
+    pub fn header(&self, header: &StringRecord) -> Result<Header, HeaderError> {
+        let mut columns = StringRecord::with_capacity(header.as_slice().len(), header.len());
+        let mut unknown = Vec::new();
+        let mut seen: HashMap<&'static str, &str> = HashMap::new();
+        for column in header.iter() {
+            match self.columns.get(&column.to_lowercase()) {
+                Some(name) => {
+                    if let Some(prev) = seen.insert(name, column) {
+                        return Err(HeaderError::AmbiguousColumn(
+                            name,
+                            prev.to_owned(),
+                            column.to_owned(),
+                        ));
+                    }
+                    columns.push_field(name);
+                }
+                None => {
+                    unknown.push(column.to_owned());
+                    columns.push_field(column);
+                }
+            }
+        }
+        if let Some(missing) = REQUIRED_COLUMNS.iter().find(|c| !seen.contains_key(*c)) {
+            return Err(HeaderError::MissingColumn(missing));
+        }
+        let type_index = columns
+            .iter()
+            .position(|c| c == "type")
+            .expect("type is a required column");
+        Ok(Header {
+            columns,
+            unknown,
+            type_index,
+        })
+    }
+
+    /// rewrite the type of the row to its internal name, if it is an alias
+    pub fn normalize_type(&self, header: &Header, record: &mut StringRecord) {
+        let value = match record.get(header.type_index) {
+            Some(value) => value,
+            None => return,
+        };
+        let name = match self.types.get(&value.to_lowercase()) {
+            Some(name) if *name != value => *name,
+            _ => return,
+        };
+        let position = record.position().cloned();
+        *record = record
+            .iter()
+            .enumerate()
+            .map(|(i, field)| if i == header.type_index { name } else { field })
+            .collect();
+        if let Some(position) = position {
+            record.set_position(Some(position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use csv::StringRecord;
+
+    use super::{ColumnMapping, HeaderError, Mapping, MappingError};
+
+    #[test]
+    fn test_mapping() {
+        let mapping = ColumnMapping {
+            columns: vec![
+                ("tx".to_owned(), vec!["txn_id".to_owned()]),
+                ("client".to_owned(), vec!["Customer".to_owned()]),
+                ("type".to_owned(), vec!["kind".to_owned()]),
+            ]
+            .into_iter()
+            .collect(),
+            types: vec![("withdrawal".to_owned(), vec!["withdraw".to_owned()])]
+                .into_iter()
+                .collect(),
+        }
+        .compile()
+        .unwrap();
+
+        let header = mapping
+            .header(&StringRecord::from(vec![
+                "Kind", "customer", "TXN_ID", "note",
+            ]))
+            .unwrap();
+        assert_eq!(header.columns, vec!["type", "client", "tx", "note"]);
+        assert_eq!(header.unknown, vec!["note".to_owned()]);
+
+        let mut record = StringRecord::from(vec!["WITHDRAW", "1", "2", ""]);
+        mapping.normalize_type(&header, &mut record);
+        assert_eq!(record, vec!["withdrawal", "1", "2", ""]);
+        let mut record = StringRecord::from(vec!["ChargeBack", "1", "2", ""]);
+        mapping.normalize_type(&header, &mut record);
+        assert_eq!(record, vec!["chargeback", "1", "2", ""]);
+
+        assert_eq!(
+            mapping
+                .header(&StringRecord::from(vec!["type", "client", "tx", "txn_id"]))
+                .unwrap_err(),
+            HeaderError::AmbiguousColumn("tx", "tx".to_owned(), "txn_id".to_owned())
+        );
+        assert_eq!(
+            Mapping::default()
+                .header(&StringRecord::from(vec!["type", "client", "amount"]))
+                .unwrap_err(),
+            HeaderError::MissingColumn("tx")
+        );
+    }
+
+    #[test]
+    fn test_invalid_mapping() {
+        let mut columns = BTreeMap::new();
+        columns.insert("tx".to_owned(), vec!["id".to_owned()]);
+        columns.insert("client".to_owned(), vec!["ID".to_owned()]);
+        columns.insert("amount".to_owned(), vec!["Id ".to_owned()]);
+        let mapping = ColumnMapping {
+            columns,
+            ..Default::default()
+        };
+        assert_eq!(
+            mapping.compile().unwrap_err(),
+            MappingError::AmbiguousAlias("id".to_owned(), "amount", "client")
+        );
+        assert_eq!(
+            mapping.compile().unwrap_err().to_string(),
+            "alias \"id\" is used for both amount and client"
+        );
+
+        let mut types = BTreeMap::new();
+        types.insert("refund".to_owned(), vec!["r".to_owned()]);
+        let mapping = ColumnMapping {
+            types,
+            ..Default::default()
+        };
+        assert_eq!(
+            mapping.compile().unwrap_err(),
+            MappingError::UnknownType("refund".to_owned())
+        );
+    }
+}
@@ -1,66 +1,1570 @@
-use std::convert::TryInto;
+use std::{convert::TryInto, str::FromStr, sync::Arc};
 
-use csv::{ReaderBuilder, Trim, WriterBuilder};
+use csv::{ReaderBuilder, StringRecord, Trim, WriterBuilder};
+use thiserror::Error;
 
+use self::{
+    mapping::{Header, HeaderError, Mapping},
+    order::{Pending, Reorder},
+    pending::{PendingLimits, PendingReferences},
+};
 use crate::{
-    db::{AccountStore, TransactionDB, TransactionStore},
-    model::{account::Account, input::TxRow, output::Record, Amount, Tx},
+    db::{fraud::Flag, AccountStore, ExpiredDispute, TransactionDB, TransactionStore},
+    fx::Converter,
+    model::{
+        account::{Account, TxError},
+        id::{parse_client_id, IdError, IdFormat},
+        input::{ConversionError, TxRow},
+        output::{ExpiredRecord, FlagRecord, MetricsRecord, Record, RejectRecord},
+        precision::{ExcessPrecision, Precision},
+        time::format_timestamp,
+        ClientId, Currency, TenantId, TransactionId, Tx, TxOperation, TxRecord,
+    },
 };
 
-pub fn read_csv_data<'a, R, T, A>(reader: R, db: &mut TransactionDB<'a, T, A>)
+pub mod compress;
+pub mod follow;
+pub mod input;
+pub mod mapping;
+pub mod order;
+pub mod pending;
+
+/// Kinds of problems that stop the processing in strict mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrictChecks {
+    /// the header contains an unknown column
+    pub unknown_column: bool,
+    /// a row has a different number of fields than the header
+    pub column_count: bool,
+    /// a row can't be parsed
+    pub malformed: bool,
+    /// a row is not a valid transaction
+    pub conversion: bool,
+    /// a transaction is refused by the database
+    pub rejected: bool,
+}
+
+impl StrictChecks {
+    pub fn all() -> Self {
+        StrictChecks {
+            unknown_column: true,
+            column_count: true,
+            malformed: true,
+            conversion: true,
+            rejected: true,
+        }
+    }
+}
+
+impl FromStr for StrictChecks {
+    type Err = String;
+
+    /// comma separated list of checks or `all`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(StrictChecks::all());
+        }
+        let mut checks = StrictChecks {
+            unknown_column: false,
+            column_count: false,
+            malformed: false,
+            conversion: false,
+            rejected: false,
+        };
+        for check in s.split(',').map(str::trim) {
+            match check {
+                "unknown-column" => checks.unknown_column = true,
+                "column-count" => checks.column_count = true,
+                "malformed" => checks.malformed = true,
+                "conversion" => checks.conversion = true,
+                "rejected" => checks.rejected = true,
+                _ => return Err(format!("unknown strict check: {}", check)),
+            }
+        }
+        Ok(checks)
+    }
+}
+
+/// Settings for reading the input
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// when set, amounts with more decimal places are rejected or rounded
+    pub input_precision: Option<Precision>,
+    pub excess_precision: ExcessPrecision,
+    /// when set, the selected kinds of problems stop the processing
+    pub strict: Option<StrictChecks>,
+    /// aliases of the column names and the transaction types
+    pub mapping: Mapping,
+    /// maximum number of rows in a single input, reading stops above it
+    pub max_rows: Option<u64>,
+    /// the kinds of transaction ids that are accepted
+    pub transaction_ids: IdFormat,
+    /// tenant of the rows that don't have a `tenant` column or leave it empty
+    pub tenant: TenantId,
+    /// currency of the rows that don't have a `currency` column or leave it empty
+    pub currency: Currency,
+    /// when set, the amounts are converted into the currency of the accounts
+    pub fx: Option<Arc<Converter>>,
+    /// when set, the rows are reordered by timestamp within a buffer of this
+    /// many rows
+    pub reorder_window: Option<usize>,
+    /// when set, disputes of transactions that were not seen yet wait for them
+    pub pending_references: Option<PendingLimits>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            input_precision: None,
+            excess_precision: ExcessPrecision::Reject,
+            strict: None,
+            mapping: Mapping::default(),
+            max_rows: None,
+            transaction_ids: IdFormat::Any,
+            tenant: TenantId::new(),
+            currency: Currency::new(),
+            fx: None,
+            reorder_window: None,
+            pending_references: None,
+        }
+    }
+}
+
+impl ReadOptions {
+    fn is_fatal(&self, reason: &RejectReason) -> bool {
+        let strict = self.strict.as_ref();
+        match reason {
+            RejectReason::Header(_) | RejectReason::RowLimit(_) => true,
+            RejectReason::Malformed(_) | RejectReason::InvalidId { .. } => {
+                strict.is_some_and(|strict| strict.malformed)
+            }
+            RejectReason::Conversion(_) => strict.is_some_and(|strict| strict.conversion),
+            RejectReason::Rejected(_) | RejectReason::PendingUnmatched(_) => {
+                strict.is_some_and(|strict| strict.rejected)
+            }
+            RejectReason::UnknownColumn(_) => strict.is_some_and(|strict| strict.unknown_column),
+            RejectReason::ColumnCount { .. } => strict.is_some_and(|strict| strict.column_count),
+        }
+    }
+
+    fn check_precision(&self, mut tx: Tx) -> Result<Tx, ConversionError> {
+        if let Some(precision) = &self.input_precision {
+            match &mut tx.operation {
+                TxOperation::Deposit(amount) | TxOperation::Withdraw(amount) => {
+                    *amount = precision.check(*amount, self.excess_precision)?;
+                }
+                TxOperation::Dispute(_) => {}
+            }
+        }
+        Ok(tx)
+    }
+}
+
+/// The reason a row from the input did not make it into the database
+#[derive(Debug, Error)]
+pub enum RejectReason {
+    /// the row can't be parsed
+    #[error("malformed row: {0}")]
+    Malformed(String),
+    /// the client or transaction id doesn't fit the id type or format
+    #[error("invalid {column} id: {error}")]
+    InvalidId {
+        column: &'static str,
+        error: IdError,
+    },
+    /// the row is parsed, but it is not a valid transaction
+    #[error(transparent)]
+    Conversion(ConversionError),
+    /// the transaction is refused by the database
+    #[error(transparent)]
+    Rejected(TxError),
+    /// the header contains a column that is not known
+    #[error("unknown column: {0}")]
+    UnknownColumn(String),
+    /// the row has a different number of fields than the header
+    #[error("expected {expected} fields, found {found}")]
+    ColumnCount { expected: usize, found: usize },
+    /// the header can't be mapped to the known columns, boxed as it is rare
+    /// and larger than the other reasons
+    #[error(transparent)]
+    Header(Box<HeaderError>),
+    /// the input has more rows than allowed
+    #[error("more than {0} rows")]
+    RowLimit(u64),
+    /// the row waited for its transaction until the end of the input, see
+    /// `ReadOptions::pending_references`
+    #[error("transaction {0} not found before the end of the input")]
+    PendingUnmatched(TransactionId),
+}
+
+impl RejectReason {
+    /// short, stable name of the reason, suitable for grouping
+    pub fn category(&self) -> &'static str {
+        match self {
+            RejectReason::Malformed(_) => "malformed",
+            RejectReason::InvalidId { .. } => "invalid_id",
+            RejectReason::Conversion(_) => "conversion",
+            RejectReason::UnknownColumn(_) => "unknown_column",
+            RejectReason::ColumnCount { .. } => "column_count",
+            RejectReason::Header(_) => "header",
+            RejectReason::RowLimit(_) => "row_limit",
+            RejectReason::PendingUnmatched(_) => "pending_unmatched",
+            RejectReason::Rejected(e) => match e {
+                TxError::AccountLocked(_) => "account_locked",
+                TxError::InsufficientFunds(_) => "insufficient_funds",
+                TxError::TransactionNotFound(_) => "transaction_not_found",
+                TxError::InvalidState(_, _) => "invalid_state",
+                TxError::DisputeNotAllowed(_) => "dispute_not_allowed",
+                TxError::DisputeWindowClosed(_) => "dispute_window_closed",
+                TxError::LimitExceeded { .. } => "limit_exceeded",
+                TxError::AccountLimit(_) => "account_limit",
+                TxError::AccountExists(_) => "account_exists",
+                TxError::OutOfOrder { .. } => "out_of_order",
+                TxError::IntegrityError(_) => "integrity",
+            },
+        }
+    }
+}
+
+/// A single rejected row, passed around boxed as it is much larger than a `Tx`
+#[derive(Debug)]
+pub struct Rejection {
+    pub line: u64,
+    /// the tenant of the row, if it could be parsed
+    pub tenant: Option<TenantId>,
+    /// the currency of the row, if it is a valid transaction
+    pub currency: Option<Currency>,
+    pub client_id: Option<ClientId>,
+    pub transaction_id: Option<TransactionId>,
+    /// the requested operation, if the row is a valid transaction
+    pub operation: Option<TxOperation>,
+    pub reason: RejectReason,
+}
+
+impl Rejection {
+    /// the rejection of a valid transaction
+    fn of_tx(line: u64, tx: &Tx, reason: RejectReason) -> Box<Self> {
+        Box::new(Rejection {
+            line,
+            tenant: Some(tx.tenant.clone()),
+            currency: Some(tx.currency.clone()),
+            client_id: Some(tx.client_id),
+            transaction_id: Some(tx.transaction_id),
+            operation: Some(tx.operation),
+            reason,
+        })
+    }
+
+    fn new(line: u64, reason: RejectReason) -> Box<Self> {
+        Box::new(Rejection {
+            line,
+            tenant: None,
+            currency: None,
+            client_id: None,
+            transaction_id: None,
+            operation: None,
+            reason,
+        })
+    }
+}
+
+/// A processed row, passed to the `RowObserver` while reading
+#[derive(Debug)]
+pub enum RowEvent<'r> {
+    /// the transaction is accepted, `account` and `record` are the states after it
+    Accepted {
+        source: &'r str,
+        line: u64,
+        tx: &'r Tx,
+        account: &'r Account,
+        record: Option<&'r TxRecord>,
+    },
+    Rejected {
+        source: &'r str,
+        rejection: &'r Rejection,
+    },
+}
+
+/// Receives every row processed by `read_csv_data_with`
+pub trait RowObserver {
+    fn on_row(&mut self, event: &RowEvent<'_>);
+}
+
+impl RowObserver for () {
+    fn on_row(&mut self, _event: &RowEvent<'_>) {}
+}
+
+/// Outcome of reading a single input source
+#[derive(Debug, Default)]
+pub struct ReadReport {
+    pub source: String,
+    pub rows: u64,
+    pub accepted: u64,
+    /// the rejected rows, `accepted + rejections` is `rows` once the input
+    /// is read
+    pub rejections: Vec<Rejection>,
+    /// the problems of the header, they are not rows
+    pub header: Vec<Rejection>,
+}
+
+impl ReadReport {
+    /// record the rejection, or fail if it is covered by the strict checks
+    fn reject(
+        &mut self,
+        rejection: Rejection,
+        options: &ReadOptions,
+        header: bool,
+    ) -> Result<(), Box<ReadError>> {
+        if options.is_fatal(&rejection.reason) {
+            return Err(Box::new(ReadError {
+                input: self.source.clone(),
+                rejection,
+                report: std::mem::take(self),
+            }));
+        }
+        warn!("{}:{}: {}", self.source, rejection.line, rejection.reason);
+        if header {
+            self.header.push(rejection);
+        } else {
+            self.rejections.push(rejection);
+        }
+        Ok(())
+    }
+
+    /// the problems of the header, then the rejected rows
+    pub fn all_rejections(&self) -> impl Iterator<Item = &Rejection> {
+        self.header.iter().chain(&self.rejections)
+    }
+
+    /// add the counters of a later part of the same input, its lines start
+    /// after `line_offset`
+    pub fn append(&mut self, other: ReadReport, line_offset: u64) {
+        self.rows += other.rows;
+        self.accepted += other.accepted;
+        self.rejections
+            .extend(other.rejections.into_iter().map(|mut rejection| {
+                rejection.line += line_offset;
+                rejection
+            }));
+        self.header.extend(other.header);
+    }
+}
+
+/// Processing stopped because of an invalid header or a row that failed a
+/// strict check, contains the report of what was processed before it
+#[derive(Debug, Error)]
+#[error("{input}:{}: {}", rejection.line, rejection.reason)]
+pub struct ReadError {
+    pub input: String,
+    pub rejection: Rejection,
+    pub report: ReadReport,
+}
+
+/// Reads transactions from csv and adds them to the database, every processed
+/// row is passed to the observer. Rows that can't be processed are collected in
+/// the returned report, unless they fall in one of the strict checks, in that
+/// case reading stops at the offending row
+pub fn read_csv_data<'a, R, T, A, O>(
+    source: &str,
+    reader: R,
+    db: &mut TransactionDB<'a, T, A>,
+    options: &ReadOptions,
+    observer: &mut O,
+) -> Result<ReadReport, Box<ReadError>>
 where
     R: std::io::Read,
     T: TransactionStore,
     A: AccountStore<'a>,
+    O: RowObserver,
 {
-    let mut reader = ReaderBuilder::new()
-        .flexible(true)
-        .trim(Trim::All)
-        .from_reader(reader);
-    for result in reader.deserialize() {
-        let row: TxRow = match result {
-            Ok(row) => row,
+    let mut merged = MergedReader::new(options.reorder_window.unwrap_or(0));
+    merged.read(source, reader, db, options, observer);
+    single_report(merged.finish(db, options, observer))
+}
+
+/// Parses and converts the transactions without processing them, the report
+/// counts the valid rows as accepted
+pub fn validate_csv_data<R>(
+    source: &str,
+    reader: R,
+    options: &ReadOptions,
+) -> Result<ReadReport, Box<ReadError>>
+where
+    R: std::io::Read,
+{
+    let mut merged = MergedReader::new(options.reorder_window.unwrap_or(0));
+    let mut handle = |_: &str, _, _, _: &mut ()| Ok(());
+    merged.read_rows(source, reader, None, options, &mut (), &mut handle);
+    single_report(merged.drain(options, &mut (), &mut handle))
+}
+
+/// the report of the only input, or the error that stopped it with the report
+fn single_report(
+    (mut reports, error): (Vec<ReadReport>, Option<Box<ReadError>>),
+) -> Result<ReadReport, Box<ReadError>> {
+    let report = reports.pop().unwrap_or_default();
+    match error {
+        Some(mut error) => {
+            error.report = report;
+            Err(error)
+        }
+        None => Ok(report),
+    }
+}
+
+/// Reads several inputs through a single reorder buffer, so the rows of an
+/// input can be processed between the rows of the inputs read before it.
+/// Every input has its own report, the rows count in the report of the input
+/// they come from
+#[derive(Debug, Default)]
+pub struct MergedReader {
+    buffer: Reorder,
+    /// disputes waiting for their transaction, see `ReadOptions::pending_references`
+    pending: PendingReferences,
+    /// the number of rows passed to the database
+    processed: u64,
+    reports: Vec<ReadReport>,
+    error: Option<Box<ReadError>>,
+}
+
+impl MergedReader {
+    /// a reader that holds up to `window` rows, zero keeps the order of the
+    /// inputs
+    pub fn new(window: usize) -> Self {
+        MergedReader {
+            buffer: Reorder::new(window),
+            ..MergedReader::default()
+        }
+    }
+
+    /// read an input into the database, returns false once a row failed a
+    /// strict check, nothing is read after that
+    pub fn read<'a, R, T, A, O>(
+        &mut self,
+        source: &str,
+        reader: R,
+        db: &mut TransactionDB<'a, T, A>,
+        options: &ReadOptions,
+        observer: &mut O,
+    ) -> bool
+    where
+        R: std::io::Read,
+        T: TransactionStore,
+        A: AccountStore<'a>,
+        O: RowObserver,
+    {
+        self.read_rows(
+            source,
+            reader,
+            None,
+            options,
+            observer,
+            &mut |source, tx, line, observer| add_tx(db, source, tx, line, observer),
+        )
+    }
+
+    /// read a part of an input that starts with the header and then the line
+    /// after `line_offset`, the rows are numbered as lines of the whole input.
+    /// The rows count in the report of the previous part of the same input
+    pub fn read_segment<'a, R, T, A, O>(
+        &mut self,
+        source: &str,
+        reader: R,
+        line_offset: u64,
+        db: &mut TransactionDB<'a, T, A>,
+        options: &ReadOptions,
+        observer: &mut O,
+    ) -> bool
+    where
+        R: std::io::Read,
+        T: TransactionStore,
+        A: AccountStore<'a>,
+        O: RowObserver,
+    {
+        self.read_rows(
+            source,
+            reader,
+            Some(line_offset),
+            options,
+            observer,
+            &mut |source, tx, line, observer| add_tx(db, source, tx, line, observer),
+        )
+    }
+
+    /// the reports of the inputs read so far, the rows still held by the
+    /// buffer are not counted as accepted or rejected yet
+    pub fn reports(&self) -> &[ReadReport] {
+        &self.reports
+    }
+
+    /// process the rows held by the reorder buffer, so the next input is not
+    /// reordered with the previous ones, the disputes waiting for their
+    /// transaction keep waiting
+    pub fn flush<'a, T, A, O>(
+        &mut self,
+        db: &mut TransactionDB<'a, T, A>,
+        options: &ReadOptions,
+        observer: &mut O,
+    ) -> bool
+    where
+        T: TransactionStore,
+        A: AccountStore<'a>,
+        O: RowObserver,
+    {
+        self.flush_with(options, observer, &mut |source, tx, line, observer| {
+            add_tx(db, source, tx, line, observer)
+        })
+    }
+
+    /// process the rows left in the buffer, returns the reports of every
+    /// input, the report of an input that failed a strict check is in them
+    pub fn finish<'a, T, A, O>(
+        self,
+        db: &mut TransactionDB<'a, T, A>,
+        options: &ReadOptions,
+        observer: &mut O,
+    ) -> (Vec<ReadReport>, Option<Box<ReadError>>)
+    where
+        T: TransactionStore,
+        A: AccountStore<'a>,
+        O: RowObserver,
+    {
+        self.drain(options, observer, &mut |source, tx, line, observer| {
+            add_tx(db, source, tx, line, observer)
+        })
+    }
+
+    /// parse the rows and pass the valid transactions to `handle` through the
+    /// buffer, a segment of an input is given its `line_offset`
+    fn read_rows<R, O, F>(
+        &mut self,
+        source: &str,
+        reader: R,
+        segment: Option<u64>,
+        options: &ReadOptions,
+        observer: &mut O,
+        handle: &mut F,
+    ) -> bool
+    where
+        R: std::io::Read,
+        O: RowObserver,
+        F: FnMut(&str, Tx, u64, &mut O) -> Result<(), Box<Rejection>>,
+    {
+        if self.error.is_some() {
+            return false;
+        }
+        let continued = segment.is_some()
+            && matches!(self.reports.last(), Some(report) if report.source == source);
+        if !continued {
+            self.reports.push(ReadReport {
+                source: source.to_owned(),
+                ..Default::default()
+            });
+        }
+        let index = self.reports.len() - 1;
+        let line_offset = segment.unwrap_or(0);
+        let mut reader = ReaderBuilder::new()
+            .flexible(true)
+            .trim(Trim::All)
+            .from_reader(reader);
+        let header = match reader.headers() {
+            Ok(header) => options.mapping.header(header),
             Err(e) => {
-                warn!("can't read row: {:?}", e);
-                continue;
+                let rejection = Rejection::new(1, RejectReason::Malformed(e.to_string()));
+                return self.reject_header(index, *rejection, options);
             }
         };
-
-        debug!("{:?}", row);
-        let tx: Tx = match row.try_into() {
-            Ok(tx) => tx,
+        let header = match header {
+            Ok(header) => header,
             Err(e) => {
-                warn!("can't create valid transaction: {:?}", e);
-                continue;
+                let rejection = Rejection::new(1, RejectReason::Header(Box::new(e)));
+                return self.reject_header(index, *rejection, options);
+            }
+        };
+        // a continued input replays the header its first segment was checked
+        // with, the problems that don't stop the reading are only counted once
+        let unknown = if continued {
+            &[][..]
+        } else {
+            &header.unknown[..]
+        };
+        for column in unknown {
+            let rejection = Rejection::new(1, RejectReason::UnknownColumn(column.clone()));
+            if !self.reject_header(index, *rejection, options) {
+                return false;
+            }
+        }
+
+        let mut record = StringRecord::new();
+        loop {
+            let rows = self.reports[index].rows;
+            if matches!(options.max_rows, Some(max) if rows >= max) {
+                let line = reader.position().line() + line_offset;
+                if reader.read_record(&mut record).unwrap_or(true) {
+                    let rejection = Rejection::new(line, RejectReason::RowLimit(rows));
+                    return self.reject(index, *rejection, options);
+                }
+                break;
+            }
+            let parsed = match reader.read_record(&mut record) {
+                Ok(true) => {
+                    let line = record.position().map_or(0, |p| p.line()) + line_offset;
+                    parse_row(&mut record, &header, options)
+                        .map_err(|mut rejection| {
+                            rejection.line = line;
+                            rejection
+                        })
+                        .map(|tx| Pending {
+                            source: index,
+                            line,
+                            tx,
+                        })
+                }
+                Ok(false) => break,
+                Err(e) => Err(Rejection::new(
+                    e.position().map_or(0, |p| p.line()) + line_offset,
+                    RejectReason::Malformed(e.to_string()),
+                )),
+            };
+            self.reports[index].rows += 1;
+            let processed = match parsed {
+                Ok(pending) => match self.buffer.push(pending) {
+                    Some(ready) => self.process(ready, options, observer, handle),
+                    None => true,
+                },
+                Err(rejection) => {
+                    observer.on_row(&RowEvent::Rejected {
+                        source,
+                        rejection: &rejection,
+                    });
+                    self.reject(index, *rejection, options)
+                }
+            };
+            if !processed {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// pass the rows held by the reorder buffer to `handle`, false if one of
+    /// them stops the reading
+    fn flush_with<O, F>(&mut self, options: &ReadOptions, observer: &mut O, handle: &mut F) -> bool
+    where
+        O: RowObserver,
+        F: FnMut(&str, Tx, u64, &mut O) -> Result<(), Box<Rejection>>,
+    {
+        while self.error.is_none() {
+            match self.buffer.pop() {
+                Some(pending) => {
+                    self.process(pending, options, observer, handle);
+                }
+                None => break,
             }
+        }
+        self.error.is_none()
+    }
+
+    /// pass the rows left in the buffer to `handle`, the rows still waiting
+    /// for their transaction are rejected as `PendingUnmatched`
+    fn drain<O, F>(
+        mut self,
+        options: &ReadOptions,
+        observer: &mut O,
+        handle: &mut F,
+    ) -> (Vec<ReadReport>, Option<Box<ReadError>>)
+    where
+        O: RowObserver,
+        F: FnMut(&str, Tx, u64, &mut O) -> Result<(), Box<Rejection>>,
+    {
+        self.flush_with(options, observer, handle);
+        while self.error.is_none() {
+            match self.pending.pop() {
+                Some(pending) => {
+                    let reason = RejectReason::PendingUnmatched(pending.tx.transaction_id);
+                    self.unmatched(pending, reason, options, observer);
+                }
+                None => break,
+            }
+        }
+        (self.reports, self.error)
+    }
+
+    /// pass a row released by the buffer to `handle`, false if it stops the
+    /// reading
+    fn process<O, F>(
+        &mut self,
+        pending: Pending,
+        options: &ReadOptions,
+        observer: &mut O,
+        handle: &mut F,
+    ) -> bool
+    where
+        O: RowObserver,
+        F: FnMut(&str, Tx, u64, &mut O) -> Result<(), Box<Rejection>>,
+    {
+        self.processed += 1;
+        let limits = options.pending_references;
+        // a dispute is kept in case its transaction comes later, and a new
+        // transaction is looked up in the disputes waiting for one
+        let (retry, arrived) = match pending.tx.operation {
+            TxOperation::Dispute(_) => (limits.map(|_| pending.tx.clone()), None),
+            TxOperation::Deposit(_) | TxOperation::Withdraw(_) => (
+                None,
+                limits.map(|_| (pending.tx.tenant.clone(), pending.tx.transaction_id)),
+            ),
+        };
+        let report = &mut self.reports[pending.source];
+        let processed = match handle(&report.source, pending.tx, pending.line, observer) {
+            Ok(()) => {
+                report.accepted += 1;
+                match arrived {
+                    Some((tenant, transaction_id)) => {
+                        self.retry(&tenant, transaction_id, options, observer, handle)
+                    }
+                    None => true,
+                }
+            }
+            Err(rejection) => match (retry, limits, &rejection.reason) {
+                (
+                    Some(tx),
+                    Some(limits),
+                    RejectReason::Rejected(TxError::TransactionNotFound(_)),
+                ) => {
+                    let parked = Pending {
+                        source: pending.source,
+                        line: pending.line,
+                        tx,
+                    };
+                    match self.pending.park(parked, self.processed, &limits) {
+                        Some(dropped) => self.not_found(dropped, options, observer),
+                        None => true,
+                    }
+                }
+                _ => {
+                    observer.on_row(&RowEvent::Rejected {
+                        source: &report.source,
+                        rejection: &rejection,
+                    });
+                    self.reject(pending.source, *rejection, options)
+                }
+            },
+        };
+        processed && self.expire_pending(options, observer)
+    }
+
+    /// process the rows that waited for the transaction
+    fn retry<O, F>(
+        &mut self,
+        tenant: &str,
+        transaction_id: TransactionId,
+        options: &ReadOptions,
+        observer: &mut O,
+        handle: &mut F,
+    ) -> bool
+    where
+        O: RowObserver,
+        F: FnMut(&str, Tx, u64, &mut O) -> Result<(), Box<Rejection>>,
+    {
+        for pending in self.pending.take(tenant, transaction_id) {
+            if !self.process(pending, options, observer, handle) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// reject the rows that waited longer than the limit
+    fn expire_pending<O: RowObserver>(&mut self, options: &ReadOptions, observer: &mut O) -> bool {
+        let limits = match options.pending_references {
+            Some(limits) => limits,
+            None => return true,
         };
+        for pending in self.pending.expired(self.processed, &limits) {
+            if !self.not_found(pending, options, observer) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// reject a row whose transaction didn't come in time
+    fn not_found<O: RowObserver>(
+        &mut self,
+        pending: Pending,
+        options: &ReadOptions,
+        observer: &mut O,
+    ) -> bool {
+        let error = TxError::TransactionNotFound(pending.tx.transaction_id);
+        self.unmatched(pending, RejectReason::Rejected(error), options, observer)
+    }
+
+    /// reject a row that waited for its transaction
+    fn unmatched<O: RowObserver>(
+        &mut self,
+        pending: Pending,
+        reason: RejectReason,
+        options: &ReadOptions,
+        observer: &mut O,
+    ) -> bool {
+        let rejection = Rejection::of_tx(pending.line, &pending.tx, reason);
+        observer.on_row(&RowEvent::Rejected {
+            source: &self.reports[pending.source].source,
+            rejection: &rejection,
+        });
+        self.reject(pending.source, *rejection, options)
+    }
+
+    /// record the rejection in the report of the input, false if it failed a
+    /// strict check
+    fn reject(&mut self, index: usize, rejection: Rejection, options: &ReadOptions) -> bool {
+        self.record(index, rejection, options, false)
+    }
+
+    fn reject_header(&mut self, index: usize, rejection: Rejection, options: &ReadOptions) -> bool {
+        self.record(index, rejection, options, true)
+    }
+
+    /// add a rejection to the report of an input, false if it stops the reading
+    fn record(
+        &mut self,
+        index: usize,
+        rejection: Rejection,
+        options: &ReadOptions,
+        header: bool,
+    ) -> bool {
+        match self.reports[index].reject(rejection, options, header) {
+            Ok(()) => true,
+            Err(mut error) => {
+                // the report stays with the others until the end
+                self.reports[index] = std::mem::take(&mut error.report);
+                self.error = Some(error);
+                false
+            }
+        }
+    }
+}
+
+/// add a parsed transaction to the database and pass it to the observer
+fn add_tx<'a, T, A, O>(
+    db: &mut TransactionDB<'a, T, A>,
+    source: &str,
+    tx: Tx,
+    line: u64,
+    observer: &mut O,
+) -> Result<(), Box<Rejection>>
+where
+    T: TransactionStore,
+    A: AccountStore<'a>,
+    O: RowObserver,
+{
+    db.add(tx.clone())
+        .map_err(|e| Rejection::of_tx(line, &tx, RejectReason::Rejected(e)))?;
+    if let Some(account) = db.tenant_account(&tx.tenant, &tx.client_id) {
+        observer.on_row(&RowEvent::Accepted {
+            source,
+            line,
+            tx: &tx,
+            account,
+            record: db.tenant_transaction(&tx.tenant, &tx.transaction_id),
+        });
+    }
+    Ok(())
+}
+
+/// parse a single csv row read with the given header into a valid transaction
+pub fn parse_row(
+    record: &mut StringRecord,
+    header: &Header,
+    options: &ReadOptions,
+) -> Result<Tx, Box<Rejection>> {
+    options.mapping.normalize_type(header, record);
+    parse_record(record, &header.columns, options)
+}
+
+/// convert a deserialized row into a valid transaction, rows without a tenant
+/// or a currency get the ones of the options, the amounts are converted after
+/// the precision is checked and rounded to it, or to the default one
+pub fn convert_row(row: TxRow, options: &ReadOptions) -> Result<Tx, ConversionError> {
+    let mut tx: Tx = row.try_into()?;
+    if tx.tenant.is_empty() {
+        tx.tenant.clone_from(&options.tenant);
+    }
+    if tx.currency.is_empty() {
+        tx.currency.clone_from(&options.currency);
+    }
+    let tx = options.check_precision(tx)?;
+    match &options.fx {
+        Some(fx) => {
+            let timestamp = tx.timestamp;
+            let precision = options.input_precision.unwrap_or_default();
+            fx.convert(tx, timestamp, &precision)
+        }
+        None => Ok(tx),
+    }
+}
 
-        if let Err(e) = db.add(tx) {
-            warn!("can't process transaction, reason({:?})", e);
-            continue;
+/// parse a single row into a valid transaction
+fn parse_record(
+    record: &StringRecord,
+    headers: &StringRecord,
+    options: &ReadOptions,
+) -> Result<Tx, Box<Rejection>> {
+    let line = record.position().map_or(0, |p| p.line());
+    if record.len() != headers.len() {
+        let reason = RejectReason::ColumnCount {
+            expected: headers.len(),
+            found: record.len(),
+        };
+        // short rows are fine unless explicitly checked, missing fields are treated as empty
+        if matches!(options.strict, Some(strict) if strict.column_count) {
+            return Err(Rejection::new(line, reason));
         }
     }
+
+    check_ids(record, headers, options).map_err(|reason| Rejection::new(line, reason))?;
+    let row: TxRow = record
+        .deserialize(Some(headers))
+        .map_err(|e| Rejection::new(line, RejectReason::Malformed(e.to_string())))?;
+    debug!("{:?}", row);
+
+    let (client_id, transaction_id) = (row.client_id(), row.transaction_id());
+    let tenant = row.tenant().unwrap_or(&options.tenant).to_owned();
+    convert_row(row, options).map_err(|e| {
+        Box::new(Rejection {
+            line,
+            tenant: Some(tenant),
+            currency: None,
+            client_id: Some(client_id),
+            transaction_id: Some(transaction_id),
+            operation: None,
+            reason: RejectReason::Conversion(e),
+        })
+    })
 }
 
+/// parse the ids before the rest of the row, so an id that doesn't fit gets a
+/// clear reason, empty ids are left to the deserializer
+fn check_ids(
+    record: &StringRecord,
+    headers: &StringRecord,
+    options: &ReadOptions,
+) -> Result<(), RejectReason> {
+    let field = |column| {
+        headers
+            .iter()
+            .position(|name| name == column)
+            .and_then(|i| record.get(i))
+            .filter(|value| !value.is_empty())
+    };
+    if let Some(value) = field("client") {
+        parse_client_id(value).map_err(|error| RejectReason::InvalidId {
+            column: "client",
+            error,
+        })?;
+    }
+    if let Some(value) = field("tx") {
+        TransactionId::parse(value, options.transaction_ids).map_err(|error| {
+            RejectReason::InvalidId {
+                column: "tx",
+                error,
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// writes the accounts ordered by tenant, client and currency
 pub fn print_results<'a>(
     writer: impl std::io::Write,
     account_iter: impl Iterator<Item = &'a Account>,
+    precision: &Precision,
 ) {
+    let mut records: Vec<Record> = account_iter
+        .flat_map(|acc| Record::all(acc, precision))
+        .collect();
+    // every row needs the tenant and currency columns once one of them has it
+    if records.iter().any(|record| record.tenant.is_some()) {
+        for record in &mut records {
+            record.tenant.get_or_insert_with(TenantId::new);
+        }
+    }
+    if records.iter().any(|record| record.currency.is_some()) {
+        for record in &mut records {
+            record.currency.get_or_insert_with(Currency::new);
+        }
+    }
+    // the stores are hash maps, the order must not depend on them
+    records.sort_by(|a, b| {
+        (&a.tenant, a.client_id, &a.currency).cmp(&(&b.tenant, b.client_id, &b.currency))
+    });
     let mut writer = WriterBuilder::new().from_writer(writer);
-    let scale = |mut amount: Amount| -> Amount {
-        amount.rescale(4);
-        amount
-    };
-    for acc in account_iter {
-        let record = Record {
-            client_id: acc.client(),
-            balance: scale(acc.balance()),
-            held: scale(acc.held()),
-            total: scale(acc.total()),
-            locked: acc.is_locked(),
-        };
+    for record in records {
         if let Err(e) = writer.serialize(record) {
             warn!("can't serialize element: {:?}", e);
         }
     }
 }
+
+/// reads accounts written by `print_results`
+pub fn read_results(reader: impl std::io::Read) -> csv::Result<Vec<Record>> {
+    ReaderBuilder::new()
+        .trim(Trim::All)
+        .from_reader(reader)
+        .deserialize()
+        .collect()
+}
+
+/// writes every rejected row, tagged with the source it came from
+pub fn print_rejections<'a>(
+    writer: impl std::io::Write,
+    reports: impl Iterator<Item = &'a ReadReport>,
+) -> csv::Result<()> {
+    let reports: Vec<&ReadReport> = reports.collect();
+    let tenants = reports
+        .iter()
+        .flat_map(|report| report.all_rejections())
+        .any(|rejection| matches!(&rejection.tenant, Some(tenant) if !tenant.is_empty()));
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for report in reports {
+        for rejection in report.all_rejections() {
+            writer.serialize(RejectRecord {
+                source: &report.source,
+                line: rejection.line,
+                tenant: if tenants {
+                    Some(rejection.tenant.as_deref().unwrap_or_default())
+                } else {
+                    None
+                },
+                client_id: rejection.client_id,
+                transaction_id: rejection.transaction_id,
+                category: rejection.reason.category(),
+                reason: rejection.reason.to_string(),
+            })?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// writes the disputes that were resolved automatically
+pub fn print_expired_disputes(
+    writer: impl std::io::Write,
+    disputes: &[ExpiredDispute],
+) -> csv::Result<()> {
+    let tenants = disputes.iter().any(|dispute| !dispute.tenant.is_empty());
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for dispute in disputes {
+        writer.serialize(ExpiredRecord {
+            tenant: Some(dispute.tenant.as_str()).filter(|_| tenants),
+            client_id: dispute.client_id,
+            transaction_id: dispute.transaction_id,
+            opened_seq: dispute.opened.sequence,
+            opened_at: dispute.opened.timestamp.map(format_timestamp),
+            expired_seq: dispute.expired.sequence,
+            expired_at: dispute.expired.timestamp.map(format_timestamp),
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// writes the flags raised by the fraud detectors
+pub fn print_flags(writer: impl std::io::Write, flags: &[Flag]) -> csv::Result<()> {
+    let tenants = flags.iter().any(|flag| !flag.tenant.is_empty());
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for flag in flags {
+        writer.serialize(FlagRecord {
+            tenant: Some(flag.tenant.as_str()).filter(|_| tenants),
+            client_id: flag.client_id,
+            transaction_id: flag.transaction_id,
+            detector: flag.detector,
+            score: flag.score,
+            total: flag.total,
+            reason: &flag.reason,
+            seq: flag.at.sequence,
+            at: flag.at.timestamp.map(format_timestamp),
+            frozen: flag.frozen,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// writes the row counters of every source
+pub fn print_metrics<'a>(
+    writer: impl std::io::Write,
+    reports: impl Iterator<Item = &'a ReadReport>,
+) -> csv::Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for report in reports {
+        writer.serialize(MetricsRecord {
+            source: &report.source,
+            rows: report.rows,
+            accepted: report.accepted,
+            rejected: report.rejections.len() as u64,
+            header: report.header.len() as u64,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rust_decimal::Decimal;
+
+    use crate::db::{
+        fraud::{Detectors, DisputeRate, FailedWithdrawals, RepeatedDisputes},
+        EngineConfig, TransactionDB,
+    };
+
+    use super::{
+        pending::PendingLimits, print_expired_disputes, print_flags, print_results, read_csv_data,
+        MergedReader, ReadOptions, StrictChecks,
+    };
+    use crate::model::id::IdFormat;
+
+    const DATA: &str = "type,client,tx,amount
+deposit,1,1,10
+withdrawal,1,2,20
+dispute,1,1
+deposit,1,3,5
+";
+
+    #[test]
+    fn test_strict_mode() {
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let report = read_csv_data(
+            "data",
+            DATA.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut (),
+        )
+        .expect("lenient reading should not fail");
+        assert_eq!(report.rows, 4);
+        assert_eq!(report.accepted, 3);
+        assert_eq!(report.rejections.len(), 1);
+        assert_eq!(report.rejections[0].reason.category(), "insufficient_funds");
+
+        let options = ReadOptions {
+            strict: Some("rejected".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let err = read_csv_data("data", DATA.as_bytes(), &mut db, &options, &mut ())
+            .expect_err("insufficient funds should stop the processing");
+        assert_eq!(err.rejection.line, 3);
+        assert_eq!(err.report.accepted, 1);
+
+        let options = ReadOptions {
+            strict: Some(StrictChecks::all()),
+            ..Default::default()
+        };
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let data = "type,client,tx,amount,note\ndeposit,1,1,10,x\n";
+        let err = read_csv_data("data", data.as_bytes(), &mut db, &options, &mut ())
+            .expect_err("unknown column should stop the processing");
+        assert_eq!(err.rejection.reason.category(), "unknown_column");
+        assert_eq!(err.report.rows, 0);
+    }
+
+    #[test]
+    fn test_ids() {
+        let data = "type,client,tx,amount
+deposit,70000,18446744073709551615,10
+deposit,4294967296,2,10
+deposit,1,18446744073709551616,10
+deposit,1,67e55044-10b1-426f-9247-bb680e5fe0c8,10
+dispute,1,67E55044-10B1-426F-9247-BB680E5FE0C8,
+deposit,1,-3,10
+";
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let report = read_csv_data(
+            "data",
+            data.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut (),
+        )
+        .unwrap();
+        assert_eq!(report.accepted, 3);
+        let reasons: Vec<_> = report
+            .rejections
+            .iter()
+            .map(|r| (r.line, r.reason.category(), r.reason.to_string()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (
+                    3,
+                    "invalid_id",
+                    "invalid client id: 4294967296 is out of range, the largest id is 4294967295"
+                        .to_owned()
+                ),
+                (
+                    4,
+                    "invalid_id",
+                    "invalid tx id: 18446744073709551616 is out of range, \
+                     the largest id is 18446744073709551615"
+                        .to_owned()
+                ),
+                (
+                    7,
+                    "invalid_id",
+                    "invalid tx id: -3 is not a valid id".to_owned()
+                ),
+            ]
+        );
+        assert_eq!(db.account(&1).unwrap().held(""), 10.into());
+
+        let options = ReadOptions {
+            transaction_ids: IdFormat::Number,
+            ..Default::default()
+        };
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let report = read_csv_data("data", data.as_bytes(), &mut db, &options, &mut ()).unwrap();
+        assert_eq!(report.accepted, 1);
+    }
+
+    #[test]
+    fn test_tenants() {
+        let data = "tenant,type,client,tx,amount
+shop,deposit,1,1,10
+,deposit,1,1,5
+bank,dispute,1,1,
+other,dispute,1,1,
+";
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let options = ReadOptions {
+            tenant: "bank".into(),
+            ..Default::default()
+        };
+        let report = read_csv_data("data", data.as_bytes(), &mut db, &options, &mut ()).unwrap();
+        assert_eq!(report.accepted, 3);
+        assert_eq!(report.rejections.len(), 1);
+        assert_eq!(report.rejections[0].line, 5);
+        assert_eq!(report.rejections[0].tenant.as_deref(), Some("other"));
+        assert_eq!(
+            report.rejections[0].reason.category(),
+            "transaction_not_found"
+        );
+
+        assert_eq!(db.tenant_account("shop", &1).unwrap().held(""), 0.into());
+        assert_eq!(db.tenant_account("bank", &1).unwrap().held(""), 5.into());
+        assert!(db.account(&1).is_none());
+
+        let mut results = Vec::new();
+        print_results(&mut results, db.accounts(), &Default::default());
+        assert_eq!(
+            String::from_utf8(results).unwrap(),
+            "tenant,client,available,held,total,locked
+bank,1,0.0000,5.0000,5.0000,false
+other,1,0.0000,0.0000,0.0000,false
+shop,1,10.0000,0.0000,10.0000,false
+"
+        );
+    }
+
+    #[test]
+    fn test_currencies() {
+        let data = "type,client,tx,amount,currency
+deposit,1,1,10,usd
+deposit,1,2,5,
+withdrawal,1,3,1,EUR
+deposit,2,4,1,euro
+dispute,1,2,,
+";
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let options = ReadOptions {
+            currency: "EUR".into(),
+            ..Default::default()
+        };
+        let report = read_csv_data("data", data.as_bytes(), &mut db, &options, &mut ()).unwrap();
+        assert_eq!(report.accepted, 4);
+        assert_eq!(report.rejections[0].line, 5);
+        assert_eq!(
+            report.rejections[0].reason.to_string(),
+            "euro is not a currency code"
+        );
+
+        let mut results = Vec::new();
+        print_results(&mut results, db.accounts(), &Default::default());
+        assert_eq!(
+            String::from_utf8(results).unwrap(),
+            "client,currency,available,held,total,locked
+1,EUR,-1.0000,5.0000,4.0000,false
+1,USD,10.0000,0.0000,10.0000,false
+"
+        );
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let data = "type,client,tx,amount,timestamp
+deposit,1,1,10,2023-11-14T22:13:20Z
+withdrawal,1,2,15,1700000030
+deposit,1,3,10,1700000010
+deposit,1,4,1,1699999995
+";
+        let config = EngineConfig {
+            time_tolerance: Some(10),
+            ..Default::default()
+        };
+        let new_db =
+            || TransactionDB::new(HashMap::default(), HashMap::default()).with_config(config);
+
+        let mut db = new_db();
+        let report = read_csv_data(
+            "data",
+            data.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut (),
+        )
+        .unwrap();
+        let reasons: Vec<_> = report
+            .rejections
+            .iter()
+            .map(|r| (r.line, r.reason.category()))
+            .collect();
+        assert_eq!(reasons, [(3, "insufficient_funds"), (5, "out_of_order")]);
+        assert_eq!(db.account(&1).unwrap().total(""), 20.into());
+        assert_eq!(
+            db.transaction(&1.into()).unwrap().timestamp,
+            Some(1700000000)
+        );
+
+        let options = ReadOptions {
+            reorder_window: Some(2),
+            ..Default::default()
+        };
+        let mut db = new_db();
+        let report = read_csv_data("data", data.as_bytes(), &mut db, &options, &mut ()).unwrap();
+        assert_eq!(report.rows, 4);
+        assert_eq!(report.accepted, 4);
+        assert_eq!(db.account(&1).unwrap().total(""), 6.into());
+
+        let first = "type,client,tx,amount,timestamp
+deposit,1,1,10,1700000000
+withdrawal,1,2,15,1700000030
+";
+        let second = "type,client,tx,amount,timestamp
+deposit,1,3,10,1700000010
+";
+        let mut db = new_db();
+        let mut merged = MergedReader::new(2);
+        assert!(merged.read("first", first.as_bytes(), &mut db, &options, &mut ()));
+        assert!(merged.read("second", second.as_bytes(), &mut db, &options, &mut ()));
+        let (reports, error) = merged.finish(&mut db, &options, &mut ());
+        assert!(error.is_none());
+        let accepted: Vec<_> = reports
+            .iter()
+            .map(|r| (r.source.as_str(), r.accepted))
+            .collect();
+        assert_eq!(accepted, [("first", 2), ("second", 1)]);
+        assert_eq!(db.account(&1).unwrap().total(""), 5.into());
+    }
+
+    #[test]
+    fn test_dispute_expiry() {
+        let data = "type,client,tx,amount,timestamp
+deposit,1,1,10,1000
+dispute,1,1,,1000
+withdrawal,1,9,100,9999
+deposit,1,2,5,1050
+deposit,1,3,5,1101
+chargeback,1,1,,1102
+";
+        let mut config = EngineConfig::default();
+        config.dispute.expire_after = Some("100s".parse().unwrap());
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default()).with_config(config);
+        let report = read_csv_data(
+            "data",
+            data.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut (),
+        )
+        .unwrap();
+        assert_eq!(report.accepted, 4);
+        // the rejected withdrawal didn't expire the dispute
+        assert_eq!(report.rejections[0].reason.category(), "insufficient_funds");
+        assert_eq!(report.rejections[1].reason.category(), "invalid_state");
+        let account = db.account(&1).unwrap();
+        assert_eq!((account.held(""), account.total("")), (0.into(), 20.into()));
+
+        let mut expired = Vec::new();
+        print_expired_disputes(&mut expired, db.expired_disputes()).unwrap();
+        assert_eq!(
+            String::from_utf8(expired).unwrap(),
+            "client,tx,opened_seq,opened_at,expired_seq,expired_at
+1,1,1,1970-01-01T00:16:40Z,3,1970-01-01T00:18:21Z
+"
+        );
+    }
+
+    #[test]
+    fn test_flags() {
+        let data = "type,client,tx,amount
+deposit,1,1,10
+withdrawal,1,2,20
+withdrawal,1,3,20
+deposit,1,4,10
+dispute,1,1,
+dispute,1,4,
+deposit,1,5,10
+";
+        let mut detectors = Detectors::default();
+        detectors.add(Box::new(FailedWithdrawals::new(
+            2,
+            "10tx".parse().unwrap(),
+            40,
+        )));
+        detectors.add(Box::new(RepeatedDisputes::new(
+            2,
+            "10tx".parse().unwrap(),
+            60,
+        )));
+        detectors.add(Box::new(DisputeRate::new(Decimal::new(5, 1), 2, 30)));
+        let config = EngineConfig {
+            freeze_score: Some(100),
+            ..EngineConfig::default()
+        };
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default())
+            .with_config(config)
+            .with_detectors(detectors);
+        let report = read_csv_data(
+            "data",
+            data.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut (),
+        )
+        .unwrap();
+        // the account is frozen by the second dispute, the flag that reached
+        // the score is marked and not the last one
+        assert_eq!(
+            report.rejections.last().unwrap().reason.category(),
+            "account_locked"
+        );
+        assert!(db.account(&1).unwrap().is_locked());
+
+        let mut flags = Vec::new();
+        print_flags(&mut flags, db.flags()).unwrap();
+        assert_eq!(
+            String::from_utf8(flags).unwrap(),
+            "client,tx,detector,score,total,reason,seq,at,frozen
+1,3,failed_withdrawals,40,40,2 withdrawals refused for insufficient funds within 10tx,1,,false
+1,4,repeated_disputes,60,100,2 disputes within 10tx,3,,true
+1,4,dispute_rate,30,130,2 disputes for 2 transactions,3,,false
+"
+        );
+    }
+
+    #[test]
+    fn test_pending_references() {
+        let data = "type,client,tx,amount
+dispute,1,1,
+resolve,1,1,
+dispute,1,9,
+deposit,1,1,10
+dispute,1,1,
+deposit,1,2,5
+";
+        let reasons = |options: &ReadOptions| {
+            let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+            let report = read_csv_data("data", data.as_bytes(), &mut db, options, &mut ()).unwrap();
+            let reasons: Vec<_> = report
+                .rejections
+                .iter()
+                .map(|r| (r.line, r.reason.category()))
+                .collect();
+            (report.accepted, reasons)
+        };
+        assert_eq!(
+            reasons(&ReadOptions::default()),
+            (
+                3,
+                vec![
+                    (2, "transaction_not_found"),
+                    (3, "transaction_not_found"),
+                    (4, "transaction_not_found")
+                ]
+            )
+        );
+
+        // the dispute and the resolve wait for the deposit, the other dispute
+        // never gets its transaction
+        let options = ReadOptions {
+            pending_references: Some(PendingLimits {
+                max_rows: 10,
+                max_age: None,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            reasons(&options),
+            (4, vec![(6, "invalid_state"), (4, "pending_unmatched")])
+        );
+
+        // the segments of a followed input share the disputes waiting and
+        // number their rows as lines of the file
+        let first = "type,client,tx,amount
+dispute,1,1,
+dispute,1,9,
+";
+        let second = "type,client,tx,amount
+deposit,1,1,10
+";
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let mut merged = MergedReader::new(0);
+        assert!(merged.read_segment("data", first.as_bytes(), 0, &mut db, &options, &mut ()));
+        assert_eq!(merged.reports()[0].accepted, 0);
+        assert!(merged.read_segment("data", second.as_bytes(), 2, &mut db, &options, &mut ()));
+        let (reports, error) = merged.finish(&mut db, &options, &mut ());
+        assert!(error.is_none());
+        assert_eq!(reports.len(), 1);
+        assert_eq!((reports[0].rows, reports[0].accepted), (3, 2));
+        let rejection = &reports[0].rejections[0];
+        assert_eq!(
+            (rejection.line, rejection.reason.category()),
+            (3, "pending_unmatched")
+        );
+        assert_eq!(db.account(&1).unwrap().held(""), 10.into());
+    }
+
+    #[test]
+    fn test_segments() {
+        let first = "type,client,tx,amount,memo
+deposit,1,1,10,a
+";
+        let second = "type,client,tx,amount,memo
+deposit,1,2,5,b
+";
+        // the unknown column is reported once, with the first segment
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let mut merged = MergedReader::new(0);
+        let options = ReadOptions::default();
+        assert!(merged.read_segment("data", first.as_bytes(), 0, &mut db, &options, &mut ()));
+        assert!(merged.read_segment("data", second.as_bytes(), 1, &mut db, &options, &mut ()));
+        let (reports, error) = merged.finish(&mut db, &options, &mut ());
+        assert!(error.is_none());
+        assert_eq!((reports[0].rows, reports[0].accepted), (2, 2));
+        assert_eq!(reports[0].header.len(), 1);
+        assert_eq!(db.account(&1).unwrap().total(""), 15.into());
+
+        // a new input checks its header again
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let mut merged = MergedReader::new(0);
+        assert!(merged.read_segment("a", first.as_bytes(), 0, &mut db, &options, &mut ()));
+        assert!(merged.read_segment("b", second.as_bytes(), 0, &mut db, &options, &mut ()));
+        let (reports, _) = merged.finish(&mut db, &options, &mut ());
+        let headers: Vec<_> = reports.iter().map(|r| r.header.len()).collect();
+        assert_eq!(headers, [1, 1]);
+    }
+}
@@ -0,0 +1,135 @@
+//! Reordering of the transactions by timestamp within a bounded buffer.
+//!
+//! Rows are held until the buffer is full, then the earliest one is released,
+//! so a row that is late by less than the size of the buffer is processed in
+//! its place. Rows without a timestamp stay behind the rows read before them.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::model::{Timestamp, Tx};
+
+/// A parsed transaction waiting in the buffer
+#[derive(Debug)]
+pub struct Pending {
+    /// index of the input the row comes from
+    pub source: usize,
+    pub line: u64,
+    pub tx: Tx,
+}
+
+/// The buffer entry, ordered so the heap pops the earliest row first and rows
+/// with the same time in the order they were read
+#[derive(Debug)]
+struct Entry {
+    key: (Timestamp, u64),
+    pending: Pending,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.cmp(&self.key)
+    }
+}
+
+/// Holds up to `window` rows and releases them by timestamp, a window of zero
+/// keeps the order of the input
+#[derive(Debug, Default)]
+pub struct Reorder {
+    window: usize,
+    entries: BinaryHeap<Entry>,
+    /// number of rows pushed so far, breaks the ties
+    seq: u64,
+    /// the timestamp of the last row that had one
+    last: Option<Timestamp>,
+}
+
+impl Reorder {
+    pub fn new(window: usize) -> Self {
+        Reorder {
+            window,
+            ..Reorder::default()
+        }
+    }
+
+    /// add a row, returns the earliest one once the buffer is over its size
+    pub fn push(&mut self, pending: Pending) -> Option<Pending> {
+        if let Some(timestamp) = pending.tx.timestamp {
+            self.last = Some(timestamp);
+        }
+        let time = pending.tx.timestamp.or(self.last).unwrap_or(Timestamp::MIN);
+        self.entries.push(Entry {
+            key: (time, self.seq),
+            pending,
+        });
+        self.seq += 1;
+        if self.entries.len() > self.window {
+            self.pop()
+        } else {
+            None
+        }
+    }
+
+    /// take the earliest row, used to drain the buffer at the end
+    pub fn pop(&mut self) -> Option<Pending> {
+        self.entries.pop().map(|entry| entry.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Amount, Timestamp, Tx, TxOperation};
+
+    use super::{Pending, Reorder};
+
+    #[test]
+    fn test_reorder() {
+        let pending = |line: u64, timestamp: Option<Timestamp>| Pending {
+            source: 0,
+            line,
+            tx: Tx {
+                tenant: String::new(),
+                transaction_id: line.into(),
+                client_id: 1,
+                currency: String::new(),
+                operation: TxOperation::Deposit(Amount::from(1)),
+                conversion: None,
+                timestamp,
+            },
+        };
+        let mut buffer = Reorder::new(2);
+        let mut order = Vec::new();
+        let rows = [
+            pending(1, Some(30)),
+            pending(2, Some(10)),
+            pending(3, None),
+            pending(4, Some(20)),
+            pending(5, Some(5)),
+        ];
+        for row in rows {
+            order.extend(buffer.push(row).map(|p| p.line));
+        }
+        while let Some(p) = buffer.pop() {
+            order.push(p.line);
+        }
+        // 5 is late by more than the window, it comes out after 2 and 3
+        assert_eq!(order, [2, 3, 5, 4, 1]);
+
+        let mut buffer = Reorder::new(0);
+        assert_eq!(buffer.push(pending(1, Some(30))).map(|p| p.line), Some(1));
+        assert!(buffer.pop().is_none());
+    }
+}
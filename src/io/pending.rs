@@ -0,0 +1,132 @@
+//! Rows that refer to a transaction that was not seen yet.
+//!
+//! A dispute, resolve or chargeback that comes before the transaction it
+//! refers to is parked and retried when the transaction is accepted. The
+//! buffer is bounded by the number of rows it holds and, optionally, by the
+//! number of rows processed since a row was parked; the rows that don't fit
+//! are rejected.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::order::Pending;
+use crate::model::{TenantId, TransactionId};
+
+/// Limits of the buffer of rows waiting for their transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingLimits {
+    /// the most rows waiting at the same time, the oldest one is dropped above it
+    pub max_rows: usize,
+    /// the most rows processed while a row is waiting
+    pub max_age: Option<u64>,
+}
+
+/// The parked rows, by the order they were parked in and by the transaction
+/// they wait for
+#[derive(Debug, Default)]
+pub struct PendingReferences {
+    rows: BTreeMap<u64, (u64, Pending)>,
+    waiting: HashMap<(TenantId, TransactionId), Vec<u64>>,
+    next: u64,
+}
+
+impl PendingReferences {
+    /// park a row at the given count of processed rows, returns the oldest
+    /// row when the buffer is over its size
+    pub fn park(&mut self, pending: Pending, now: u64, limits: &PendingLimits) -> Option<Pending> {
+        let key = (pending.tx.tenant.clone(), pending.tx.transaction_id);
+        self.waiting.entry(key).or_default().push(self.next);
+        self.rows.insert(self.next, (now, pending));
+        self.next += 1;
+        if self.rows.len() > limits.max_rows {
+            self.pop()
+        } else {
+            None
+        }
+    }
+
+    /// the rows waiting for the transaction, in the order they were parked
+    pub fn take(&mut self, tenant: &str, transaction_id: TransactionId) -> Vec<Pending> {
+        if self.waiting.is_empty() {
+            return Vec::new();
+        }
+        self.waiting
+            .remove(&(tenant.to_owned(), transaction_id))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|id| self.rows.remove(&id))
+            .map(|(_, pending)| pending)
+            .collect()
+    }
+
+    /// the rows that waited for more than `max_age` processed rows
+    pub fn expired(&mut self, now: u64, limits: &PendingLimits) -> Vec<Pending> {
+        let max_age = match limits.max_age {
+            Some(max_age) => max_age,
+            None => return Vec::new(),
+        };
+        let mut expired = Vec::new();
+        while matches!(self.rows.values().next(), Some((parked, _)) if now - parked > max_age) {
+            expired.extend(self.pop());
+        }
+        expired
+    }
+
+    /// take the oldest row, used to drain the buffer at the end
+    pub fn pop(&mut self) -> Option<Pending> {
+        let id = *self.rows.keys().next()?;
+        let (_, pending) = self.rows.remove(&id)?;
+        let key = (pending.tx.tenant.clone(), pending.tx.transaction_id);
+        if let Some(ids) = self.waiting.get_mut(&key) {
+            ids.retain(|waiting| *waiting != id);
+            if ids.is_empty() {
+                self.waiting.remove(&key);
+            }
+        }
+        Some(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        io::order::Pending,
+        model::{DisputeState, Tx, TxOperation},
+    };
+
+    use super::{PendingLimits, PendingReferences};
+
+    #[test]
+    fn test_pending_references() {
+        let pending = |line: u64, transaction_id: u64| Pending {
+            source: 0,
+            line,
+            tx: Tx {
+                tenant: String::new(),
+                transaction_id: transaction_id.into(),
+                client_id: 1,
+                currency: String::new(),
+                operation: TxOperation::Dispute(DisputeState::Initiated),
+                conversion: None,
+                timestamp: None,
+            },
+        };
+        let lines = |rows: Vec<Pending>| rows.iter().map(|p| p.line).collect::<Vec<_>>();
+        let limits = PendingLimits {
+            max_rows: 2,
+            max_age: Some(3),
+        };
+        let mut buffer = PendingReferences::default();
+        assert!(buffer.park(pending(1, 10), 0, &limits).is_none());
+        assert!(buffer.park(pending(2, 20), 1, &limits).is_none());
+        // the oldest row is dropped when the buffer is full
+        assert_eq!(
+            buffer.park(pending(3, 20), 2, &limits).map(|p| p.line),
+            Some(1)
+        );
+        assert!(buffer.take("", 10.into()).is_empty());
+        assert_eq!(lines(buffer.expired(4, &limits)), [] as [u64; 0]);
+        assert_eq!(lines(buffer.expired(5, &limits)), [2]);
+        assert_eq!(lines(buffer.take("", 20.into())), [3]);
+        assert!(buffer.pop().is_none());
+    }
+}
@@ -0,0 +1,42 @@
+//! Transaction engine for client accounts: deposits, withdrawals and the
+//! dispute, resolve and chargeback flows.
+//!
+//! ```
+//! use std::collections::HashMap;
+//!
+//! use atm::{
+//!     db::TransactionDB,
+//!     model::{Tx, TxOperation},
+//! };
+//!
+//! let mut db = TransactionDB::new(HashMap::new(), HashMap::new());
+//! db.add(Tx {
+//!     tenant: String::new(),
+//!     transaction_id: 1.into(),
+//!     client_id: 7,
+//!     currency: "USD".into(),
+//!     operation: TxOperation::Deposit(10.into()),
+//!     conversion: None,
+//!     timestamp: None,
+//! })
+//! .unwrap();
+//! assert_eq!(db.account(&7).unwrap().balance("USD"), 10.into());
+//! ```
+//!
+//! The `cli` feature (on by default) builds the `csvatm` binary, the `server`
+//! feature adds the http and tcp front end used by `csvatm serve`.
+
+#[macro_use]
+extern crate log;
+
+pub mod config;
+pub mod db;
+pub mod ffi;
+pub mod fx;
+pub mod generate;
+pub mod io;
+pub mod model;
+pub mod repl;
+pub mod report;
+#[cfg(feature = "server")]
+pub mod server;
@@ -2,47 +2,109 @@
 extern crate log;
 extern crate env_logger;
 
-mod db;
-mod io;
-mod model;
+mod cmd;
 
-use std::{collections::HashMap, fs::File, path::PathBuf, time::Instant};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+};
 
 use env_logger::Env;
-use structopt::StructOpt;
+use structopt::{
+    clap::{self, AppSettings},
+    StructOpt,
+};
 
-use crate::{
-    db::TransactionDB,
-    io::{print_results, read_csv_data},
+use atm::config::Config;
+
+use crate::cmd::{
+    diff::DiffOpt, generate::GenerateOpt, process::ProcessOpt, reconcile::ReconcileOpt,
+    repl::ReplOpt, serve::ServeOpt, statement::StatementOpt, stats::StatsOpt,
+    validate::ValidateOpt, ExitCode,
 };
 
 fn main() {
-    //read cli arguments
-    let opt = Opt::from_args();
+    //read cli arguments, help and version exit with 0
+    let opt = match Opt::from_args_safe() {
+        Ok(opt) => opt,
+        Err(e) if is_info(&e) => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            std::process::exit(ExitCode::Config as i32);
+        }
+    };
     //init logger
     let log_level = if opt.debug { "debug" } else { "error" };
     env_logger::Builder::from_env(Env::default().default_filter_or(log_level)).init();
 
-    let input_file = match File::open(opt.input) {
-        Ok(f) => f,
-        Err(e) => {
-            error!("can't open file: {:?}", e);
-            return;
-        }
+    let config = match &opt.config {
+        Some(path) => match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("{}: {}", path.display(), e);
+                std::process::exit(ExitCode::Config as i32);
+            }
+        },
+        None => Config::default(),
     };
 
-    let start = Instant::now();
-    let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
-    read_csv_data(input_file, &mut db);
-    print_results(std::io::stdout(), db.accounts().into_iter());
-    debug!("processed in {:?}", start.elapsed());
+    // a panic means one of the account invariants was broken
+    let code = panic::catch_unwind(AssertUnwindSafe(|| match opt.command {
+        Some(Command::Process(opt)) => cmd::process::run(opt, &config),
+        Some(Command::Validate(opt)) => cmd::validate::run(opt, &config),
+        Some(Command::Stats(opt)) => cmd::stats::run(opt, &config),
+        Some(Command::Statement(opt)) => cmd::statement::run(opt, &config),
+        Some(Command::Reconcile(opt)) => cmd::reconcile::run(opt, &config),
+        Some(Command::Serve(opt)) => cmd::serve::run(opt, &config),
+        Some(Command::Repl(opt)) => cmd::repl::run(opt, &config),
+        Some(Command::Generate(opt)) => cmd::generate::run(opt, &config),
+        Some(Command::Diff(opt)) => cmd::diff::run(opt, &config),
+        // `csvatm FILE...` is the same as `csvatm process FILE...`
+        None => cmd::process::run(opt.process, &config),
+    }))
+    .unwrap_or(ExitCode::Invariant);
+    std::process::exit(code as i32);
+}
+
+fn is_info(e: &clap::Error) -> bool {
+    matches!(
+        e.kind,
+        clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed
+    )
 }
 
 #[derive(StructOpt)]
-#[structopt(name = "csvatm")]
+#[structopt(name = "csvatm", setting = AppSettings::SubcommandsNegateReqs)]
 struct Opt {
-    #[structopt(short, long)]
+    #[structopt(short, long, global = true)]
     pub debug: bool,
-    #[structopt(name = "FILE", parse(from_os_str))]
-    input: PathBuf,
+    /// toml file with the engine and i/o settings, command line flags override it
+    #[structopt(long, global = true, parse(from_os_str))]
+    config: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    #[structopt(flatten)]
+    process: ProcessOpt,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// process the inputs and print the accounts
+    Process(ProcessOpt),
+    /// parse the inputs without processing them
+    Validate(ValidateOpt),
+    /// print a summary of the inputs
+    Stats(StatsOpt),
+    /// print the statement of a single client
+    Statement(StatementOpt),
+    /// compare the accounts with an expected result
+    Reconcile(ReconcileOpt),
+    /// accept transactions over http and tcp
+    Serve(ServeOpt),
+    /// apply and inspect transactions interactively
+    Repl(ReplOpt),
+    /// write a reproducible synthetic input
+    Generate(GenerateOpt),
+    /// compare two results files or the results of two configs
+    Diff(DiffOpt),
 }
@@ -1,51 +1,112 @@
+use std::collections::BTreeMap;
+
 use rust_decimal::Decimal;
 use thiserror::Error;
 
 use crate::db::{TransactionStore, TransactionStoreError};
 
 use super::{
-    Amount, ClientId, DisputeState, TransactionId, Tx, TxOperation, TxRecord, TxRecordType,
+    time::{format_timestamp, Window},
+    Amount, ClientId, Currency, DisputeState, Moment, TenantId, Timestamp, TransactionId, Tx,
+    TxOperation, TxRecord, TxRecordType,
 };
 
 /// Account is he main entity that is responsible for transaction processing,
 /// keep the internals private, should be modified only by transaction
 #[derive(Debug, Clone)]
 pub struct Account {
+    tenant: TenantId,
     client_id: ClientId,
-    total: Amount,
-    held: Amount,
+    balances: BTreeMap<Currency, Balance>,
     locked: bool,
 }
 
+/// The amounts an account holds in a single currency
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub total: Amount,
+    pub held: Amount,
+}
+
+impl Balance {
+    /// the funds that are not held
+    pub fn available(&self) -> Amount {
+        self.total - self.held
+    }
+}
+
 impl Account {
     /// construct new Account
     pub fn new(client_id: ClientId) -> Self {
         Account {
+            tenant: TenantId::new(),
             client_id,
-            total: Amount::default(),
-            held: Amount::default(),
+            balances: BTreeMap::new(),
             locked: false,
         }
     }
 
+    /// construct an Account as it was saved in a snapshot of the results, the
+    /// balances are added with `restore_balance`
+    pub fn restore(client_id: ClientId, locked: bool) -> Self {
+        Account {
+            locked,
+            ..Account::new(client_id)
+        }
+    }
+
+    /// set the balance of a currency restored from a snapshot
+    pub fn restore_balance(mut self, currency: Currency, total: Amount, held: Amount) -> Self {
+        self.balances.insert(currency, Balance { total, held });
+        self
+    }
+
+    /// move the account to the given tenant
+    pub fn in_tenant(mut self, tenant: TenantId) -> Self {
+        self.tenant = tenant;
+        self
+    }
+
+    /// the tenant of the account, empty if there is none
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
     /// returns client id
     pub fn client(&self) -> ClientId {
         self.client_id
     }
 
+    /// the balances of every currency the account has used, ordered by currency
+    pub fn balances(&self) -> impl Iterator<Item = (&str, &Balance)> {
+        self.balances
+            .iter()
+            .map(|(currency, balance)| (currency.as_str(), balance))
+    }
+
+    /// the amounts in the currency, zero if it was never used
+    pub fn balance_in(&self, currency: &str) -> Balance {
+        self.balances.get(currency).copied().unwrap_or_default()
+    }
+
     /// chek current balance, i.e. available funds
-    pub fn balance(&self) -> Amount {
-        self.total - self.held
+    pub fn balance(&self, currency: &str) -> Amount {
+        self.balance_in(currency).available()
     }
 
     /// check the total amount in the account
-    pub fn total(&self) -> Amount {
-        self.total
+    pub fn total(&self, currency: &str) -> Amount {
+        self.balance_in(currency).total
     }
 
     /// the held amount
-    pub fn held(&self) -> Amount {
-        self.held
+    pub fn held(&self, currency: &str) -> Amount {
+        self.balance_in(currency).held
+    }
+
+    /// lock the account outside of a chargeback, e.g. when it is suspicious
+    pub fn freeze(&mut self) {
+        self.locked = true;
     }
 
     /// if account is locked no transactions should be processed
@@ -53,8 +114,32 @@ impl Account {
         self.locked
     }
 
-    /// process new transaction
-    pub fn process<T>(&mut self, tx: Tx, store: &mut T) -> Result<(), TxError>
+    /// process new transaction, disputes are handled according to the policy
+    pub fn process<T>(
+        &mut self,
+        tx: Tx,
+        store: &mut T,
+        policy: &DisputePolicy,
+    ) -> Result<(), TxError>
+    where
+        T: TransactionStore,
+    {
+        let at = Moment {
+            sequence: 0,
+            timestamp: tx.timestamp,
+        };
+        self.process_at(tx, store, policy, at)
+    }
+
+    /// process new transaction as the given moment, the dispute windows of the
+    /// policy are measured from it
+    pub fn process_at<T>(
+        &mut self,
+        tx: Tx,
+        store: &mut T,
+        policy: &DisputePolicy,
+        at: Moment,
+    ) -> Result<(), TxError>
     where
         T: TransactionStore,
     {
@@ -71,12 +156,17 @@ impl Account {
                         origin: TxRecordType::Deposit(amount),
                         dispute: None,
                         client_id: self.client_id,
+                        currency: tx.currency.clone(),
+                        conversion: tx.conversion.clone(),
+                        timestamp: tx.timestamp,
+                        sequence: at.sequence,
+                        disputed_at: None,
                     },
                 )?;
-                self.total += amount;
+                self.balances.entry(tx.currency).or_default().total += amount;
             }
             TxOperation::Withdraw(amount) => {
-                let balance = self.balance();
+                let balance = self.balance(&tx.currency);
                 if amount > balance {
                     return Err(TxError::InsufficientFunds(tx.transaction_id));
                 }
@@ -86,51 +176,37 @@ impl Account {
                         origin: TxRecordType::Withdraw(amount),
                         dispute: None,
                         client_id: self.client_id,
+                        currency: tx.currency.clone(),
+                        conversion: tx.conversion.clone(),
+                        timestamp: tx.timestamp,
+                        sequence: at.sequence,
+                        disputed_at: None,
                     },
                 )?;
-                self.total -= amount;
+                self.balances.entry(tx.currency).or_default().total -= amount;
             }
             TxOperation::Dispute(new_dispute) => {
                 match store.get_tx_mut(&self.client_id, &tx.transaction_id)? {
-                    Some(prev_tx) => match new_dispute {
-                        DisputeState::Initiated => match prev_tx.dispute {
-                            None => {
-                                //XXX at this point the balance may become negative value
-                                self.held += prev_tx.amount();
-                                prev_tx.dispute = Some(new_dispute);
-                            }
-                            _ => {
-                                return Err(TxError::InvalidState(new_dispute, prev_tx.dispute));
-                            }
-                        },
-                        DisputeState::Resolved => {
-                            match prev_tx.dispute {
-                                //resolve only if initiated
-                                Some(DisputeState::Initiated) => {
-                                    prev_tx.dispute = Some(new_dispute);
-                                    self.held -= prev_tx.amount();
-                                    assert!(self.held >= Decimal::from(0));
+                    Some(prev_tx) => {
+                        // the dispute applies in the currency of the disputed transaction
+                        let balance = self.balances.entry(prev_tx.currency.clone()).or_default();
+                        match new_dispute {
+                            DisputeState::Initiated => match prev_tx.dispute {
+                                None if matches!(policy.open_window, Some(window)
+                                    if window.is_over(prev_tx.moment(), at)) =>
+                                {
+                                    return Err(TxError::DisputeWindowClosed(tx.transaction_id));
                                 }
-                                _ => {
-                                    return Err(TxError::InvalidState(
-                                        new_dispute,
-                                        prev_tx.dispute,
-                                    ));
+                                None if !policy.dispute_withdrawals
+                                    && matches!(prev_tx.origin, TxRecordType::Withdraw(_)) =>
+                                {
+                                    return Err(TxError::DisputeNotAllowed(tx.transaction_id));
                                 }
-                            }
-                        }
-                        DisputeState::ChargeBack => {
-                            //chargeback only if dispute is initiated, lock account afterwards
-                            //not sure if we want to perform chargeback if there is not sufficient
-                            //amount, but my gut feeling is that we should perform it, even if the
-                            //total becomes less than 0
-                            match prev_tx.dispute {
-                                Some(DisputeState::Initiated) => {
+                                None => {
+                                    //XXX at this point the balance may become negative value
+                                    balance.held += prev_tx.amount();
                                     prev_tx.dispute = Some(new_dispute);
-                                    let amount = prev_tx.amount();
-                                    self.held -= amount;
-                                    self.total -= amount;
-                                    self.locked = true;
+                                    prev_tx.disputed_at = Some(at);
                                 }
                                 _ => {
                                     return Err(TxError::InvalidState(
@@ -138,9 +214,46 @@ impl Account {
                                         prev_tx.dispute,
                                     ));
                                 }
+                            },
+                            DisputeState::Resolved => {
+                                match prev_tx.dispute {
+                                    //resolve only if initiated
+                                    Some(DisputeState::Initiated) => {
+                                        prev_tx.dispute = Some(new_dispute);
+                                        balance.held -= prev_tx.amount();
+                                        assert!(balance.held >= Decimal::from(0));
+                                    }
+                                    _ => {
+                                        return Err(TxError::InvalidState(
+                                            new_dispute,
+                                            prev_tx.dispute,
+                                        ));
+                                    }
+                                }
+                            }
+                            DisputeState::ChargeBack => {
+                                //chargeback only if dispute is initiated, lock account afterwards
+                                //not sure if we want to perform chargeback if there is not sufficient
+                                //amount, but my gut feeling is that we should perform it, even if the
+                                //total becomes less than 0
+                                match prev_tx.dispute {
+                                    Some(DisputeState::Initiated) => {
+                                        prev_tx.dispute = Some(new_dispute);
+                                        let amount = prev_tx.amount();
+                                        balance.held -= amount;
+                                        balance.total -= amount;
+                                        self.locked = policy.lock_on_chargeback;
+                                    }
+                                    _ => {
+                                        return Err(TxError::InvalidState(
+                                            new_dispute,
+                                            prev_tx.dispute,
+                                        ));
+                                    }
+                                }
                             }
                         }
-                    },
+                    }
                     None => {
                         return Err(TxError::TransactionNotFound(tx.transaction_id));
                     }
@@ -151,16 +264,60 @@ impl Account {
     }
 }
 
+/// Rules applied to disputes, the default allows disputes of any transaction
+/// at any time and locks the account after a chargeback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisputePolicy {
+    /// lock the account after a chargeback
+    pub lock_on_chargeback: bool,
+    /// allow disputes of withdrawals, not only of deposits
+    pub dispute_withdrawals: bool,
+    /// how long after the transaction a dispute may be opened
+    pub open_window: Option<Window>,
+    /// how long a dispute stays open before it is resolved automatically
+    pub expire_after: Option<Window>,
+}
+
+impl Default for DisputePolicy {
+    fn default() -> Self {
+        DisputePolicy {
+            lock_on_chargeback: true,
+            dispute_withdrawals: true,
+            open_window: None,
+            expire_after: None,
+        }
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum TxError {
     #[error("account locked: {0:?}")]
     AccountLocked(ClientId),
     #[error("insufficient funds")]
     InsufficientFunds(TransactionId),
-    #[error("transaction not found: {0:?}")]
+    #[error("transaction not found: {0}")]
     TransactionNotFound(TransactionId),
     #[error("invalid dispute state for: {0:?}, {1:?}")]
     InvalidState(DisputeState, Option<DisputeState>),
+    #[error("dispute not allowed: {0}")]
+    DisputeNotAllowed(TransactionId),
+    #[error("dispute window closed: {0}")]
+    DisputeWindowClosed(TransactionId),
+    #[error("limit exceeded: {rule}")]
+    LimitExceeded { rule: String },
+    #[error("account limit reached, can't open: {0:?}")]
+    AccountLimit(ClientId),
+    #[error("account already exists: {0:?}")]
+    AccountExists(ClientId),
+    #[error(
+        "out of order: {} is before {} by more than the tolerance",
+        format_timestamp(*.timestamp),
+        format_timestamp(*.latest)
+    )]
+    OutOfOrder {
+        timestamp: Timestamp,
+        latest: Timestamp,
+    },
     #[error(transparent)]
     IntegrityError(#[from] TransactionStoreError),
 }
@@ -170,104 +327,147 @@ mod tests {
     use std::collections::HashMap;
 
     use crate::model::{
-        account::TxError, Amount, DisputeState, TransactionId, Tx, TxOperation, TxRecord,
+        account::TxError, Amount, Currency, DisputeState, Moment, TenantId, Timestamp,
+        TransactionId, Tx, TxOperation, TxRecord,
     };
 
-    use super::Account;
+    use super::{Account, DisputePolicy};
     use crate::db::TransactionStoreError;
+    use crate::model::time::Window;
 
     #[test]
     fn test_processing() {
         // perform multiple transactions on a single account, check the state after each one
         let mut acc = Account::new(12);
         let mut store: HashMap<TransactionId, TxRecord> = Default::default();
+        let policy = DisputePolicy::default();
 
-        assert_eq!(acc.balance(), 0.into());
+        assert_eq!(acc.balance(""), 0.into());
         acc.process(
             Tx {
-                transaction_id: 1,
+                tenant: TenantId::new(),
+                transaction_id: 1.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Deposit(Amount::from(10)),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         )
         .expect("should succeed");
 
         //check balance
-        assert_eq!(acc.balance(), Amount::from(10));
+        assert_eq!(acc.balance(""), Amount::from(10));
 
         //try to withdraw more than the available amount
         let res = acc.process(
             Tx {
-                transaction_id: 2,
+                tenant: TenantId::new(),
+                transaction_id: 2.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Withdraw(Amount::from(20)),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         );
-        assert_eq!(res, Err(TxError::InsufficientFunds(2)));
+        assert_eq!(res, Err(TxError::InsufficientFunds(2.into())));
 
         //try to withdraw lower amount
         acc.process(
             Tx {
-                transaction_id: 3,
+                tenant: TenantId::new(),
+                transaction_id: 3.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Withdraw(Amount::from(5)),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         )
         .expect("withdraw should succeed");
-        assert_eq!(acc.balance(), Amount::from(5));
-        assert_eq!(acc.total(), Amount::from(5));
-        assert_eq!(acc.held(), Amount::from(0));
+        assert_eq!(acc.balance(""), Amount::from(5));
+        assert_eq!(acc.total(""), Amount::from(5));
+        assert_eq!(acc.held(""), Amount::from(0));
 
         //try to withdraw a lower amount with the same transaction id
         let res = acc.process(
             Tx {
-                transaction_id: 3,
+                tenant: TenantId::new(),
+                transaction_id: 3.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Withdraw(Amount::from(1)),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
+        );
+        assert_eq!(
+            res,
+            Err(TxError::IntegrityError(
+                TransactionStoreError::TransactionAlreadyExists(3.into())
+            ))
         );
-        assert_eq!(res, Err(TxError::IntegrityError(TransactionStoreError::TransactionAlreadyExists(3))));
-        assert_eq!(acc.balance(), Amount::from(5));
-        assert_eq!(acc.total(), Amount::from(5));
+        assert_eq!(acc.balance(""), Amount::from(5));
+        assert_eq!(acc.total(""), Amount::from(5));
 
         //try to dispute transaction 2, which was not successful
         let res = acc.process(
             Tx {
-                transaction_id: 2,
+                tenant: TenantId::new(),
+                transaction_id: 2.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Dispute(DisputeState::Initiated),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         );
-        assert_eq!(res, Err(TxError::TransactionNotFound(2)));
+        assert_eq!(res, Err(TxError::TransactionNotFound(2.into())));
 
         //try to dispute transaction 3
         acc.process(
             Tx {
-                transaction_id: 3,
+                tenant: TenantId::new(),
+                transaction_id: 3.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Dispute(DisputeState::Initiated),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         )
         .expect("dispute should be processed");
 
-        assert_eq!(acc.balance(), Amount::from(0));
-        assert_eq!(acc.held(), Amount::from(5));
-        assert_eq!(acc.total(), Amount::from(5));
+        assert_eq!(acc.balance(""), Amount::from(0));
+        assert_eq!(acc.held(""), Amount::from(5));
+        assert_eq!(acc.total(""), Amount::from(5));
 
         //try to dispute transaction 3 again
         let res = acc.process(
             Tx {
-                transaction_id: 3,
+                tenant: TenantId::new(),
+                transaction_id: 3.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Dispute(DisputeState::Initiated),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         );
 
         assert_eq!(
@@ -281,25 +481,35 @@ mod tests {
         //try to resolve the dispute for transaction 3
         acc.process(
             Tx {
-                transaction_id: 3,
+                tenant: TenantId::new(),
+                transaction_id: 3.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Dispute(DisputeState::Resolved),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         )
         .expect("resolve for transaction 3 should succeed");
-        assert_eq!(acc.balance(), Amount::from(5));
-        assert_eq!(acc.total(), Amount::from(5));
-        assert_eq!(acc.held(), Amount::from(0));
+        assert_eq!(acc.balance(""), Amount::from(5));
+        assert_eq!(acc.total(""), Amount::from(5));
+        assert_eq!(acc.held(""), Amount::from(0));
 
         //try to resolve the dispute for transaction 3 again
         let res = acc.process(
             Tx {
-                transaction_id: 3,
+                tenant: TenantId::new(),
+                transaction_id: 3.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Dispute(DisputeState::Resolved),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         );
         assert_eq!(
             res,
@@ -312,48 +522,239 @@ mod tests {
         //try to perform dispute for transaction 1
         acc.process(
             Tx {
-                transaction_id: 1,
+                tenant: TenantId::new(),
+                transaction_id: 1.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Dispute(DisputeState::Initiated),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         )
         .expect("dispute for the first transaction should be ok");
-        assert_eq!(acc.balance(), Amount::from(-5));
-        assert_eq!(acc.total(), Amount::from(5));
-        assert_eq!(acc.held(), Amount::from(10));
+        assert_eq!(acc.balance(""), Amount::from(-5));
+        assert_eq!(acc.total(""), Amount::from(5));
+        assert_eq!(acc.held(""), Amount::from(10));
 
         //try to charge back the dispute for transaction 1
         acc.process(
             Tx {
-                transaction_id: 1,
+                tenant: TenantId::new(),
+                transaction_id: 1.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Dispute(DisputeState::ChargeBack),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         )
         .expect("chargeback for transaction 1 should succeed");
-        assert_eq!(acc.balance(), Amount::from(-5));
-        assert_eq!(acc.total(), Amount::from(-5)); //is this OK?!?
-        assert_eq!(acc.held(), Amount::from(0));
+        assert_eq!(acc.balance(""), Amount::from(-5));
+        assert_eq!(acc.total(""), Amount::from(-5)); //is this OK?!?
+        assert_eq!(acc.held(""), Amount::from(0));
         assert!(acc.is_locked());
 
         //check if account when locked is really locked
         let res = acc.process(
             Tx {
-                transaction_id: 1,
+                tenant: TenantId::new(),
+                transaction_id: 1.into(),
                 client_id: 12,
+                currency: Currency::new(),
                 operation: TxOperation::Deposit(Amount::from(100)),
+                conversion: None,
+                timestamp: None,
             },
             &mut store,
+            &policy,
         );
         assert_eq!(res, Err(TxError::AccountLocked(12)));
         //account amounts should stay the same
-        assert_eq!(acc.balance(), Amount::from(-5));
-        assert_eq!(acc.total(), Amount::from(-5));
-        assert_eq!(acc.held(), Amount::from(0));
+        assert_eq!(acc.balance(""), Amount::from(-5));
+        assert_eq!(acc.total(""), Amount::from(-5));
+        assert_eq!(acc.held(""), Amount::from(0));
 
         //check the number of transactions, should be 2
         assert_eq!(store.len(), 2);
     }
+
+    #[test]
+    fn test_dispute_policy() {
+        let mut acc = Account::new(1);
+        let mut store: HashMap<TransactionId, TxRecord> = Default::default();
+        let policy = DisputePolicy {
+            lock_on_chargeback: false,
+            dispute_withdrawals: false,
+            open_window: None,
+            expire_after: None,
+        };
+        let tx = |transaction_id: u64, operation| Tx {
+            tenant: TenantId::new(),
+            transaction_id: transaction_id.into(),
+            client_id: 1,
+            currency: Currency::new(),
+            operation,
+            conversion: None,
+            timestamp: None,
+        };
+
+        acc.process(
+            tx(1, TxOperation::Deposit(Amount::from(10))),
+            &mut store,
+            &policy,
+        )
+        .expect("deposit should succeed");
+        acc.process(
+            tx(2, TxOperation::Withdraw(Amount::from(4))),
+            &mut store,
+            &policy,
+        )
+        .expect("withdraw should succeed");
+
+        //withdrawals can't be disputed
+        let res = acc.process(
+            tx(2, TxOperation::Dispute(DisputeState::Initiated)),
+            &mut store,
+            &policy,
+        );
+        assert_eq!(res, Err(TxError::DisputeNotAllowed(2.into())));
+        assert_eq!(acc.held(""), Amount::from(0));
+
+        //chargeback doesn't lock the account
+        for state in &[DisputeState::Initiated, DisputeState::ChargeBack] {
+            acc.process(tx(1, TxOperation::Dispute(*state)), &mut store, &policy)
+                .expect("dispute of a deposit should succeed");
+        }
+        assert_eq!(acc.total(""), Amount::from(-4));
+        assert!(!acc.is_locked());
+    }
+
+    #[test]
+    fn test_currencies() {
+        let mut acc = Account::new(1);
+        let mut store: HashMap<TransactionId, TxRecord> = Default::default();
+        let policy = DisputePolicy::default();
+        let tx = |transaction_id: u64, currency: &str, operation| Tx {
+            tenant: TenantId::new(),
+            transaction_id: transaction_id.into(),
+            client_id: 1,
+            currency: currency.into(),
+            operation,
+            conversion: None,
+            timestamp: None,
+        };
+
+        acc.process(
+            tx(1, "USD", TxOperation::Deposit(Amount::from(10))),
+            &mut store,
+            &policy,
+        )
+        .expect("deposit should succeed");
+        acc.process(
+            tx(2, "EUR", TxOperation::Deposit(Amount::from(5))),
+            &mut store,
+            &policy,
+        )
+        .expect("deposit should succeed");
+
+        //the balances are not shared between currencies
+        let res = acc.process(
+            tx(3, "EUR", TxOperation::Withdraw(Amount::from(6))),
+            &mut store,
+            &policy,
+        );
+        assert_eq!(res, Err(TxError::InsufficientFunds(3.into())));
+
+        //the dispute applies in the currency of the disputed transaction
+        acc.process(
+            tx(1, "EUR", TxOperation::Dispute(DisputeState::Initiated)),
+            &mut store,
+            &policy,
+        )
+        .expect("dispute should succeed");
+        assert_eq!(acc.held("USD"), Amount::from(10));
+        assert_eq!(acc.held("EUR"), Amount::from(0));
+        acc.process(
+            tx(1, "", TxOperation::Dispute(DisputeState::ChargeBack)),
+            &mut store,
+            &policy,
+        )
+        .expect("chargeback should succeed");
+        assert_eq!(acc.total("USD"), Amount::from(0));
+        assert_eq!(acc.total("EUR"), Amount::from(5));
+        let currencies: Vec<&str> = acc.balances().map(|(currency, _)| currency).collect();
+        assert_eq!(currencies, ["EUR", "USD"]);
+        assert!(acc.is_locked());
+    }
+
+    #[test]
+    fn test_dispute_windows() {
+        let mut acc = Account::new(1);
+        let mut store: HashMap<TransactionId, TxRecord> = Default::default();
+        let policy = DisputePolicy {
+            open_window: Some("1h".parse().unwrap()),
+            ..DisputePolicy::default()
+        };
+        let tx = |transaction_id: u64, operation, timestamp: Option<Timestamp>| Tx {
+            tenant: TenantId::new(),
+            transaction_id: transaction_id.into(),
+            client_id: 1,
+            currency: Currency::new(),
+            operation,
+            conversion: None,
+            timestamp,
+        };
+        let dispute = TxOperation::Dispute(DisputeState::Initiated);
+
+        for (id, timestamp) in &[(1, Some(0)), (2, None)] {
+            acc.process(
+                tx(*id, TxOperation::Deposit(Amount::from(10)), *timestamp),
+                &mut store,
+                &policy,
+            )
+            .expect("deposit should succeed");
+        }
+        let res = acc.process(tx(1, dispute, Some(3601)), &mut store, &policy);
+        assert_eq!(res, Err(TxError::DisputeWindowClosed(1.into())));
+        //a window in seconds doesn't apply to transactions without a timestamp
+        acc.process(tx(2, dispute, Some(3601)), &mut store, &policy)
+            .expect("dispute should succeed");
+        assert_eq!(
+            store[&2.into()].disputed_at.and_then(|at| at.timestamp),
+            Some(3601)
+        );
+
+        let policy = DisputePolicy {
+            open_window: Some(Window::Transactions(2)),
+            ..DisputePolicy::default()
+        };
+        let at = |sequence| Moment {
+            sequence,
+            timestamp: None,
+        };
+        acc.process_at(
+            tx(3, TxOperation::Deposit(Amount::from(10)), None),
+            &mut store,
+            &policy,
+            at(5),
+        )
+        .expect("deposit should succeed");
+        let res = acc.process_at(tx(3, dispute, None), &mut store, &policy, at(8));
+        assert_eq!(res, Err(TxError::DisputeWindowClosed(3.into())));
+        acc.process_at(tx(3, dispute, None), &mut store, &policy, at(7))
+            .expect("dispute should succeed");
+        assert_eq!(acc.held(""), Amount::from(20));
+
+        assert_eq!("90d".parse::<Window>(), Ok(Window::Seconds(90 * 86400)));
+        assert_eq!("10tx".parse::<Window>(), Ok(Window::Transactions(10)));
+        assert_eq!(Window::Seconds(7200).to_string(), "2h");
+        assert_eq!(Window::Seconds(90).to_string(), "90s");
+        for invalid in &["d", "10", "5w", "-1h"] {
+            assert!(invalid.parse::<Window>().is_err());
+        }
+    }
 }
@@ -0,0 +1,245 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use super::ClientId;
+
+/// A transaction id, either a 64-bit number or a UUID, the UUID is kept as
+/// big endian bytes so the id stays small
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TransactionId {
+    Number(u64),
+    Uuid([u8; 16]),
+}
+
+/// The kinds of transaction ids accepted in the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdFormat {
+    #[default]
+    Any,
+    Number,
+    Uuid,
+}
+
+impl FromStr for IdFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(IdFormat::Any),
+            "number" => Ok(IdFormat::Number),
+            "uuid" => Ok(IdFormat::Uuid),
+            _ => Err(format!("unknown id format: {}", s)),
+        }
+    }
+}
+
+impl Display for IdFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdFormat::Any => write!(f, "any"),
+            IdFormat::Number => write!(f, "number"),
+            IdFormat::Uuid => write!(f, "uuid"),
+        }
+    }
+}
+
+impl IdFormat {
+    /// fails if the id is not of this format
+    pub fn check(self, id: TransactionId) -> Result<TransactionId, IdError> {
+        match (self, id) {
+            (IdFormat::Any, _)
+            | (IdFormat::Number, TransactionId::Number(_))
+            | (IdFormat::Uuid, TransactionId::Uuid(_)) => Ok(id),
+            _ => Err(IdError::Format(id.to_string(), self)),
+        }
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum IdError {
+    #[error("{0} is out of range, the largest id is {1}")]
+    OutOfRange(String, u64),
+    #[error("{0} is not a valid id")]
+    Invalid(String),
+    #[error("{0} is not a {1} id")]
+    Format(String, IdFormat),
+}
+
+/// parse a client id, numbers that don't fit are reported as out of range
+pub fn parse_client_id(s: &str) -> Result<ClientId, IdError> {
+    s.parse().map_err(|_| {
+        if is_number(s) {
+            IdError::OutOfRange(s.to_owned(), u64::from(ClientId::MAX))
+        } else {
+            IdError::Invalid(s.to_owned())
+        }
+    })
+}
+
+impl TransactionId {
+    /// parse an id of the given format
+    pub fn parse(s: &str, format: IdFormat) -> Result<Self, IdError> {
+        let id = if is_number(s) {
+            s.parse()
+                .map(TransactionId::Number)
+                .map_err(|_| IdError::OutOfRange(s.to_owned(), u64::MAX))?
+        } else {
+            parse_uuid(s)
+                .map(|uuid| TransactionId::Uuid(uuid.to_be_bytes()))
+                .ok_or_else(|| IdError::Invalid(s.to_owned()))?
+        };
+        format.check(id)
+    }
+}
+
+impl From<u64> for TransactionId {
+    fn from(id: u64) -> Self {
+        TransactionId::Number(id)
+    }
+}
+
+impl FromStr for TransactionId {
+    type Err = IdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TransactionId::parse(s, IdFormat::Any)
+    }
+}
+
+impl Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TransactionId::Number(id) => write!(f, "{}", id),
+            TransactionId::Uuid(bytes) => {
+                let id = u128::from_be_bytes(bytes);
+                write!(
+                    f,
+                    "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+                    id >> 96,
+                    (id >> 80) & 0xffff,
+                    (id >> 64) & 0xffff,
+                    (id >> 48) & 0xffff,
+                    id & 0xffff_ffff_ffff
+                )
+            }
+        }
+    }
+}
+
+impl Serialize for TransactionId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            TransactionId::Number(id) => serializer.serialize_u64(*id),
+            TransactionId::Uuid(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionId {
+    /// a number or a string, so it can be read from both csv and json
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = TransactionId;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a 64-bit transaction id or a UUID")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(TransactionId::Number(v))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Err(E::custom(IdError::Invalid(v.to_string())))
+            }
+
+            fn visit_u128<E: de::Error>(self, v: u128) -> Result<Self::Value, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            /// json numbers that don't fit in 64 bits
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                if v > 0.0 && v.fract() == 0.0 {
+                    return Err(E::custom(IdError::OutOfRange(v.to_string(), u64::MAX)));
+                }
+                Err(E::custom(IdError::Invalid(v.to_string())))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// the hyphenated form, e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8`
+fn parse_uuid(s: &str) -> Option<u128> {
+    let groups: Vec<&str> = s.split('-').collect();
+    let lengths: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+    if lengths != [8, 4, 4, 4, 12]
+        || !groups
+            .iter()
+            .all(|g| g.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        return None;
+    }
+    u128::from_str_radix(&groups.concat(), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_client_id, IdError, IdFormat, TransactionId};
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(parse_client_id("70000"), Ok(70000));
+        assert_eq!(
+            parse_client_id("4294967296"),
+            Err(IdError::OutOfRange(
+                "4294967296".into(),
+                u64::from(u32::MAX)
+            ))
+        );
+        assert_eq!(parse_client_id("-1"), Err(IdError::Invalid("-1".into())));
+
+        assert_eq!(
+            "18446744073709551615".parse(),
+            Ok(TransactionId::Number(u64::MAX))
+        );
+        assert_eq!(
+            "18446744073709551616".parse::<TransactionId>(),
+            Err(IdError::OutOfRange("18446744073709551616".into(), u64::MAX))
+        );
+        let uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let id: TransactionId = uuid.parse().unwrap();
+        assert_eq!(
+            id,
+            TransactionId::Uuid(0x67e5504410b1426f9247bb680e5fe0c8u128.to_be_bytes())
+        );
+        assert_eq!(id.to_string(), uuid);
+        assert_eq!(
+            TransactionId::parse("67E55044-10B1-426F-9247-BB680E5FE0C8", IdFormat::Uuid),
+            Ok(id)
+        );
+        assert_eq!(
+            TransactionId::parse(uuid, IdFormat::Number),
+            Err(IdError::Format(uuid.into(), IdFormat::Number))
+        );
+        assert!(TransactionId::parse("12", IdFormat::Uuid).is_err());
+        assert!("67e55044-10b1-426f-9247".parse::<TransactionId>().is_err());
+        assert!("0x10".parse::<TransactionId>().is_err());
+    }
+}
@@ -3,7 +3,10 @@ use std::convert::TryFrom;
 use serde::{self, Deserialize};
 use thiserror::Error;
 
-use super::{Amount, ClientId, DisputeState, TransactionId, Tx, TxOperation};
+use super::{
+    time::{parse_timestamp, TimestampError},
+    Amount, ClientId, Currency, DisputeState, TenantId, TransactionId, Tx, TxOperation,
+};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -15,6 +18,24 @@ pub enum TransactionType {
     ChargeBack,
 }
 
+/// Names of the columns in the input
+pub const COLUMNS: &[&str] = &[
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "tenant",
+    "currency",
+    "timestamp",
+];
+/// Columns that must be present in the input, `amount` is needed only by some
+/// types, `tenant` and `currency` only when the input has several of them and
+/// `timestamp` only for the time based features
+pub const REQUIRED_COLUMNS: &[&str] = &["type", "client", "tx"];
+/// Names of the transaction types in the input
+pub const TRANSACTION_TYPES: &[&str] =
+    &["deposit", "withdrawal", "dispute", "resolve", "chargeback"];
+
 #[derive(Debug, Deserialize)]
 pub struct TxRow {
     #[serde(rename = "tx")]
@@ -25,6 +46,27 @@ pub struct TxRow {
     row_type: TransactionType,
     #[serde(rename = "amount")]
     amount: Option<Amount>,
+    #[serde(rename = "tenant")]
+    tenant: Option<TenantId>,
+    #[serde(rename = "currency")]
+    currency: Option<Currency>,
+    /// RFC 3339 or seconds since the unix epoch
+    #[serde(rename = "timestamp")]
+    timestamp: Option<String>,
+}
+
+impl TxRow {
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
+    }
+
+    pub fn transaction_id(&self) -> TransactionId {
+        self.transaction_id
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
 }
 
 #[derive(Debug, Error, Eq, PartialEq)]
@@ -35,6 +77,23 @@ pub enum ConversionError {
     WithdrawalWithoutAmount,
     #[error("dispute action should not contain amount")]
     DisputeWithAmount,
+    #[error("amount has {scale} decimal places, at most {max} allowed")]
+    TooManyDecimals { scale: u32, max: u32 },
+    #[error("{0} is not a currency code")]
+    InvalidCurrency(String),
+    #[error("no exchange rate from {from} to {to}")]
+    NoRate { from: Currency, to: Currency },
+    #[error(transparent)]
+    InvalidTimestamp(#[from] TimestampError),
+}
+
+/// a three letter currency code in upper case, empty if there is none
+pub fn parse_currency(s: &str) -> Result<Currency, ConversionError> {
+    if s.is_empty() || (s.len() == 3 && s.bytes().all(|b| b.is_ascii_alphabetic())) {
+        Ok(s.to_ascii_uppercase())
+    } else {
+        Err(ConversionError::InvalidCurrency(s.to_owned()))
+    }
 }
 
 impl TryFrom<TxRow> for Tx {
@@ -62,9 +121,17 @@ impl TryFrom<TxRow> for Tx {
             },
         };
         Ok(Tx {
+            tenant: value.tenant.unwrap_or_default(),
             transaction_id: value.transaction_id,
             client_id: value.client_id,
+            currency: parse_currency(value.currency.as_deref().unwrap_or_default())?,
             operation,
+            conversion: None,
+            timestamp: value
+                .timestamp
+                .as_deref()
+                .map(parse_timestamp)
+                .transpose()?,
         })
     }
 }
@@ -73,36 +140,58 @@ impl TryFrom<TxRow> for Tx {
 mod tests {
     use std::convert::TryInto;
 
-    use crate::model::{input::ConversionError, Amount, Tx, TxOperation};
+    use crate::model::{input::ConversionError, Amount, Currency, TenantId, Tx, TxOperation};
 
     use super::TxRow;
 
     #[test]
     fn test_conversion() {
         let row = TxRow {
-            transaction_id: 1,
+            transaction_id: 1.into(),
             client_id: 1,
             row_type: super::TransactionType::Deposit,
             amount: Some(Amount::from(10)),
+            tenant: None,
+            currency: Some("eur".into()),
+            timestamp: Some("2023-11-14T22:13:20Z".into()),
         };
 
         assert_eq!(
             row.try_into(),
             Ok(Tx {
-                transaction_id: 1,
+                tenant: TenantId::new(),
+                transaction_id: 1.into(),
                 client_id: 1,
+                currency: Currency::from("EUR"),
                 operation: TxOperation::Deposit(Amount::from(10)),
+                conversion: None,
+                timestamp: Some(1700000000),
             })
         );
 
         let row = TxRow {
-            transaction_id: 2,
+            transaction_id: 2.into(),
             client_id: 2,
             row_type: crate::model::input::TransactionType::Resolve,
             amount: Some(Amount::from(10)),
+            tenant: None,
+            currency: None,
+            timestamp: None,
         };
 
         let res: Result<Tx, ConversionError> = row.try_into();
         assert_eq!(res, Err(ConversionError::DisputeWithAmount));
+
+        let row = TxRow {
+            transaction_id: 3.into(),
+            client_id: 3,
+            row_type: crate::model::input::TransactionType::Deposit,
+            amount: Some(Amount::from(10)),
+            tenant: None,
+            currency: Some("euro".into()),
+            timestamp: None,
+        };
+        let res: Result<Tx, ConversionError> = row.try_into();
+        assert_eq!(res, Err(ConversionError::InvalidCurrency("euro".into())));
     }
 }
@@ -1,11 +1,30 @@
 use rust_decimal::Decimal;
 pub mod account;
+pub mod id;
 pub mod input;
 pub mod output;
+pub mod precision;
+pub mod time;
 
-pub type TransactionId = u32;
-pub type ClientId = u16;
+pub use id::TransactionId;
+pub type ClientId = u32;
+/// The merchant that clients and transactions belong to, ids are unique only
+/// within a tenant. The empty tenant is used for inputs that have none
+pub type TenantId = String;
+/// An ISO 4217 currency code such as `USD`, the empty currency is used for
+/// inputs that have none
+pub type Currency = String;
 pub type Amount = Decimal;
+/// Seconds since the unix epoch
+pub type Timestamp = i64;
+
+/// When a transaction was processed: its position among the accepted
+/// transactions and its timestamp, if the input has one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Moment {
+    pub sequence: u64,
+    pub timestamp: Option<Timestamp>,
+}
 
 /// A dispute may be in one of the tree states - Initiated, Resolved and ChargeBack
 /// Valid transitions are:
@@ -20,23 +39,55 @@ pub enum DisputeState {
 
 /// A valid transaction can be one of the following: Deposit, Withdraw, Dispute{Initiated,
 /// Resolved, ChargeBack}
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TxOperation {
     Deposit(Amount),
     Withdraw(Amount),
     Dispute(DisputeState),
 }
 
+impl TxOperation {
+    /// name of the operation as used in the input
+    pub fn name(&self) -> &'static str {
+        match self {
+            TxOperation::Deposit(_) => "deposit",
+            TxOperation::Withdraw(_) => "withdrawal",
+            TxOperation::Dispute(DisputeState::Initiated) => "dispute",
+            TxOperation::Dispute(DisputeState::Resolved) => "resolve",
+            TxOperation::Dispute(DisputeState::ChargeBack) => "chargeback",
+        }
+    }
+}
+
 /// A singe transaction than needs to be processed, contains transaction_id that is globally unique
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Tx {
+    pub tenant: TenantId,
     pub transaction_id: TransactionId,
     pub client_id: ClientId,
+    /// the currency of deposits and withdrawals, disputes use the one of the
+    /// disputed transaction
+    pub currency: Currency,
     pub operation: TxOperation,
+    /// set when the amount was converted from another currency
+    pub conversion: Option<Conversion>,
+    /// the time of the transaction, if the input has one
+    pub timestamp: Option<Timestamp>,
+}
+
+/// The amount of a transaction before it was converted into the currency of
+/// the account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    pub currency: Currency,
+    pub amount: Amount,
+    pub rate: Decimal,
+    /// `amount * rate`, the converted amount before it was rounded
+    pub unrounded: Amount,
 }
 
 /// Each TxRecord is constructed with one of the following: Deposit or Withdraw
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TxRecordType {
     Deposit(Amount),
     Withdraw(Amount),
@@ -44,14 +95,30 @@ pub enum TxRecordType {
 
 /// TxRecord is the main entity responsible for the lifecycle of the transaction,
 /// once created with TxRecordType it can be further modified by setting `dispute` field
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TxRecord {
     pub origin: TxRecordType,
     pub client_id: ClientId,
+    pub currency: Currency,
     pub dispute: Option<DisputeState>,
+    /// the original amount, disputes use the converted one
+    pub conversion: Option<Conversion>,
+    pub timestamp: Option<Timestamp>,
+    /// the position of the transaction among the accepted ones
+    pub sequence: u64,
+    /// when the current dispute was opened
+    pub disputed_at: Option<Moment>,
 }
 
 impl TxRecord {
+    /// when the transaction was processed
+    pub fn moment(&self) -> Moment {
+        Moment {
+            sequence: self.sequence,
+            timestamp: self.timestamp,
+        }
+    }
+
     pub fn amount(&self) -> Amount {
         match self.origin {
             TxRecordType::Deposit(amount) => amount,
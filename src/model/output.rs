@@ -1,10 +1,18 @@
-use super::{Amount, ClientId};
-use serde::Serialize;
+use super::{
+    account::Account, precision::Precision, Amount, ClientId, Currency, TenantId, TransactionId,
+};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Record {
+    /// only written when the accounts have tenants
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<TenantId>,
     #[serde(rename = "client")]
     pub client_id: ClientId,
+    /// only written when the accounts have currencies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
     #[serde(rename = "available")]
     pub balance: Amount,
     #[serde(rename = "held")]
@@ -14,3 +22,122 @@ pub struct Record {
     #[serde(rename = "locked")]
     pub locked: bool,
 }
+
+impl Record {
+    /// the state of the account in a currency with amounts in the given precision
+    pub fn new(account: &Account, currency: &str, precision: &Precision) -> Self {
+        let balance = account.balance_in(currency);
+        Record {
+            tenant: Some(account.tenant())
+                .filter(|tenant| !tenant.is_empty())
+                .map(str::to_owned),
+            client_id: account.client(),
+            currency: Some(currency)
+                .filter(|currency| !currency.is_empty())
+                .map(str::to_owned),
+            balance: precision.format(balance.available()),
+            held: precision.format(balance.held),
+            total: precision.format(balance.total),
+            locked: account.is_locked(),
+        }
+    }
+
+    /// a record for every currency of the account, or a single empty one if
+    /// the account has no balances yet
+    pub fn all(account: &Account, precision: &Precision) -> Vec<Self> {
+        let mut records: Vec<Self> = account
+            .balances()
+            .map(|(currency, _)| Record::new(account, currency, precision))
+            .collect();
+        if records.is_empty() {
+            records.push(Record::new(account, "", precision));
+        }
+        records
+    }
+}
+
+/// A row that was not accepted, see `io::Rejection`
+#[derive(Debug, Serialize)]
+pub struct RejectRecord<'a> {
+    pub source: &'a str,
+    pub line: u64,
+    /// only written when the rows have tenants
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<&'a str>,
+    #[serde(rename = "client")]
+    pub client_id: Option<ClientId>,
+    #[serde(rename = "tx")]
+    pub transaction_id: Option<TransactionId>,
+    pub category: &'static str,
+    pub reason: String,
+}
+
+/// Row counters for a single input source
+#[derive(Debug, Serialize)]
+pub struct MetricsRecord<'a> {
+    pub source: &'a str,
+    pub rows: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    /// the problems of the header
+    pub header: u64,
+}
+
+/// A line of a client statement, see `report::statement`
+#[derive(Debug, Serialize)]
+pub struct StatementRecord<'a> {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    /// only written when the lines have currencies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<&'a str>,
+    pub source: &'a str,
+    pub line: u64,
+    #[serde(rename = "tx")]
+    pub transaction_id: Option<TransactionId>,
+    #[serde(rename = "type")]
+    pub kind: Option<&'static str>,
+    pub amount: Option<Amount>,
+    pub status: &'static str,
+    pub reason: Option<&'a str>,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+}
+
+/// A dispute that was resolved automatically, see `db::ExpiredDispute`, the
+/// times are empty for transactions without a timestamp
+#[derive(Debug, Serialize)]
+pub struct ExpiredRecord<'a> {
+    /// only written when the disputes have tenants
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<&'a str>,
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    #[serde(rename = "tx")]
+    pub transaction_id: TransactionId,
+    pub opened_seq: u64,
+    pub opened_at: Option<String>,
+    pub expired_seq: u64,
+    pub expired_at: Option<String>,
+}
+
+/// A client flagged by a fraud detector, see `db::fraud::Flag`
+#[derive(Debug, Serialize)]
+pub struct FlagRecord<'a> {
+    /// only written when the flags have tenants
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<&'a str>,
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    #[serde(rename = "tx")]
+    pub transaction_id: TransactionId,
+    pub detector: &'a str,
+    pub score: u32,
+    pub total: u32,
+    pub reason: &'a str,
+    pub seq: u64,
+    pub at: Option<String>,
+    pub frozen: bool,
+}
@@ -0,0 +1,176 @@
+use std::str::FromStr;
+
+use rust_decimal::RoundingStrategy;
+
+use super::{input::ConversionError, Amount};
+
+/// the largest scale supported by the decimal type
+pub const MAX_SCALE: u32 = 28;
+
+/// parse a number of decimal places, at most `MAX_SCALE`
+pub fn parse_scale(s: &str) -> Result<u32, String> {
+    let scale: u32 = s
+        .parse()
+        .map_err(|e| format!("invalid scale {}: {}", s, e))?;
+    if scale > MAX_SCALE {
+        return Err(format!("the scale must be at most {}", MAX_SCALE));
+    }
+    Ok(scale)
+}
+
+/// How amounts are rounded when they have more decimal places than allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// round half to even
+    Bankers,
+    /// round half away from zero
+    HalfUp,
+    /// drop the extra digits
+    Truncate,
+}
+
+impl Rounding {
+    fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::Bankers => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::Truncate => RoundingStrategy::ToZero,
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bankers" => Ok(Rounding::Bankers),
+            "half-up" => Ok(Rounding::HalfUp),
+            "truncate" => Ok(Rounding::Truncate),
+            _ => Err(format!("unknown rounding: {}", s)),
+        }
+    }
+}
+
+/// What to do with input amounts that have more decimal places than allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExcessPrecision {
+    Reject,
+    Round,
+}
+
+impl FromStr for ExcessPrecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(ExcessPrecision::Reject),
+            "round" => Ok(ExcessPrecision::Round),
+            _ => Err(format!("unknown excess precision policy: {}", s)),
+        }
+    }
+}
+
+/// Number of decimal places and the rounding used to get there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precision {
+    pub scale: u32,
+    pub rounding: Rounding,
+}
+
+impl Default for Precision {
+    /// four decimal places, rounded half up
+    fn default() -> Self {
+        Precision {
+            scale: 4,
+            rounding: Rounding::HalfUp,
+        }
+    }
+}
+
+impl Precision {
+    /// round the amount to at most `scale` decimal places
+    pub fn round(&self, amount: Amount) -> Amount {
+        amount.round_dp_with_strategy(self.scale, self.rounding.strategy())
+    }
+
+    /// round the amount and pad it with zeros up to `scale` decimal places
+    pub fn format(&self, amount: Amount) -> Amount {
+        let mut amount = self.round(amount);
+        amount.rescale(self.scale);
+        amount
+    }
+
+    /// validate an input amount, trailing zeros are not counted as decimal places
+    pub fn check(
+        &self,
+        amount: Amount,
+        excess: ExcessPrecision,
+    ) -> Result<Amount, ConversionError> {
+        let scale = amount.normalize().scale();
+        if scale <= self.scale {
+            return Ok(amount);
+        }
+        match excess {
+            ExcessPrecision::Reject => Err(ConversionError::TooManyDecimals {
+                scale,
+                max: self.scale,
+            }),
+            ExcessPrecision::Round => Ok(self.round(amount)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::model::{input::ConversionError, Amount};
+
+    use super::{parse_scale, ExcessPrecision, Precision, Rounding};
+
+    fn amount(s: &str) -> Amount {
+        Amount::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        let cases = [
+            (Rounding::Bankers, "2.125", "2.12"),
+            (Rounding::HalfUp, "2.125", "2.13"),
+            (Rounding::Truncate, "2.129", "2.12"),
+            (Rounding::HalfUp, "-2.125", "-2.13"),
+            (Rounding::Bankers, "2", "2.00"),
+        ];
+        for (rounding, input, expected) in &cases {
+            let precision = Precision {
+                scale: 2,
+                rounding: *rounding,
+            };
+            assert_eq!(precision.format(amount(input)).to_string(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let precision = Precision {
+            scale: 2,
+            rounding: Rounding::Truncate,
+        };
+        assert_eq!(
+            precision.check(amount("1.2300"), ExcessPrecision::Reject),
+            Ok(amount("1.23"))
+        );
+        assert_eq!(
+            precision.check(amount("1.239"), ExcessPrecision::Reject),
+            Err(ConversionError::TooManyDecimals { scale: 3, max: 2 })
+        );
+        assert_eq!(
+            precision.check(amount("1.239"), ExcessPrecision::Round),
+            Ok(amount("1.23"))
+        );
+        assert_eq!(parse_scale("28"), Ok(28));
+        assert!(parse_scale("29").is_err());
+        assert!(parse_scale("-1").is_err());
+    }
+}
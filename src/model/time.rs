@@ -0,0 +1,252 @@
+use std::{
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+use super::{Moment, Timestamp};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("{0} is not an RFC 3339 or epoch timestamp")]
+pub struct TimestampError(pub String);
+
+/// parse seconds since the unix epoch, e.g. `1700000000`, or an RFC 3339 date
+/// and time, e.g. `2023-11-14T22:13:20Z` or `2023-11-14 23:13:20.5+01:00`,
+/// fractions of a second are dropped
+pub fn parse_timestamp(s: &str) -> Result<Timestamp, TimestampError> {
+    let error = || TimestampError(s.to_owned());
+    let (epoch, fraction) = s.split_once('.').unwrap_or((s, "0"));
+    if !epoch.is_empty() && epoch.bytes().all(|b| b.is_ascii_digit() || b == b'-') {
+        if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(error());
+        }
+        return epoch.parse().map_err(|_| error());
+    }
+    parse_rfc3339(s.as_bytes()).ok_or_else(error)
+}
+
+/// the RFC 3339 form in UTC, e.g. `2023-11-14T22:13:20Z`
+pub fn format_timestamp(timestamp: Timestamp) -> String {
+    let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    let mut s = String::new();
+    let _ = write!(
+        s,
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    s
+}
+
+/// A period measured in seconds between the timestamps of the transactions,
+/// or in number of transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Seconds(Timestamp),
+    Transactions(u64),
+}
+
+impl Window {
+    /// the last position in the window that starts at `start`, none if the
+    /// window is in seconds and `start` has no timestamp
+    pub fn deadline(&self, start: Moment) -> Option<i64> {
+        match self {
+            Window::Seconds(seconds) => start.timestamp.map(|t| t.saturating_add(*seconds)),
+            Window::Transactions(count) => {
+                Some((start.sequence as i64).saturating_add(*count as i64))
+            }
+        }
+    }
+
+    /// the position of `now` in the unit of the window
+    pub fn position(&self, now: Moment) -> Option<i64> {
+        match self {
+            Window::Seconds(_) => now.timestamp,
+            Window::Transactions(_) => Some(now.sequence as i64),
+        }
+    }
+
+    /// if the window that starts at `start` is over at `now`, windows in
+    /// seconds never end for transactions without a timestamp
+    pub fn is_over(&self, start: Moment, now: Moment) -> bool {
+        matches!(
+            (self.deadline(start), self.position(now)),
+            (Some(deadline), Some(position)) if position > deadline
+        )
+    }
+
+    /// if `now` is in the window that starts at `start`, windows in seconds
+    /// contain nothing for transactions without a timestamp
+    pub fn contains(&self, start: Moment, now: Moment) -> bool {
+        matches!(
+            (self.deadline(start), self.position(now)),
+            (Some(deadline), Some(position)) if position <= deadline
+        )
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    /// a number followed by `s`, `m`, `h` or `d` for a period of time, or by
+    /// `tx` for a number of transactions, e.g. `90d` or `1000tx`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid window: {}", s);
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let number: u32 = s[..split].parse().map_err(|_| invalid())?;
+        let seconds = match &s[split..] {
+            "tx" => return Ok(Window::Transactions(number.into())),
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            _ => return Err(invalid()),
+        };
+        Ok(Window::Seconds(Timestamp::from(number) * seconds))
+    }
+}
+
+impl fmt::Display for Window {
+    /// the form `from_str` parses, in the largest unit that divides the period
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Window::Transactions(count) => write!(f, "{}tx", count),
+            Window::Seconds(seconds) => {
+                let (length, unit) = [(86400, "d"), (3600, "h"), (60, "m")]
+                    .iter()
+                    .find(|(length, _)| *seconds != 0 && seconds % length == 0)
+                    .map_or((1, "s"), |(length, unit)| (*length, *unit));
+                write!(f, "{}{}", seconds / length, unit)
+            }
+        }
+    }
+}
+
+fn parse_rfc3339(s: &[u8]) -> Option<Timestamp> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = s.get(range)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    };
+    if s.len() < 20 || s[4] != b'-' || s[7] != b'-' || s[13] != b':' || s[16] != b':' {
+        return None;
+    }
+    if !matches!(s[10], b'T' | b't' | b' ') {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut rest = &s[19..];
+    if let Some(fraction) = rest.strip_prefix(b".") {
+        let digits = fraction.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        b"Z" | b"z" => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let offset = std::str::from_utf8(&[*h1, *h2]).ok()?.parse::<i64>().ok()? * 3600
+                + std::str::from_utf8(&[*m1, *m2]).ok()?.parse::<i64>().ok()? * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+    let days = days_from_civil(year, month, day);
+    Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// days since 1970-01-01 of a date of the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// the inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_timestamp, parse_timestamp, TimestampError};
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(parse_timestamp("1700000000"), Ok(1700000000));
+        assert_eq!(parse_timestamp("1700000000.75"), Ok(1700000000));
+        assert_eq!(parse_timestamp("2023-11-14T22:13:20Z"), Ok(1700000000));
+        assert_eq!(
+            parse_timestamp("2023-11-14 23:13:20.5+01:00"),
+            Ok(1700000000)
+        );
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), Ok(-1));
+        assert_eq!(parse_timestamp("2024-02-29T00:00:00Z"), Ok(1709164800));
+        for invalid in &[
+            "",
+            "yesterday",
+            "2023-02-29T00:00:00Z",
+            "2023-11-14T22:13:20",
+            "2023-11-14T25:13:20Z",
+            "2023-11-14T22:13:20.Z",
+            "1700000000.",
+            "1700000000.abc",
+            "1700000000.5e9",
+            "1700000000.5.5",
+        ] {
+            assert_eq!(
+                parse_timestamp(invalid),
+                Err(TimestampError(invalid.to_string()))
+            );
+        }
+        assert_eq!(format_timestamp(1700000000), "2023-11-14T22:13:20Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59Z");
+        assert_eq!(format_timestamp(1709164800), "2024-02-29T00:00:00Z");
+    }
+}
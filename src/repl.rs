@@ -0,0 +1,469 @@
+use std::{collections::BTreeMap, fs::File, path::Path};
+
+use csv::StringRecord;
+
+use crate::{
+    db::{Change, MemoryDB},
+    io::{
+        compress::decompress,
+        mapping::{Header, HeaderError},
+        parse_row, print_results, read_csv_data, read_results, ReadOptions,
+    },
+    model::{
+        account::Account, input::COLUMNS, output::Record, precision::Precision,
+        time::format_timestamp, ClientId, DisputeState, TenantId, TransactionId, TxRecordType,
+    },
+    report::compare::compare,
+};
+
+/// the changes of this many commands are kept for undo
+const UNDO_LIMIT: usize = 100;
+
+pub const HELP: &str = "\
+<type> <client> <tx> [amount]  apply a transaction, e.g. `deposit 1 10 2.5` or `dispute 1 10`
+show account [tenant] <client> print an account, of the --tenant by default
+show tx [tenant] <tx>          print a transaction
+show accounts                  print all the accounts as csv
+load <file>                    process an input file
+restore <file>                 add the accounts of a results file written by `process`
+undo                           revert the last change
+help                           print this help
+quit                           exit";
+
+/// An interactive session on top of a database, every command returns the
+/// text to print
+pub struct Session {
+    db: MemoryDB<'static>,
+    undo: Vec<Change>,
+    options: ReadOptions,
+    header: Header,
+    precision: Precision,
+}
+
+impl Session {
+    pub fn new(
+        db: MemoryDB<'static>,
+        options: ReadOptions,
+        precision: Precision,
+    ) -> Result<Self, HeaderError> {
+        let header = options
+            .mapping
+            .header(&StringRecord::from(COLUMNS.to_vec()))?;
+        Ok(Session {
+            db,
+            undo: Vec::new(),
+            options,
+            header,
+            precision,
+        })
+    }
+
+    /// run a single command line
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help"] => Ok(HELP.to_owned()),
+            ["show", "account", client] => self.show_account(&self.options.tenant, client),
+            ["show", "account", tenant, client] => self.show_account(tenant, client),
+            ["show", "tx", tx] => self.show_tx(&self.options.tenant, tx),
+            ["show", "tx", tenant, tx] => self.show_tx(tenant, tx),
+            ["show", "accounts"] => {
+                let mut table = Vec::new();
+                print_results(&mut table, self.db.accounts(), &self.precision);
+                Ok(String::from_utf8_lossy(&table).trim_end().to_owned())
+            }
+            ["load", path] => self.load(Path::new(path)),
+            ["restore", path] => self.restore(Path::new(path)),
+            ["undo"] => {
+                let change = self.undo.pop().ok_or("nothing to undo")?;
+                let clients: Vec<(TenantId, ClientId)> = change
+                    .accounts()
+                    .map(|(tenant, client_id, _)| (tenant.to_owned(), client_id))
+                    .collect();
+                let precision = self.precision;
+                let current = |db: &MemoryDB<'static>| {
+                    let accounts = clients
+                        .iter()
+                        .filter_map(|(tenant, client_id)| db.tenant_account(tenant, client_id));
+                    records(accounts, &precision)
+                };
+                let before = current(&self.db);
+                self.db.revert(change);
+                Ok(differences(&before, &current(&self.db)))
+            }
+            [_, _, _] | [_, _, _, _] => self.apply(&words),
+            _ => Err(format!("unknown command: {}, try `help`", line.trim())),
+        }
+    }
+
+    /// process a single transaction given as the fields of an input row
+    fn apply(&mut self, fields: &[&str]) -> Result<String, String> {
+        let mut record = StringRecord::from(fields.to_vec());
+        let tx = parse_row(&mut record, &self.header, &self.options)
+            .map_err(|rejection| rejection.reason.to_string())?;
+        let flags = self.db.flags().len();
+        let (res, change) = self.db.record(|db| db.add(tx));
+        if let Err(e) = res {
+            // a rejected transaction may still have opened the account, but
+            // the detectors keep it as when reading an input, it can only be
+            // undone if they flagged the client
+            let change = self.db.revert_rejected(change);
+            if self.db.flags().len() == flags {
+                return Err(e.to_string());
+            }
+            let changes = self.changes(&change);
+            self.save(change);
+            return Err(format!("{}\n{}", e, changes));
+        }
+        let changes = self.changes(&change);
+        self.save(change);
+        Ok(changes)
+    }
+
+    /// process an input file, like the `load` command
+    pub fn load(&mut self, path: &Path) -> Result<String, String> {
+        let input = File::open(path)
+            .and_then(decompress)
+            .map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+        let source = path.display().to_string();
+        let options = &self.options;
+        let (res, change) = self
+            .db
+            .record(|db| read_csv_data(&source, input, db, options, &mut ()));
+        let report = match res {
+            Ok(report) => report,
+            Err(e) => {
+                self.db.revert(change);
+                return Err(e.to_string());
+            }
+        };
+        let mut lines: Vec<String> = report
+            .all_rejections()
+            .map(|r| format!("{}:{}: {}", source, r.line, r.reason))
+            .collect();
+        lines.push(format!(
+            "{}: {} rows, {} accepted, {} rejected",
+            source,
+            report.rows,
+            report.accepted,
+            report.rejections.len()
+        ));
+        lines.push(self.changes(&change));
+        self.save(change);
+        Ok(lines.join("\n"))
+    }
+
+    /// add the accounts of a results file, like the `restore` command
+    pub fn restore(&mut self, path: &Path) -> Result<String, String> {
+        let records = File::open(path)
+            .and_then(decompress)
+            .map_err(csv::Error::from)
+            .and_then(read_results)
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        // a row per currency, merged into a single account per tenant and client
+        let mut accounts: BTreeMap<(TenantId, ClientId), Account> = BTreeMap::new();
+        for record in records {
+            let key = (record.tenant.unwrap_or_default(), record.client_id);
+            let locked = record.locked;
+            let account = accounts
+                .remove(&key)
+                .unwrap_or_else(|| Account::restore(key.1, locked).in_tenant(key.0.clone()));
+            let account = account.restore_balance(
+                record.currency.unwrap_or_default(),
+                record.total,
+                record.held,
+            );
+            accounts.insert(key, account);
+        }
+        let (res, change) = self.db.record(|db| {
+            accounts
+                .into_values()
+                .try_for_each(|account| db.restore_account(account))
+        });
+        if let Err(e) = res {
+            self.db.revert(change);
+            return Err(e.to_string());
+        }
+        let changes = self.changes(&change);
+        self.save(change);
+        Ok(changes)
+    }
+
+    fn save(&mut self, change: Change) {
+        if self.undo.len() == UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.undo.push(change);
+    }
+
+    /// the accounts the change touched that are not as they were before it
+    fn changes(&self, change: &Change) -> String {
+        let before = records(
+            change.accounts().filter_map(|(_, _, account)| account),
+            &self.precision,
+        );
+        let after = records(
+            change
+                .accounts()
+                .filter_map(|(tenant, client_id, _)| self.db.tenant_account(tenant, &client_id)),
+            &self.precision,
+        );
+        differences(&before, &after)
+    }
+
+    fn show_account(&self, tenant: &str, client: &str) -> Result<String, String> {
+        let client_id = parse_id::<ClientId>(client)?;
+        self.db
+            .tenant_account(tenant, &client_id)
+            .map(|account| self.describe_account(account))
+            .ok_or_else(|| format!("account {} not found", client_id))
+    }
+
+    fn show_tx(&self, tenant: &str, tx: &str) -> Result<String, String> {
+        let transaction_id = parse_id::<TransactionId>(tx)?;
+        self.describe_tx(tenant, transaction_id)
+            .ok_or_else(|| format!("transaction {} not found", transaction_id))
+    }
+
+    /// one line per currency of the account
+    fn describe_account(&self, account: &Account) -> String {
+        Record::all(account, &self.precision)
+            .iter()
+            .map(|record| {
+                format!(
+                    "{}client {}{}: available {}, held {}, total {}, locked {}",
+                    record
+                        .tenant
+                        .as_ref()
+                        .map_or_else(String::new, |tenant| format!("tenant {}, ", tenant)),
+                    record.client_id,
+                    record
+                        .currency
+                        .as_ref()
+                        .map_or_else(String::new, |currency| format!(" {}", currency)),
+                    record.balance,
+                    record.held,
+                    record.total,
+                    record.locked
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn describe_tx(&self, tenant: &str, transaction_id: TransactionId) -> Option<String> {
+        let record = self.db.tenant_transaction(tenant, &transaction_id)?;
+        let origin = match record.origin {
+            TxRecordType::Deposit(_) => "deposit",
+            TxRecordType::Withdraw(_) => "withdrawal",
+        };
+        let dispute = match record.dispute {
+            None => "not disputed",
+            Some(DisputeState::Initiated) => "disputed",
+            Some(DisputeState::Resolved) => "resolved",
+            Some(DisputeState::ChargeBack) => "charged back",
+        };
+        let mut amount = self.precision.format(record.amount()).to_string();
+        if !record.currency.is_empty() {
+            amount = format!("{} {}", amount, record.currency);
+        }
+        if let Some(conversion) = &record.conversion {
+            amount = format!(
+                "{} (from {} {} at {})",
+                amount,
+                self.precision.format(conversion.amount),
+                conversion.currency,
+                conversion.rate
+            );
+        }
+        let time = record
+            .timestamp
+            .map_or_else(String::new, |t| format!(" at {}", format_timestamp(t)));
+        Some(format!(
+            "tx {}: client {}, {} {}{}, {}",
+            transaction_id, record.client_id, origin, amount, time, dispute
+        ))
+    }
+}
+
+fn records<'r>(accounts: impl Iterator<Item = &'r Account>, precision: &Precision) -> Vec<Record> {
+    accounts
+        .flat_map(|account| Record::all(account, precision))
+        .collect()
+}
+
+/// the accounts that differ, one per line
+fn differences(before: &[Record], after: &[Record]) -> String {
+    let differences = compare(before, after);
+    if differences.is_empty() {
+        return "no changes".to_owned();
+    }
+    differences
+        .iter()
+        .map(|difference| difference.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_id<T: std::str::FromStr>(s: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    s.parse().map_err(|e| format!("invalid id {}: {}", s, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use super::Session;
+    use crate::{
+        db::{
+            fraud::{DepositWithdrawal, Detectors, FailedWithdrawals},
+            EngineConfig, TransactionDB,
+        },
+        io::ReadOptions,
+        model::{account::DisputePolicy, precision::Precision, time::Window},
+    };
+
+    #[test]
+    fn test_session() {
+        let db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let mut session = Session::new(db, ReadOptions::default(), Precision::default()).unwrap();
+
+        assert_eq!(
+            session.execute("deposit 1 10 2.5").unwrap(),
+            "client 1: added (available 2.5000, held 0.0000, total 2.5000, locked false)"
+        );
+        assert_eq!(
+            session.execute("dispute 1 10").unwrap(),
+            "client 1: available 2.5000 -> 0.0000 (-2.5000), held 0.0000 -> 2.5000 (+2.5000)"
+        );
+        assert_eq!(
+            session.execute("show tx 10").unwrap(),
+            "tx 10: client 1, deposit 2.5000, disputed"
+        );
+        assert_eq!(
+            session.execute("withdrawal 1 11 1").unwrap_err(),
+            "insufficient funds"
+        );
+        assert_eq!(
+            session.execute("undo").unwrap(),
+            "client 1: available 0.0000 -> 2.5000 (+2.5000), held 2.5000 -> 0.0000 (-2.5000)"
+        );
+        assert_eq!(
+            session.execute("show account 1").unwrap(),
+            "client 1: available 2.5000, held 0.0000, total 2.5000, locked false"
+        );
+        assert!(session.execute("deposit 1 x 2").is_err());
+        session.execute("undo").unwrap();
+        assert_eq!(session.execute("undo").unwrap_err(), "nothing to undo");
+        assert!(session.execute("show account 1").is_err());
+    }
+
+    #[test]
+    fn test_undo() {
+        let config = EngineConfig {
+            dispute: DisputePolicy {
+                expire_after: Some(Window::Transactions(1)),
+                ..DisputePolicy::default()
+            },
+            freeze_score: Some(10),
+            ..EngineConfig::default()
+        };
+        let mut detectors = Detectors::default();
+        detectors.add(Box::new(DepositWithdrawal::new(
+            5.into(),
+            Window::Transactions(10),
+            10,
+        )));
+        let db = TransactionDB::new(HashMap::default(), HashMap::default())
+            .with_config(config)
+            .with_detectors(detectors);
+        let mut session = Session::new(db, ReadOptions::default(), Precision::default()).unwrap();
+
+        session.execute("deposit 1 1 5").unwrap();
+        session.execute("dispute 1 1").unwrap();
+        session.execute("deposit 2 2 1").unwrap();
+        // the dispute expires before the withdrawal, which freezes the account
+        session.execute("withdrawal 1 3 5").unwrap();
+        assert_eq!(session.db.expired_disputes().len(), 1);
+        assert_eq!(session.db.flags().len(), 1);
+        assert_eq!(
+            session.execute("undo").unwrap(),
+            "client 1: held 0.0000 -> 5.0000 (+5.0000), total 0.0000 -> 5.0000 (+5.0000), \
+             locked true -> false"
+        );
+        assert!(session.db.expired_disputes().is_empty());
+        assert!(session.db.flags().is_empty());
+        assert!(session.db.transaction(&3.into()).is_none());
+        // the detector forgot the withdrawal, not the deposit
+        session.execute("withdrawal 1 3 5").unwrap();
+        assert!(session.db.flags()[0].frozen);
+    }
+
+    #[test]
+    fn test_rejected() {
+        let config = EngineConfig {
+            freeze_score: Some(10),
+            ..EngineConfig::default()
+        };
+        let mut detectors = Detectors::default();
+        detectors.add(Box::new(FailedWithdrawals::new(
+            2,
+            Window::Transactions(10),
+            10,
+        )));
+        let db = TransactionDB::new(HashMap::default(), HashMap::default())
+            .with_config(config)
+            .with_detectors(detectors);
+        let mut session = Session::new(db, ReadOptions::default(), Precision::default()).unwrap();
+
+        // the detectors see the refused withdrawals, as when reading an input
+        session.execute("deposit 1 1 1").unwrap();
+        assert_eq!(
+            session.execute("withdrawal 1 2 5").unwrap_err(),
+            "insufficient funds"
+        );
+        assert_eq!(
+            session.execute("withdrawal 1 3 5").unwrap_err(),
+            "insufficient funds\nclient 1: locked false -> true"
+        );
+        assert!(session.db.flags()[0].frozen);
+        assert!(session.db.transaction(&3.into()).is_none());
+        assert_eq!(
+            session.execute("undo").unwrap(),
+            "client 1: locked true -> false"
+        );
+        assert!(session.db.flags().is_empty());
+
+        // a path is not split on its spaces
+        let dir = std::env::temp_dir().join(format!("atm-repl-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("with space.csv");
+        fs::write(&path, "type,client,tx,amount\ndeposit,2,4,1\n").unwrap();
+        assert!(session.load(&path).is_ok());
+        assert!(session.db.account(&2).is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tenants() {
+        let db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let options = ReadOptions {
+            tenant: "a".into(),
+            ..ReadOptions::default()
+        };
+        let mut session = Session::new(db, options, Precision::default()).unwrap();
+
+        session.execute("deposit 1 10 2").unwrap();
+        let account =
+            "tenant a, client 1: available 2.0000, held 0.0000, total 2.0000, locked false";
+        assert_eq!(session.execute("show account 1").unwrap(), account);
+        assert_eq!(session.execute("show account a 1").unwrap(), account);
+        assert!(session.execute("show account b 1").is_err());
+        assert!(session.execute("show tx a 10").is_ok());
+        assert!(session.execute("show tx b 10").is_err());
+    }
+}
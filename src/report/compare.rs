@@ -0,0 +1,238 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::model::{output::Record, Amount, ClientId, Currency, TenantId};
+
+/// accounts are matched by tenant, client and currency
+type Key = (Option<TenantId>, ClientId, Option<Currency>);
+
+/// A single field that differs between two records of the same client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+    /// right - left, for the amount fields
+    pub delta: Option<Amount>,
+}
+
+/// Difference between two sets of account records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// the client is only in the right set
+    Added(Record),
+    /// the client is only in the left set
+    Removed(Record),
+    Changed {
+        tenant: Option<TenantId>,
+        client_id: ClientId,
+        currency: Option<Currency>,
+        changes: Vec<FieldChange>,
+    },
+}
+
+impl Difference {
+    pub fn client(&self) -> ClientId {
+        self.key().1
+    }
+
+    fn key(&self) -> Key {
+        match self {
+            Difference::Added(record) | Difference::Removed(record) => key(record),
+            Difference::Changed {
+                tenant,
+                client_id,
+                currency,
+                ..
+            } => (tenant.clone(), *client_id, currency.clone()),
+        }
+    }
+}
+
+fn key(record: &Record) -> Key {
+    (
+        record.tenant.clone(),
+        record.client_id,
+        record.currency.clone(),
+    )
+}
+
+/// `client 1`, with `tenant a, ` before it when the account has a tenant and
+/// ` USD` after it when it has a currency
+fn label(tenant: &Option<TenantId>, client_id: ClientId, currency: &Option<Currency>) -> String {
+    let mut label = format!("client {}", client_id);
+    if let Some(tenant) = tenant {
+        label = format!("tenant {}, {}", tenant, label);
+    }
+    if let Some(currency) = currency {
+        label = format!("{} {}", label, currency);
+    }
+    label
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Added(r) => write!(
+                f,
+                "{}: added (available {}, held {}, total {}, locked {})",
+                label(&r.tenant, r.client_id, &r.currency),
+                r.balance,
+                r.held,
+                r.total,
+                r.locked
+            ),
+            Difference::Removed(r) => write!(
+                f,
+                "{}: removed (available {}, held {}, total {}, locked {})",
+                label(&r.tenant, r.client_id, &r.currency),
+                r.balance,
+                r.held,
+                r.total,
+                r.locked
+            ),
+            Difference::Changed {
+                tenant,
+                client_id,
+                currency,
+                changes,
+            } => {
+                write!(f, "{}:", label(tenant, *client_id, currency))?;
+                for (i, change) in changes.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(
+                        f,
+                        "{} {} {} -> {}",
+                        sep, change.field, change.left, change.right
+                    )?;
+                    if let Some(delta) = change.delta {
+                        let sign = if delta.is_sign_negative() { "" } else { "+" };
+                        write!(f, " ({}{})", sign, delta)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Compares two sets of account records, amounts are compared by value so
+/// `1.5` and `1.5000` are equal. The differences are ordered by tenant, client
+/// id and currency, only the last record of an account is compared, see
+/// `duplicates`
+pub fn compare(left: &[Record], right: &[Record]) -> Vec<Difference> {
+    let left: BTreeMap<Key, &Record> = left.iter().map(|r| (key(r), r)).collect();
+    let right: BTreeMap<Key, &Record> = right.iter().map(|r| (key(r), r)).collect();
+
+    let mut differences = Vec::new();
+    for (key, l) in &left {
+        match right.get(key) {
+            None => differences.push(Difference::Removed((*l).clone())),
+            Some(r) => {
+                let changes = changes(l, r);
+                if !changes.is_empty() {
+                    differences.push(Difference::Changed {
+                        tenant: key.0.clone(),
+                        client_id: key.1,
+                        currency: key.2.clone(),
+                        changes,
+                    });
+                }
+            }
+        }
+    }
+    for (key, r) in &right {
+        if !left.contains_key(key) {
+            differences.push(Difference::Added((*r).clone()));
+        }
+    }
+    differences.sort_by_key(Difference::key);
+    differences
+}
+
+/// the accounts that have more than one record, e.g. `client 1 USD`, in order
+pub fn duplicates(records: &[Record]) -> Vec<String> {
+    let mut counts: BTreeMap<Key, usize> = BTreeMap::new();
+    for record in records {
+        *counts.entry(key(record)).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|((tenant, client_id, currency), _)| label(&tenant, client_id, &currency))
+        .collect()
+}
+
+fn changes(left: &Record, right: &Record) -> Vec<FieldChange> {
+    let amounts = [
+        ("available", left.balance, right.balance),
+        ("held", left.held, right.held),
+        ("total", left.total, right.total),
+    ];
+    let mut changes: Vec<FieldChange> = amounts
+        .iter()
+        .filter(|(_, l, r)| l != r)
+        .map(|(field, l, r)| FieldChange {
+            field,
+            left: l.to_string(),
+            right: r.to_string(),
+            delta: Some(r - l),
+        })
+        .collect();
+    if left.locked != right.locked {
+        changes.push(FieldChange {
+            field: "locked",
+            left: left.locked.to_string(),
+            right: right.locked.to_string(),
+            delta: None,
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::model::{output::Record, Amount, ClientId};
+
+    use super::{compare, duplicates, Difference};
+
+    fn record(client_id: ClientId, available: &str, locked: bool) -> Record {
+        let amount = Amount::from_str(available).unwrap();
+        Record {
+            tenant: None,
+            client_id,
+            currency: None,
+            balance: amount,
+            held: Amount::from(0),
+            total: amount,
+            locked,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let left = vec![
+            record(1, "1.5", false),
+            record(2, "2", false),
+            record(3, "3", false),
+        ];
+        let right = vec![
+            record(4, "4", false),
+            record(2, "2.5", true),
+            record(1, "1.5000", false),
+        ];
+        let differences = compare(&left, &right);
+        assert_eq!(differences.len(), 3);
+        assert_eq!(differences[1], Difference::Removed(record(3, "3", false)));
+        assert_eq!(differences[2], Difference::Added(record(4, "4", false)));
+        assert_eq!(
+            differences[0].to_string(),
+            "client 2: available 2 -> 2.5 (+0.5), total 2 -> 2.5 (+0.5), locked false -> true"
+        );
+        assert!(duplicates(&left).is_empty());
+        let mut twice = right;
+        twice.push(record(2, "1", false));
+        assert_eq!(duplicates(&twice), vec!["client 2"]);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod compare;
+pub mod statement;
+pub mod stats;
+pub mod summary;
@@ -0,0 +1,324 @@
+use std::{collections::BTreeMap, io::Write, str::FromStr};
+
+use csv::WriterBuilder;
+
+use crate::{
+    io::{RowEvent, RowObserver},
+    model::{
+        account::Account, output::StatementRecord, precision::Precision, Amount, ClientId,
+        Currency, TenantId, TransactionId, TxOperation,
+    },
+};
+
+/// Output format of the statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementFormat {
+    Csv,
+    Text,
+}
+
+impl FromStr for StatementFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(StatementFormat::Csv),
+            "text" => Ok(StatementFormat::Text),
+            _ => Err(format!("unknown statement format: {}", s)),
+        }
+    }
+}
+
+/// Balances of an account at some point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balances {
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+}
+
+impl Balances {
+    /// the balances of the account in a single currency
+    pub fn new(account: &Account, currency: &str) -> Self {
+        let balance = account.balance_in(currency);
+        Balances {
+            available: balance.available(),
+            held: balance.held,
+            total: balance.total,
+            locked: account.is_locked(),
+        }
+    }
+}
+
+/// A single line of the statement, either an accepted transaction or a
+/// rejected attempt, the balances are the ones after the line
+#[derive(Debug, Clone)]
+pub struct StatementLine {
+    pub source: String,
+    pub line: u64,
+    pub transaction_id: Option<TransactionId>,
+    pub operation: Option<TxOperation>,
+    /// for disputes this is the amount of the disputed transaction
+    pub amount: Option<Amount>,
+    /// the reason if the line was rejected
+    pub rejected: Option<String>,
+    /// the currency of the amount and the balances
+    pub currency: Currency,
+    pub balances: Balances,
+}
+
+/// Collects all the events of a single client while the input is processed
+#[derive(Debug)]
+pub struct Statement {
+    tenant: TenantId,
+    client_id: ClientId,
+    lines: Vec<StatementLine>,
+    balances: BTreeMap<Currency, Balances>,
+}
+
+impl Statement {
+    pub fn new(client_id: ClientId) -> Self {
+        Statement {
+            tenant: TenantId::new(),
+            client_id,
+            lines: Vec::new(),
+            balances: BTreeMap::new(),
+        }
+    }
+
+    /// the statement of the client of the given tenant
+    pub fn in_tenant(mut self, tenant: TenantId) -> Self {
+        self.tenant = tenant;
+        self
+    }
+
+    pub fn client(&self) -> ClientId {
+        self.client_id
+    }
+
+    pub fn lines(&self) -> &[StatementLine] {
+        &self.lines
+    }
+
+    /// the balances in the currency after the last line
+    pub fn balances(&self, currency: &str) -> Balances {
+        self.balances.get(currency).copied().unwrap_or_default()
+    }
+
+    /// the currencies of the lines, ordered by name
+    pub fn currencies(&self) -> impl Iterator<Item = &str> {
+        self.balances.keys().map(String::as_str)
+    }
+
+    /// the currency of the last line, empty if there is none
+    fn last_currency(&self) -> Currency {
+        self.lines
+            .last()
+            .map(|line| line.currency.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl RowObserver for Statement {
+    fn on_row(&mut self, event: &RowEvent<'_>) {
+        match event {
+            RowEvent::Accepted {
+                source,
+                line,
+                tx,
+                account,
+                record,
+            } if tx.tenant == self.tenant && tx.client_id == self.client_id => {
+                // disputes are in the currency of the disputed transaction
+                let currency = record.map_or(&tx.currency, |r| &r.currency).clone();
+                let balances = Balances::new(account, &currency);
+                self.balances.insert(currency.clone(), balances);
+                let amount = match tx.operation {
+                    TxOperation::Deposit(amount) | TxOperation::Withdraw(amount) => Some(amount),
+                    TxOperation::Dispute(_) => record.map(|r| r.amount()),
+                };
+                self.lines.push(StatementLine {
+                    source: source.to_string(),
+                    line: *line,
+                    transaction_id: Some(tx.transaction_id),
+                    operation: Some(tx.operation),
+                    amount,
+                    rejected: None,
+                    currency,
+                    balances,
+                });
+            }
+            RowEvent::Rejected { source, rejection }
+                if rejection.tenant.as_ref() == Some(&self.tenant)
+                    && rejection.client_id == Some(self.client_id) =>
+            {
+                let (amount, currency) = match (rejection.operation, &rejection.currency) {
+                    (
+                        Some(TxOperation::Deposit(amount)) | Some(TxOperation::Withdraw(amount)),
+                        Some(currency),
+                    ) => (Some(amount), currency.clone()),
+                    _ => (None, self.last_currency()),
+                };
+                self.lines.push(StatementLine {
+                    source: source.to_string(),
+                    line: rejection.line,
+                    transaction_id: rejection.transaction_id,
+                    operation: rejection.operation,
+                    amount,
+                    rejected: Some(rejection.reason.to_string()),
+                    balances: self.balances(&currency),
+                    currency,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// writes the statement as csv, one row per line
+pub fn print_statement_csv(
+    writer: impl Write,
+    statement: &Statement,
+    precision: &Precision,
+) -> csv::Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    // every row needs the currency column once one of them has it
+    let currencies = statement
+        .lines()
+        .iter()
+        .any(|line| !line.currency.is_empty());
+    for line in statement.lines() {
+        writer.serialize(StatementRecord {
+            client_id: statement.client(),
+            currency: Some(line.currency.as_str()).filter(|_| currencies),
+            source: &line.source,
+            line: line.line,
+            transaction_id: line.transaction_id,
+            kind: line.operation.map(|o| o.name()),
+            amount: line.amount.map(|a| precision.format(a)),
+            status: if line.rejected.is_some() {
+                "rejected"
+            } else {
+                "accepted"
+            },
+            reason: line.rejected.as_deref(),
+            available: precision.format(line.balances.available),
+            held: precision.format(line.balances.held),
+            total: precision.format(line.balances.total),
+            locked: line.balances.locked,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// writes the statement as a human readable report
+pub fn print_statement_text(
+    mut writer: impl Write,
+    statement: &Statement,
+    precision: &Precision,
+) -> std::io::Result<()> {
+    let fmt = |amount: Option<Amount>| {
+        amount.map_or_else(String::new, |a| precision.format(a).to_string())
+    };
+    writeln!(writer, "Statement for client {}", statement.client())?;
+    writeln!(
+        writer,
+        "{:<24} {:>10} {:<10} {:>14} {:>14} {:>14} {:>14}  status",
+        "source", "tx", "type", "amount", "available", "held", "total"
+    )?;
+    for line in statement.lines() {
+        let source = format!("{}:{}", line.source, line.line);
+        let status = match (&line.rejected, line.balances.locked) {
+            (Some(reason), _) => format!("rejected: {}", reason),
+            (None, true) => "accepted, locked".to_owned(),
+            (None, false) => "accepted".to_owned(),
+        };
+        writeln!(
+            writer,
+            "{:<24} {:>10} {:<10} {:>14} {:>14} {:>14} {:>14}  {}",
+            source,
+            line.transaction_id
+                .map_or_else(String::new, |id| id.to_string()),
+            line.operation.map_or("", |o| o.name()),
+            // the balances are in the same currency as the amount
+            match line.amount {
+                Some(_) if !line.currency.is_empty() =>
+                    format!("{} {}", fmt(line.amount), line.currency),
+                _ => fmt(line.amount),
+            },
+            fmt(Some(line.balances.available)),
+            fmt(Some(line.balances.held)),
+            fmt(Some(line.balances.total)),
+            status
+        )?;
+    }
+    let mut currencies: Vec<&str> = statement.currencies().collect();
+    if currencies.is_empty() {
+        currencies.push("");
+    }
+    for currency in currencies {
+        let balances = statement.balances(currency);
+        writeln!(
+            writer,
+            "closing balance{}{}: available {}, held {}, total {}{}",
+            if currency.is_empty() { "" } else { " " },
+            currency,
+            precision.format(balances.available),
+            precision.format(balances.held),
+            precision.format(balances.total),
+            if balances.locked { ", locked" } else { "" }
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        db::TransactionDB,
+        io::{read_csv_data, ReadOptions},
+        model::{Amount, TxOperation},
+    };
+
+    use super::Statement;
+
+    #[test]
+    fn test_statement() {
+        let data = "type,client,tx,amount
+deposit,1,1,10
+deposit,2,2,5
+withdrawal,1,3,20
+dispute,1,1,
+resolve,1,1,
+withdrawal,1,4,4
+";
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let mut statement = Statement::new(1);
+        read_csv_data(
+            "data",
+            data.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut statement,
+        )
+        .unwrap();
+
+        let lines = statement.lines();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1].transaction_id, Some(3.into()));
+        assert!(lines[1].rejected.is_some());
+        assert_eq!(lines[1].balances.available, Amount::from(10));
+        assert_eq!(lines[2].amount, Some(Amount::from(10)));
+        assert_eq!(lines[2].balances.held, Amount::from(10));
+        assert_eq!(lines[2].balances.available, Amount::from(0));
+        assert_eq!(
+            lines[4].operation,
+            Some(TxOperation::Withdraw(Amount::from(4)))
+        );
+        assert_eq!(statement.balances("").total, Amount::from(6));
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Write,
+};
+
+use crate::{
+    io::{ReadReport, RowEvent, RowObserver},
+    model::{
+        input::TRANSACTION_TYPES, precision::Precision, Amount, ClientId, Currency, TenantId,
+        TxOperation,
+    },
+};
+
+/// Counters for a single transaction type in a single currency
+#[derive(Debug, Clone, Default)]
+pub struct TypeStats {
+    pub rows: u64,
+    pub accepted: u64,
+    /// sum of the accepted amounts
+    pub amount: Amount,
+}
+
+/// The smallest and the largest accepted amount of a currency
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountRange {
+    pub min: Amount,
+    pub max: Amount,
+}
+
+/// Summary of the processed input, collected while reading, the amounts of
+/// different currencies are never added up or compared
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// per transaction type and currency
+    pub types: BTreeMap<(&'static str, Currency), TypeStats>,
+    /// clients are counted per tenant
+    pub clients: HashSet<(TenantId, ClientId)>,
+    pub amounts: BTreeMap<Currency, AmountRange>,
+}
+
+impl Stats {
+    fn count(&mut self, operation: &TxOperation, currency: &str, accepted: bool) {
+        let stats = self
+            .types
+            .entry((operation.name(), currency.to_owned()))
+            .or_default();
+        stats.rows += 1;
+        if !accepted {
+            return;
+        }
+        stats.accepted += 1;
+        if let TxOperation::Deposit(amount) | TxOperation::Withdraw(amount) = operation {
+            stats.amount += amount;
+            let amount = *amount;
+            self.amounts
+                .entry(currency.to_owned())
+                .and_modify(|range| {
+                    range.min = range.min.min(amount);
+                    range.max = range.max.max(amount);
+                })
+                .or_insert(AmountRange {
+                    min: amount,
+                    max: amount,
+                });
+        }
+    }
+
+    /// true once a row has a currency
+    fn has_currencies(&self) -> bool {
+        self.types.keys().any(|(_, currency)| !currency.is_empty())
+    }
+}
+
+impl RowObserver for Stats {
+    fn on_row(&mut self, event: &RowEvent<'_>) {
+        match event {
+            RowEvent::Accepted { tx, .. } => {
+                self.clients.insert((tx.tenant.clone(), tx.client_id));
+                self.count(&tx.operation, &tx.currency, true);
+            }
+            RowEvent::Rejected { rejection, .. } => {
+                if let (Some(tenant), Some(client_id)) = (&rejection.tenant, rejection.client_id) {
+                    self.clients.insert((tenant.clone(), client_id));
+                }
+                if let Some(operation) = &rejection.operation {
+                    let currency = rejection.currency.as_deref().unwrap_or_default();
+                    self.count(operation, currency, false);
+                }
+            }
+        }
+    }
+}
+
+/// writes the summary of the input as a human readable report
+pub fn print_stats<'a>(
+    mut writer: impl Write,
+    stats: &Stats,
+    reports: impl Iterator<Item = &'a ReadReport>,
+    precision: &Precision,
+) -> std::io::Result<()> {
+    let (mut rows, mut accepted) = (0, 0);
+    let mut rejected: BTreeMap<&'static str, u64> = BTreeMap::new();
+    let mut sources = 0;
+    let mut header = 0;
+    for report in reports {
+        sources += 1;
+        rows += report.rows;
+        accepted += report.accepted;
+        for rejection in &report.rejections {
+            *rejected.entry(rejection.reason.category()).or_default() += 1;
+        }
+        header += report.header.len();
+    }
+
+    writeln!(writer, "inputs: {}", sources)?;
+    writeln!(writer, "rows: {}", rows)?;
+    writeln!(writer, "accepted: {}", accepted)?;
+    writeln!(writer, "rejected: {}", rejected.values().sum::<u64>())?;
+    for (category, count) in &rejected {
+        writeln!(writer, "  {}: {}", category, count)?;
+    }
+    if header > 0 {
+        writeln!(writer, "header problems: {}", header)?;
+    }
+    writeln!(writer, "clients: {}", stats.clients.len())?;
+    for (currency, range) in &stats.amounts {
+        let label = match currency.as_str() {
+            "" => "amounts".to_owned(),
+            currency => format!("amounts {}", currency),
+        };
+        writeln!(
+            writer,
+            "{}: min {}, max {}",
+            label,
+            precision.format(range.min),
+            precision.format(range.max)
+        )?;
+    }
+    // the currency column is only there once a row has a currency
+    let currencies = stats.has_currencies();
+    let header = ["type", "currency", "rows", "accepted", "amount"];
+    writeln!(writer, "{}", table_row(currencies, header))?;
+    for name in TRANSACTION_TYPES {
+        let mut types = stats
+            .types
+            .iter()
+            .filter(|((t, _), _)| t == name)
+            .peekable();
+        if types.peek().is_none() {
+            let amount = precision.format(Amount::default()).to_string();
+            writeln!(
+                writer,
+                "{}",
+                table_row(currencies, [name, "", "0", "0", &amount])
+            )?;
+        }
+        for ((_, currency), t) in types {
+            let (rows, accepted) = (t.rows.to_string(), t.accepted.to_string());
+            let amount = precision.format(t.amount).to_string();
+            let cells = [name, currency.as_str(), &rows, &accepted, &amount];
+            writeln!(writer, "{}", table_row(currencies, cells))?;
+        }
+    }
+    Ok(())
+}
+
+/// a line of the table of the types, `cells` has the currency second
+fn table_row(currencies: bool, cells: [&str; 5]) -> String {
+    let [name, currency, rows, accepted, amount] = cells;
+    if currencies {
+        format!(
+            "{:<12} {:<8} {:>10} {:>10} {:>16}",
+            name, currency, rows, accepted, amount
+        )
+    } else {
+        format!("{:<12} {:>10} {:>10} {:>16}", name, rows, accepted, amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{print_stats, AmountRange, Stats};
+    use crate::{
+        db::TransactionDB,
+        io::{read_csv_data, ReadOptions},
+        model::precision::Precision,
+    };
+
+    #[test]
+    fn test_currencies() {
+        let data = "type,client,tx,amount,currency
+deposit,1,1,10,USD
+deposit,1,2,5,EUR
+deposit,2,3,20,usd
+withdrawal,1,4,3,EUR
+withdrawal,1,5,100,USD
+";
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let mut stats = Stats::default();
+        let report = read_csv_data(
+            "data",
+            data.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut stats,
+        )
+        .unwrap();
+        let deposits = |currency: &str| stats.types[&("deposit", currency.to_owned())].amount;
+        assert_eq!((deposits("USD"), deposits("EUR")), (30.into(), 5.into()));
+        assert_eq!(
+            stats.amounts["EUR"],
+            AmountRange {
+                min: 3.into(),
+                max: 5.into()
+            }
+        );
+
+        let mut out = Vec::new();
+        print_stats(
+            &mut out,
+            &stats,
+            std::iter::once(&report),
+            &Precision::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "inputs: 1
+rows: 5
+accepted: 4
+rejected: 1
+  insufficient_funds: 1
+clients: 2
+amounts EUR: min 3.0000, max 5.0000
+amounts USD: min 10.0000, max 20.0000
+type         currency       rows   accepted           amount
+deposit      EUR               1          1           5.0000
+deposit      USD               2          2          30.0000
+withdrawal   EUR               1          1           3.0000
+withdrawal   USD               1          0           0.0000
+dispute                        0          0           0.0000
+resolve                        0          0           0.0000
+chargeback                     0          0           0.0000
+"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use crate::{io::ReadReport, model::account::Account};
+
+/// End of run counters of all the inputs and accounts
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub rows: u64,
+    pub accepted: u64,
+    /// rejected rows by reason category
+    pub rejected: BTreeMap<&'static str, u64>,
+    /// problems of the headers by reason category, they are not rows
+    pub header: BTreeMap<&'static str, u64>,
+    pub accounts: usize,
+    pub locked: usize,
+    /// accounts and locked accounts by tenant, the empty tenant included
+    pub tenants: BTreeMap<String, (usize, usize)>,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    pub fn new<'r, 'a, R, A>(reports: R, accounts: A, elapsed: Duration) -> Self
+    where
+        R: IntoIterator<Item = &'r ReadReport>,
+        A: IntoIterator<Item = &'a Account>,
+    {
+        let mut summary = Summary {
+            elapsed,
+            ..Summary::default()
+        };
+        for report in reports {
+            summary.rows += report.rows;
+            summary.accepted += report.accepted;
+            for rejection in &report.rejections {
+                *summary
+                    .rejected
+                    .entry(rejection.reason.category())
+                    .or_default() += 1;
+            }
+            for rejection in &report.header {
+                *summary
+                    .header
+                    .entry(rejection.reason.category())
+                    .or_default() += 1;
+            }
+        }
+        for account in accounts {
+            let tenant = summary
+                .tenants
+                .entry(account.tenant().to_owned())
+                .or_default();
+            summary.accounts += 1;
+            tenant.0 += 1;
+            if account.is_locked() {
+                summary.locked += 1;
+                tenant.1 += 1;
+            }
+        }
+        summary
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.rejected.values().sum()
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "rows: {}, accepted: {}, rejected: {}",
+            self.rows,
+            self.accepted,
+            self.rejected()
+        )?;
+        for (category, count) in &self.rejected {
+            writeln!(f, "  {}: {}", category, count)?;
+        }
+        if !self.header.is_empty() {
+            writeln!(f, "header: {}", self.header.values().sum::<u64>())?;
+            for (category, count) in &self.header {
+                writeln!(f, "  {}: {}", category, count)?;
+            }
+        }
+        writeln!(f, "accounts: {}, locked: {}", self.accounts, self.locked)?;
+        // only broken down when some accounts have a tenant
+        if self.tenants.keys().any(|tenant| !tenant.is_empty()) {
+            for (tenant, (accounts, locked)) in &self.tenants {
+                let tenant = if tenant.is_empty() { "(none)" } else { tenant };
+                writeln!(
+                    f,
+                    "  tenant {}: accounts: {}, locked: {}",
+                    tenant, accounts, locked
+                )?;
+            }
+        }
+        write!(f, "elapsed: {:?}", self.elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::Summary;
+    use crate::{db::TransactionDB, io::read_csv_data, io::ReadOptions};
+
+    #[test]
+    fn test_summary() {
+        let input = "type,client,tx,amount,note\n\
+                     deposit,1,1,5,\n\
+                     withdrawal,1,2,10,\n\
+                     deposit,2,3,1,\n\
+                     dispute,2,3,,\n\
+                     chargeback,2,3,,\n\
+                     dispute,1,9,,\n\
+                     deposit,1,4,x,\n";
+        let mut db = TransactionDB::new(HashMap::default(), HashMap::default());
+        let report = read_csv_data(
+            "test",
+            input.as_bytes(),
+            &mut db,
+            &ReadOptions::default(),
+            &mut (),
+        )
+        .unwrap();
+        let summary = Summary::new(&[report], db.accounts(), Duration::from_millis(3));
+        assert_eq!(summary.rows, 7);
+        assert_eq!(summary.accepted, 4);
+        assert_eq!(summary.rejected(), 3);
+        assert_eq!(summary.rejected["insufficient_funds"], 1);
+        assert_eq!(summary.rejected["transaction_not_found"], 1);
+        assert_eq!(summary.rejected["malformed"], 1);
+        assert_eq!(summary.header["unknown_column"], 1);
+        assert_eq!((summary.accounts, summary.locked), (2, 1));
+        assert_eq!(
+            summary.to_string(),
+            "rows: 7, accepted: 4, rejected: 3\n  \
+             insufficient_funds: 1\n  \
+             malformed: 1\n  \
+             transaction_not_found: 1\n\
+             header: 1\n  \
+             unknown_column: 1\n\
+             accounts: 2, locked: 1\n\
+             elapsed: 3ms"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use tiny_http::{Header, Method, Request, Response};
+
+use super::{Reply, Server};
+use crate::{
+    io::RejectReason,
+    model::{input::parse_currency, ClientId},
+};
+
+/// A response before it is sent
+#[derive(Debug, PartialEq, Eq)]
+pub struct HttpReply {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpReply {
+    fn json(status: u16, value: &impl serde::Serialize) -> Self {
+        HttpReply {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(value).expect("serializable reply"),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        HttpReply::json(status, &serde_json::json!({ "error": message }))
+    }
+}
+
+/// Answers the requests until the server is closed.
+///
+/// - `POST /transactions` with a json row, e.g. `{"type": "deposit", "client": 1,
+///   "tx": 1, "amount": "2.5"}`, answers 200 if accepted, 422 if rejected
+/// - `GET /accounts` the accounts as json, `GET /accounts.csv` as csv
+/// - `GET /accounts/<client>` a single account
+pub fn serve(server: &Server, http: &tiny_http::Server) {
+    for mut request in http.incoming_requests() {
+        let mut body = String::new();
+        let reply = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => handle(server, request.method(), request.url(), &body),
+            Err(e) => HttpReply::error(400, &e.to_string()),
+        };
+        if let Err(e) = respond(request, reply) {
+            warn!("can't send response: {}", e);
+        }
+    }
+}
+
+fn respond(request: Request, reply: HttpReply) -> std::io::Result<()> {
+    let header =
+        Header::from_bytes("Content-Type", reply.content_type).expect("valid content type header");
+    request.respond(
+        Response::from_string(reply.body)
+            .with_status_code(reply.status)
+            .with_header(header),
+    )
+}
+
+pub fn handle(server: &Server, method: &Method, url: &str, body: &str) -> HttpReply {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    match (method, path) {
+        (Method::Post, "/transactions") => {
+            let reply = Reply::from(
+                serde_json::from_str(body)
+                    .map_err(|e| RejectReason::Malformed(e.to_string()))
+                    .and_then(|row| server.submit_row(row)),
+            );
+            HttpReply::json(if reply.is_accepted() { 200 } else { 422 }, &reply)
+        }
+        (Method::Get, "/accounts") => HttpReply::json(200, &server.accounts()),
+        (Method::Get, "/accounts.csv") => {
+            let mut body = Vec::new();
+            server.write_results(&mut body);
+            HttpReply {
+                status: 200,
+                content_type: "text/csv",
+                body: String::from_utf8_lossy(&body).into_owned(),
+            }
+        }
+        (Method::Get, path) if path.starts_with("/accounts/") => {
+            // the tenant and currency are given as `?tenant=name&currency=EUR`,
+            // the default ones otherwise
+            let param = |name: &str| {
+                query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+            };
+            let tenant = param("tenant").unwrap_or(&server.options().tenant);
+            let currency = match param("currency").map(parse_currency) {
+                Some(Ok(currency)) => currency,
+                Some(Err(e)) => return HttpReply::error(400, &e.to_string()),
+                None => server.options().currency.clone(),
+            };
+            match path["/accounts/".len()..].parse::<ClientId>() {
+                Ok(client_id) => match server.account(tenant, client_id, &currency) {
+                    Some(record) => HttpReply::json(200, &record),
+                    None => HttpReply::error(404, "account not found"),
+                },
+                Err(e) => HttpReply::error(400, &format!("invalid client: {}", e)),
+            }
+        }
+        (_, "/transactions") | (_, "/accounts") | (_, "/accounts.csv") => {
+            HttpReply::error(405, "method not allowed")
+        }
+        _ => HttpReply::error(404, "not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tiny_http::Method;
+
+    use super::handle;
+    use crate::{db::TransactionDB, io::ReadOptions, model::precision::Precision, server::Server};
+
+    #[test]
+    fn test_handle() {
+        let server = Server::new(
+            TransactionDB::new(HashMap::default(), HashMap::default()),
+            ReadOptions::default(),
+            Precision::default(),
+        );
+        let post = |body| handle(&server, &Method::Post, "/transactions", body);
+
+        let reply = post(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}"#);
+        assert_eq!(
+            (reply.status, reply.body.as_str()),
+            (200, r#"{"status":"accepted"}"#)
+        );
+        let reply = post(r#"{"type": "withdrawal", "client": 1, "tx": 2, "amount": "3"}"#);
+        assert_eq!(reply.status, 422);
+        assert!(reply.body.contains(r#""category":"insufficient_funds""#));
+        let reply = post(r#"{"type": "deposit", "client": 1}"#);
+        assert_eq!(reply.status, 422);
+        assert!(reply.body.contains(r#""category":"malformed""#));
+
+        let reply = handle(&server, &Method::Get, "/accounts/1", "");
+        assert_eq!(
+            (reply.status, reply.body.as_str()),
+            (
+                200,
+                r#"{"client":1,"available":"2.5000","held":"0.0000","total":"2.5000","locked":false}"#
+            )
+        );
+        assert_eq!(handle(&server, &Method::Get, "/accounts/2", "").status, 404);
+
+        let reply =
+            post(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "1", "tenant": "shop"}"#);
+        assert_eq!(reply.status, 200);
+        let reply = handle(&server, &Method::Get, "/accounts/1?tenant=shop", "");
+        assert!(reply
+            .body
+            .starts_with(r#"{"tenant":"shop","client":1,"available":"1.0000""#));
+        let reply = handle(&server, &Method::Get, "/accounts.csv", "");
+        assert_eq!(
+            reply.body,
+            "tenant,client,available,held,total,locked\n\
+             ,1,2.5000,0.0000,2.5000,false\n\
+             shop,1,1.0000,0.0000,1.0000,false\n"
+        );
+        assert_eq!(
+            handle(&server, &Method::Delete, "/accounts", "").status,
+            405
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::{Mutex, MutexGuard};
+
+use serde::Serialize;
+
+use crate::{
+    db::MemoryDB,
+    io::{convert_row, print_results, ReadOptions, RejectReason},
+    model::{input::TxRow, output::Record, precision::Precision, ClientId, Tx},
+};
+
+pub mod http;
+pub mod tcp;
+
+/// A database shared by all the connections of the server
+pub struct Server {
+    db: Mutex<MemoryDB<'static>>,
+    options: ReadOptions,
+    precision: Precision,
+}
+
+impl Server {
+    pub fn new(db: MemoryDB<'static>, options: ReadOptions, precision: Precision) -> Self {
+        Server {
+            db: Mutex::new(db),
+            options,
+            precision,
+        }
+    }
+
+    /// convert the row and add it to the database
+    pub fn submit_row(&self, row: TxRow) -> Result<(), RejectReason> {
+        self.options
+            .transaction_ids
+            .check(row.transaction_id())
+            .map_err(|error| RejectReason::InvalidId {
+                column: "tx",
+                error,
+            })?;
+        let tx = convert_row(row, &self.options).map_err(RejectReason::Conversion)?;
+        self.submit(tx)
+    }
+
+    pub fn submit(&self, tx: Tx) -> Result<(), RejectReason> {
+        self.lock().add(tx).map_err(RejectReason::Rejected)
+    }
+
+    /// the balance of the account in a single currency
+    pub fn account(&self, tenant: &str, client_id: ClientId, currency: &str) -> Option<Record> {
+        self.lock()
+            .tenant_account(tenant, &client_id)
+            .map(|account| Record::new(account, currency, &self.precision))
+    }
+
+    /// all the accounts, ordered by tenant, client and currency
+    pub fn accounts(&self) -> Vec<Record> {
+        let db = self.lock();
+        let mut records: Vec<Record> = db
+            .accounts()
+            .flat_map(|account| Record::all(account, &self.precision))
+            .collect();
+        records.sort_by(|a, b| {
+            (&a.tenant, a.client_id, &a.currency).cmp(&(&b.tenant, b.client_id, &b.currency))
+        });
+        records
+    }
+
+    /// write the accounts as `process` does
+    pub fn write_results(&self, writer: impl std::io::Write) {
+        let db = self.lock();
+        print_results(writer, db.accounts(), &self.precision);
+    }
+
+    pub fn options(&self) -> &ReadOptions {
+        &self.options
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryDB<'static>> {
+        // the lock is poisoned only if processing a transaction panicked,
+        // the accounts can't be trusted after that
+        self.db.lock().expect("account invariant violated")
+    }
+}
+
+/// The answer to a submitted transaction
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Reply {
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Reply {
+    pub fn is_accepted(&self) -> bool {
+        self.category.is_none()
+    }
+}
+
+impl From<Result<(), RejectReason>> for Reply {
+    fn from(result: Result<(), RejectReason>) -> Self {
+        match result {
+            Ok(()) => Reply {
+                status: "accepted",
+                category: None,
+                reason: None,
+            },
+            Err(reason) => Reply {
+                status: "rejected",
+                category: Some(reason.category()),
+                reason: Some(reason.to_string()),
+            },
+        }
+    }
+}
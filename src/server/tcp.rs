@@ -0,0 +1,124 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+use csv::{ReaderBuilder, StringRecord, Trim, WriterBuilder};
+
+use super::{Reply, Server};
+use crate::io::{parse_row, RejectReason};
+
+/// Accepts csv connections until the listener fails, every connection is
+/// handled on its own thread
+pub fn serve(server: &Server, listener: &TcpListener) {
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("can't accept connection: {}", e);
+                    continue;
+                }
+            };
+            scope.spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default();
+                if let Err(e) = stream
+                    .try_clone()
+                    .and_then(|reader| handle(server, reader, stream))
+                {
+                    warn!("{}: {}", peer, e);
+                }
+            });
+        }
+    });
+}
+
+/// Reads csv rows starting with a header, like an input file, and answers
+/// every row with a `line,status,category,reason` line
+pub fn handle(server: &Server, reader: impl Read, writer: impl Write) -> std::io::Result<()> {
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(reader);
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    let mut answer = |line: u64, reply: Reply| -> std::io::Result<()> {
+        writer.write_record([
+            line.to_string().as_str(),
+            reply.status,
+            reply.category.unwrap_or_default(),
+            reply.reason.as_deref().unwrap_or_default(),
+        ])?;
+        writer.flush()
+    };
+
+    let header = reader
+        .headers()
+        .map_err(|e| RejectReason::Malformed(e.to_string()))
+        .and_then(|header| {
+            server
+                .options()
+                .mapping
+                .header(header)
+                .map_err(|e| RejectReason::Header(Box::new(e)))
+        });
+    let header = match header {
+        Ok(header) => header,
+        Err(reason) => return answer(1, Reply::from(Err(reason))),
+    };
+
+    let mut record = StringRecord::new();
+    loop {
+        let (line, result) = match reader.read_record(&mut record) {
+            Ok(true) => {
+                let line = record.position().map_or(0, |p| p.line());
+                let result = parse_row(&mut record, &header, server.options())
+                    .map_err(|rejection| rejection.reason)
+                    .and_then(|tx| server.submit(tx));
+                (line, result)
+            }
+            Ok(false) => return Ok(()),
+            Err(e) => (
+                e.position().map_or(0, |p| p.line()),
+                Err(RejectReason::Malformed(e.to_string())),
+            ),
+        };
+        answer(line, Reply::from(result))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::handle;
+    use crate::{db::TransactionDB, io::ReadOptions, model::precision::Precision, server::Server};
+
+    #[test]
+    fn test_handle() {
+        let server = Server::new(
+            TransactionDB::new(HashMap::default(), HashMap::default()),
+            ReadOptions::default(),
+            Precision::default(),
+        );
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,2.5\n\
+                     dispute,1,2,\n";
+        let mut output = Vec::new();
+        handle(&server, input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2,accepted,,\n3,rejected,transaction_not_found,transaction not found: 2\n"
+        );
+
+        let mut output = Vec::new();
+        handle(&server, "kind,client\n".as_bytes(), &mut output).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .starts_with("1,rejected,header,"));
+        assert_eq!(server.accounts().len(), 1);
+    }
+}
@@ -0,0 +1,81 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn header() -> String {
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        header: Some("/* Generated from src/ffi.rs by cbindgen, run `UPDATE_HEADER=1 cargo test --test ffi` to update. */".into()),
+        include_guard: Some("ATM_H".into()),
+        cpp_compat: true,
+        enumeration: cbindgen::EnumConfig {
+            rename_variants: cbindgen::RenameRule::ScreamingSnakeCase,
+            prefix_with_name: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(manifest_dir().join("src/ffi.rs"))
+        .generate()
+        .expect("header generation")
+        .write(&mut out);
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_header_is_current() {
+    let path = manifest_dir().join("include/atm.h");
+    let generated = header();
+    if env::var_os("UPDATE_HEADER").is_some() {
+        fs::write(&path, &generated).unwrap();
+    }
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        current == generated,
+        "include/atm.h is out of date, run `UPDATE_HEADER=1 cargo test --test ffi`"
+    );
+}
+
+#[test]
+fn test_c_program() {
+    // the cdylib is built next to the test binary
+    let lib_dir = env::current_exe().unwrap().parent().unwrap().to_owned();
+    let out = lib_dir.join("atm-ffi-test");
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".into());
+    let status = Command::new(compiler)
+        .arg(manifest_dir().join("tests/ffi/test.c"))
+        .arg("-I")
+        .arg(manifest_dir().join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .args(["-latm", "-o"])
+        .arg(&out)
+        .status()
+        .expect("a C compiler");
+    assert!(status.success(), "can't compile tests/ffi/test.c");
+
+    let output = Command::new(&out)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .env("DYLD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        ",1,,7.5000,0.0000,7.5000,0\n\
+         ,2,,0.0000,0.0000,0.0000,1\n\
+         ,3,EUR,2.0000,0.0000,2.0000,0\n\
+         ,3,USD,5.0000,0.0000,5.0000,0\n\
+         ,4000000000,,1.0000,0.0000,1.0000,0\n\
+         b,3,USD,1.0000,0.0000,1.0000,0\n"
+    );
+}
@@ -0,0 +1,47 @@
+#include <stdio.h>
+#include <string.h>
+
+#include "atm.h"
+
+#define CHECK(call, expected)                                                   \
+    do {                                                                        \
+        AtmStatus status = (call);                                              \
+        if (status != (expected)) {                                             \
+            fprintf(stderr, "%s:%d: %s: %s\n", __FILE__, __LINE__, #call,       \
+                    atm_status_message(status));                                \
+            return 1;                                                           \
+        }                                                                       \
+    } while (0)
+
+int main(void) {
+    AtmDb *db = atm_db_new();
+
+    CHECK(atm_db_submit(db, 1, 1, "deposit", "10"), ATM_STATUS_OK);
+    CHECK(atm_db_submit(db, 1, 2, "withdrawal", "2.5"), ATM_STATUS_OK);
+    CHECK(atm_db_submit(db, 1, 3, "withdrawal", "100"), ATM_STATUS_INSUFFICIENT_FUNDS);
+    CHECK(atm_db_submit(db, 1, 4, "deposit", NULL), ATM_STATUS_DEPOSIT_WITHOUT_AMOUNT);
+    CHECK(atm_db_submit(db, 1, 5, "deposit", "ten"), ATM_STATUS_MALFORMED);
+    CHECK(atm_db_submit(db, 1, 1, "deposit", "1"), ATM_STATUS_TRANSACTION_EXISTS);
+    CHECK(atm_db_submit(db, 1, 9, "dispute", NULL), ATM_STATUS_TRANSACTION_NOT_FOUND);
+    CHECK(atm_db_submit(db, 2, 6, "deposit", "3"), ATM_STATUS_OK);
+    CHECK(atm_db_submit(db, 2, 6, "dispute", NULL), ATM_STATUS_OK);
+    CHECK(atm_db_submit(db, 2, 6, "chargeback", NULL), ATM_STATUS_OK);
+    CHECK(atm_db_submit(db, 2, 7, "deposit", "1"), ATM_STATUS_ACCOUNT_LOCKED);
+    CHECK(atm_db_submit(db, 4000000000u, 18446744073709551615u, "deposit", "1"), ATM_STATUS_OK);
+    CHECK(atm_db_submit(NULL, 1, 8, "deposit", "1"), ATM_STATUS_NULL_POINTER);
+    /* a client with two currencies, and the same client in another tenant */
+    CHECK(atm_db_submit_in(db, NULL, "usd", 3, 10, "deposit", "5"), ATM_STATUS_OK);
+    CHECK(atm_db_submit_in(db, NULL, "EUR", 3, 11, "deposit", "2"), ATM_STATUS_OK);
+    CHECK(atm_db_submit_in(db, NULL, "EU", 3, 12, "deposit", "2"), ATM_STATUS_INVALID_CURRENCY);
+    CHECK(atm_db_submit_in(db, "b", "USD", 3, 10, "deposit", "1"), ATM_STATUS_OK);
+
+    AtmAccounts *accounts = atm_accounts_new(db);
+    AtmAccount account;
+    while (atm_accounts_next(accounts, &account)) {
+        printf("%s,%u,%s,%s,%s,%s,%d\n", account.tenant, account.client, account.currency,
+               account.available, account.held, account.total, account.locked);
+    }
+    atm_accounts_free(accounts);
+    atm_db_free(db);
+    return 0;
+}